@@ -0,0 +1,494 @@
+// Integer arithmetic as used by `$((...))`, `((...))` and `let`.
+//
+// Expressions are tokenized, parsed into a small tree and then evaluated so
+// that `&&`, `||` and `?:` only evaluate the operand they need (assignments in
+// the skipped branch must not happen).
+
+const MAX_RECURSION_DEPTH: usize = 32;
+
+pub trait Variables {
+    fn get_variable(&self, name: &str) -> Option<String>;
+    fn set_variable(&mut self, name: &str, value: String);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Operator(&'static str),
+}
+
+// Longest operators first so that `<<=` is not read as `<` followed by `<=`.
+const OPERATORS: [&str; 40] = [
+    "<<=", ">>=", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "++", "--", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "=", "?", ":", ",", "(", ")", "$",
+];
+
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Assign(String, &'static str, Box<Expr>),
+    PreIncrement(String, i64),
+    PostIncrement(String, i64),
+}
+
+pub fn evaluate(expression: &str, variables: &mut dyn Variables) -> Result<i64, &'static str> {
+    evaluate_with_depth(expression, variables, 0)
+}
+
+fn evaluate_with_depth(
+    expression: &str,
+    variables: &mut dyn Variables,
+    depth: usize,
+) -> Result<i64, &'static str> {
+    if depth > MAX_RECURSION_DEPTH {
+        return Err("expression recursion level exceeded");
+    }
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+    let expr = parser.parse_comma()?;
+    if parser.position != parser.tokens.len() {
+        return Err("syntax error in expression");
+    }
+    let mut evaluator = Evaluator { variables, depth };
+    evaluator.eval(&expr)
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, &'static str> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || chars[i] == '#' || chars[i] == '_')
+            {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(parse_number(&literal)?));
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..i].iter().collect()));
+            continue;
+        }
+        let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+        match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            // `$name` is accepted as a plain variable reference.
+            Some(&"$") => i += 1,
+            Some(op) => {
+                tokens.push(Token::Operator(op));
+                i += op.len();
+            }
+            None => return Err("syntax error: invalid arithmetic operator"),
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(literal: &str) -> Result<i64, &'static str> {
+    let (base, digits) = if let Some((base, digits)) = literal.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base) if (2..=36).contains(&base) => (base, digits),
+            _ => return Err("invalid arithmetic base"),
+        }
+    } else if let Some(digits) = literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))
+    {
+        (16, digits)
+    } else if literal.len() > 1 && literal.starts_with('0') {
+        (8, &literal[1..])
+    } else {
+        (10, literal)
+    };
+    if digits.is_empty() {
+        return Err("invalid number");
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        match c.to_digit(base) {
+            Some(digit) => value = value.wrapping_mul(base as i64).wrapping_add(digit as i64),
+            None => return Err("value too great for base"),
+        }
+    }
+    Ok(value)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &'static str) -> Result<(), &'static str> {
+        if self.peek_operator() == Some(op) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err("syntax error in expression")
+        }
+    }
+
+    fn parse_comma(&mut self) -> Result<Expr, &'static str> {
+        let mut expr = self.parse_assignment()?;
+        while self.peek_operator() == Some(",") {
+            self.position += 1;
+            let rhs = self.parse_assignment()?;
+            expr = Expr::Binary(",", Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_assignment(&mut self) -> Result<Expr, &'static str> {
+        let lhs = self.parse_conditional()?;
+        let op = match self.peek_operator() {
+            Some(
+                op @ ("=" | "+=" | "-=" | "*=" | "/=" | "%=" | "<<=" | ">>=" | "&=" | "^=" | "|="),
+            ) => op,
+            _ => return Ok(lhs),
+        };
+        let name = match lhs {
+            Expr::Variable(name) => name,
+            _ => return Err("attempted assignment to non-variable"),
+        };
+        self.position += 1;
+        let rhs = self.parse_assignment()?;
+        Ok(Expr::Assign(name, op, Box::new(rhs)))
+    }
+
+    fn parse_conditional(&mut self) -> Result<Expr, &'static str> {
+        let condition = self.parse_binary(0)?;
+        if self.peek_operator() != Some("?") {
+            return Ok(condition);
+        }
+        self.position += 1;
+        let if_true = self.parse_comma()?;
+        self.expect(":")?;
+        let if_false = self.parse_assignment()?;
+        Ok(Expr::Conditional(
+            Box::new(condition),
+            Box::new(if_true),
+            Box::new(if_false),
+        ))
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, &'static str> {
+        const LEVELS: [&[&str]; 10] = [
+            &["||"],
+            &["&&"],
+            &["|"],
+            &["^"],
+            &["&"],
+            &["==", "!="],
+            &["<", "<=", ">", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.parse_power();
+        }
+        let mut expr = self.parse_binary(level + 1)?;
+        while let Some(op) = self.peek_operator() {
+            if !LEVELS[level].contains(&op) {
+                break;
+            }
+            self.position += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_power(&mut self) -> Result<Expr, &'static str> {
+        let base = self.parse_unary()?;
+        if self.peek_operator() != Some("**") {
+            return Ok(base);
+        }
+        self.position += 1;
+        // `**` is right associative.
+        let exponent = self.parse_power()?;
+        Ok(Expr::Binary("**", Box::new(base), Box::new(exponent)))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, &'static str> {
+        match self.peek_operator() {
+            Some(op @ ("!" | "~" | "-" | "+")) => {
+                self.position += 1;
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            Some(op @ ("++" | "--")) => {
+                self.position += 1;
+                match self.tokens.get(self.position) {
+                    Some(Token::Identifier(name)) => {
+                        let name = name.clone();
+                        self.position += 1;
+                        Ok(Expr::PreIncrement(name, if op == "++" { 1 } else { -1 }))
+                    }
+                    _ => Err("syntax error: operand expected"),
+                }
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, &'static str> {
+        let primary = self.parse_primary()?;
+        if let Expr::Variable(name) = &primary {
+            if let Some(op @ ("++" | "--")) = self.peek_operator() {
+                self.position += 1;
+                return Ok(Expr::PostIncrement(
+                    name.clone(),
+                    if op == "++" { 1 } else { -1 },
+                ));
+            }
+        }
+        Ok(primary)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, &'static str> {
+        match self.tokens.get(self.position).cloned() {
+            Some(Token::Number(value)) => {
+                self.position += 1;
+                Ok(Expr::Number(value))
+            }
+            Some(Token::Identifier(name)) => {
+                self.position += 1;
+                Ok(Expr::Variable(name))
+            }
+            Some(Token::Operator("(")) => {
+                self.position += 1;
+                let expr = self.parse_comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            _ => Err("syntax error: operand expected"),
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    variables: &'a mut dyn Variables,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, expr: &Expr) -> Result<i64, &'static str> {
+        match expr {
+            Expr::Number(value) => Ok(*value),
+            Expr::Variable(name) => self.read(name),
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                Ok(match *op {
+                    "!" => (value == 0) as i64,
+                    "~" => !value,
+                    "-" => value.wrapping_neg(),
+                    _ => value,
+                })
+            }
+            Expr::Binary("&&", lhs, rhs) => {
+                Ok((self.eval(lhs)? != 0 && self.eval(rhs)? != 0) as i64)
+            }
+            Expr::Binary("||", lhs, rhs) => {
+                Ok((self.eval(lhs)? != 0 || self.eval(rhs)? != 0) as i64)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                apply_binary(op, lhs, rhs)
+            }
+            Expr::Conditional(condition, if_true, if_false) => {
+                if self.eval(condition)? != 0 {
+                    self.eval(if_true)
+                } else {
+                    self.eval(if_false)
+                }
+            }
+            Expr::Assign(name, op, rhs) => {
+                let rhs = self.eval(rhs)?;
+                let value = match *op {
+                    "=" => rhs,
+                    op => apply_binary(&op[..op.len() - 1], self.read(name)?, rhs)?,
+                };
+                self.variables.set_variable(name, value.to_string());
+                Ok(value)
+            }
+            Expr::PreIncrement(name, delta) => {
+                let value = self.read(name)?.wrapping_add(*delta);
+                self.variables.set_variable(name, value.to_string());
+                Ok(value)
+            }
+            Expr::PostIncrement(name, delta) => {
+                let value = self.read(name)?;
+                self.variables
+                    .set_variable(name, value.wrapping_add(*delta).to_string());
+                Ok(value)
+            }
+        }
+    }
+
+    // Unset and empty variables are 0; anything else is itself evaluated as an
+    // expression, so `a=b; b=3; $((a))` gives 3.
+    fn read(&mut self, name: &str) -> Result<i64, &'static str> {
+        let value = self.variables.get_variable(name).unwrap_or_default();
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(0);
+        }
+        if let Ok(number) = value.parse::<i64>() {
+            return Ok(number);
+        }
+        evaluate_with_depth(value, self.variables, self.depth + 1)
+    }
+}
+
+fn apply_binary(op: &str, lhs: i64, rhs: i64) -> Result<i64, &'static str> {
+    Ok(match op {
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err("division by zero"),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        "**" => {
+            if rhs < 0 {
+                return Err("exponent less than 0");
+            }
+            power(lhs, rhs as u64)
+        }
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "<" => (lhs < rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "&" => lhs & rhs,
+        "^" => lhs ^ rhs,
+        "|" => lhs | rhs,
+        "," => rhs,
+        _ => return Err("syntax error in expression"),
+    })
+}
+
+fn power(mut base: i64, mut exponent: u64) -> i64 {
+    let mut result: i64 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    impl Variables for HashMap<String, String> {
+        fn get_variable(&self, name: &str) -> Option<String> {
+            self.get(name).cloned()
+        }
+
+        fn set_variable(&mut self, name: &str, value: String) {
+            self.insert(name.to_string(), value);
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let test_cases = [
+            ("1 + 2 * 3", 7),
+            ("(1 + 2) * 3", 9),
+            ("2 ** 3 ** 2", 512),
+            ("-2 ** 2", 4),
+            ("7 / 2", 3),
+            ("-7 % 3", -1),
+            ("1 < 2 && 3 >= 4", 0),
+            ("0 || 5", 1),
+            ("!0", 1),
+            ("~5 & 0xff", 250),
+            ("1 << 4 | 1", 17),
+            ("6 ^ 3", 5),
+            ("010 + 2#101", 13),
+            ("1 ? 2 : 3", 2),
+            ("", 0),
+        ];
+
+        let mut variables: HashMap<String, String> = HashMap::new();
+        for (input, expected) in test_cases.iter() {
+            match evaluate(input, &mut variables) {
+                Ok(value) => assert_eq!(value, *expected, "{}", input),
+                Err(err) => panic!("[test_evaluate] {}: expected Ok, got {}", input, err),
+            }
+        }
+    }
+
+    #[test]
+    fn test_evaluate_assignment() {
+        let mut variables: HashMap<String, String> = HashMap::new();
+        variables.insert("i".to_string(), "4".to_string());
+
+        assert_eq!(evaluate("i += 1", &mut variables), Ok(5));
+        assert_eq!(evaluate("j = i++ * 2", &mut variables), Ok(10));
+        assert_eq!(evaluate("--i, $j + i", &mut variables), Ok(15));
+        assert_eq!(evaluate("0 && (k = 1)", &mut variables), Ok(0));
+        assert_eq!(variables.get("i").unwrap(), "5");
+        assert_eq!(variables.get("j").unwrap(), "10");
+        assert!(!variables.contains_key("k"));
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let test_cases = [
+            ("1 / 0", "division by zero"),
+            ("5 % (2 - 2)", "division by zero"),
+            ("2 ** -1", "exponent less than 0"),
+            ("1 +", "syntax error: operand expected"),
+            ("(1 + 2", "syntax error in expression"),
+            ("3 = 4", "attempted assignment to non-variable"),
+            ("9#9", "value too great for base"),
+        ];
+
+        let mut variables: HashMap<String, String> = HashMap::new();
+        for (input, expected) in test_cases.iter() {
+            match evaluate(input, &mut variables) {
+                Ok(value) => panic!("{}: expected error, got {}", input, value),
+                Err(err) => assert_eq!(err, *expected),
+            }
+        }
+    }
+}
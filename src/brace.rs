@@ -0,0 +1,225 @@
+// Brace expansion: `{a,b,c}` alternatives and `{x..y[..step]}` sequences.
+//
+// This runs on words straight from `split_words`, before any other expansion,
+// so quoted or escaped braces are left alone here and become literal later.
+
+// The most values a `{x..y}` sequence may expand to; longer ones are left as
+// they are rather than exhausting memory.
+const MAX_SEQUENCE_LENGTH: u64 = 1_000_000;
+
+/// Expands the first brace expression in `word` and then, recursively, the
+/// results, so nested and repeated braces are all expanded.
+pub fn expand_braces(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let plain = plain_positions(&chars);
+    for open in 0..chars.len() {
+        if chars[open] != '{' || !plain[open] || (open > 0 && chars[open - 1] == '$') {
+            continue;
+        }
+        let Some(close) = matching_brace(&chars, &plain, open) else {
+            continue;
+        };
+        let Some(alternatives) =
+            brace_alternatives(&chars[open + 1..close], &plain[open + 1..close])
+        else {
+            continue;
+        };
+        let prefix: String = chars[..open].iter().collect();
+        let suffix: String = chars[close + 1..].iter().collect();
+        return alternatives
+            .iter()
+            .flat_map(|alternative| expand_braces(&format!("{}{}{}", prefix, alternative, suffix)))
+            .collect();
+    }
+    vec![word.to_string()]
+}
+
+// Marks the characters that are outside quotes, not escaped and not part of a
+// `${...}` parameter expansion.
+fn plain_positions(chars: &[char]) -> Vec<bool> {
+    let mut plain = vec![false; chars.len()];
+    let mut quote: Option<char> = None;
+    let mut parameter_depth = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some('"') if c == '\\' => i += 1,
+            Some(_) => {}
+            None if c == '\\' => i += 1,
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '$' && chars.get(i + 1) == Some(&'{') => {
+                parameter_depth += 1;
+                i += 1;
+            }
+            None if parameter_depth > 0 => {
+                if c == '}' {
+                    parameter_depth -= 1;
+                }
+            }
+            None => plain[i] = true,
+        }
+        i += 1;
+    }
+    plain
+}
+
+fn matching_brace(chars: &[char], plain: &[bool], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for i in open..chars.len() {
+        if !plain[i] {
+            continue;
+        }
+        match chars[i] {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Returns the alternatives of a brace body, or `None` if it is neither a comma
+// list nor a valid sequence (e.g. `{}` or `{a}`), in which case the braces are
+// kept literally.
+fn brace_alternatives(body: &[char], plain: &[bool]) -> Option<Vec<String>> {
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in body.iter().enumerate() {
+        if !plain[i] {
+            continue;
+        }
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(body[start..i].iter().collect());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !alternatives.is_empty() {
+        alternatives.push(body[start..].iter().collect());
+        return Some(alternatives);
+    }
+    let body: String = body.iter().collect();
+    expand_sequence(&body)
+}
+
+fn expand_sequence(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    if parts.len() != 2 && parts.len() != 3 {
+        return None;
+    }
+    let step = match parts.get(2) {
+        Some(step) => step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        None => 1,
+    };
+    let (start, end) = (parts[0], parts[1]);
+    if let (Ok(from), Ok(to)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // A leading zero on either end pads every value to the longer width.
+        let padded = |s: &str| {
+            s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        return Some(
+            sequence(from, to, step)?
+                .into_iter()
+                .map(|value| format!("{:0width$}", value, width = width))
+                .collect(),
+        );
+    }
+    let (mut start_chars, mut end_chars) = (start.chars(), end.chars());
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(from), None, Some(to), None)
+            if from.is_ascii_alphabetic() && to.is_ascii_alphabetic() =>
+        {
+            Some(
+                sequence(from as i64, to as i64, step)?
+                    .into_iter()
+                    .map(|value| (value as u8 as char).to_string())
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+// The values from `from` towards `to`, `step` apart. `None` when there are
+// too many of them.
+fn sequence(from: i64, to: i64, step: u64) -> Option<Vec<i64>> {
+    let count = (from.abs_diff(to) / step).checked_add(1)?;
+    if count > MAX_SEQUENCE_LENGTH {
+        return None;
+    }
+    (0..count)
+        .map(|i| {
+            let offset = i.checked_mul(step)?;
+            match from <= to {
+                true => from.checked_add_unsigned(offset),
+                false => from.checked_sub_unsigned(offset),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_braces() {
+        let test_cases = [
+            ("src/{bin,lib}", vec!["src/bin", "src/lib"]),
+            (
+                "file{1..3}.txt",
+                vec!["file1.txt", "file2.txt", "file3.txt"],
+            ),
+            ("{01..10..4}", vec!["01", "05", "09"]),
+            ("{3..-1..2}", vec!["3", "1", "-1"]),
+            ("{a..e..2}", vec!["a", "c", "e"]),
+            ("a{b,c{1,2}}d", vec!["abd", "ac1d", "ac2d"]),
+            ("{x,y}{1..2}", vec!["x1", "x2", "y1", "y2"]),
+            ("x{,.bak}", vec!["x", "x.bak"]),
+            ("{a}", vec!["{a}"]),
+            ("{}", vec!["{}"]),
+            ("{1..a}", vec!["{1..a}"]),
+            ("'{a,b}'", vec!["'{a,b}'"]),
+            ("\"{a,b}\"{c,d}", vec!["\"{a,b}\"c", "\"{a,b}\"d"]),
+            ("\\{a,b}", vec!["\\{a,b}"]),
+            ("${x}{1,2}", vec!["${x}1", "${x}2"]),
+            ("{1..3..-9223372036854775808}", vec!["1"]),
+            (
+                "{-9223372036854775808..9223372036854775807..9223372036854775807}",
+                vec!["-9223372036854775808", "-1", "9223372036854775806"],
+            ),
+            (
+                "{-9223372036854775808..9223372036854775807}",
+                vec!["{-9223372036854775808..9223372036854775807}"],
+            ),
+            ("{0..9223372036854775807}", vec!["{0..9223372036854775807}"]),
+            ("{1..10000000000}", vec!["{1..10000000000}"]),
+        ];
+
+        for (input, expected) in test_cases.iter() {
+            assert_eq!(expand_braces(input), *expected, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Option parsing and line decoration for `cat`.
+
+#[derive(Default)]
+pub struct CatOptions {
+    pub number: bool,
+    pub number_nonblank: bool,
+    pub show_ends: bool,
+    pub show_tabs: bool,
+    pub show_nonprinting: bool,
+}
+
+impl CatOptions {
+    /// Splits `cat` arguments into options and operands; `-` is stdin.
+    pub fn parse(arguments: &[String]) -> Result<(CatOptions, Vec<String>), &'static str> {
+        let mut options = CatOptions::default();
+        let mut operands = Vec::new();
+        let mut end_of_options = false;
+        for argument in arguments {
+            if end_of_options || argument == "-" || !argument.starts_with('-') {
+                operands.push(argument.clone());
+                continue;
+            }
+            if argument == "--" {
+                end_of_options = true;
+                continue;
+            }
+            let flags: Vec<char> = match argument.as_str() {
+                "--number" => vec!['n'],
+                "--number-nonblank" => vec!['b'],
+                "--show-all" => vec!['A'],
+                "--show-ends" => vec!['E'],
+                "--show-tabs" => vec!['T'],
+                "--show-nonprinting" => vec!['v'],
+                _ if argument.starts_with("--") => return Err("cat: unrecognized option"),
+                _ => argument[1..].chars().collect(),
+            };
+            for flag in flags {
+                match flag {
+                    'n' => options.number = true,
+                    'b' => options.number_nonblank = true,
+                    'E' => options.show_ends = true,
+                    'T' => options.show_tabs = true,
+                    'v' => options.show_nonprinting = true,
+                    'A' => {
+                        options.show_nonprinting = true;
+                        options.show_ends = true;
+                        options.show_tabs = true;
+                    }
+                    'e' => {
+                        options.show_nonprinting = true;
+                        options.show_ends = true;
+                    }
+                    't' => {
+                        options.show_nonprinting = true;
+                        options.show_tabs = true;
+                    }
+                    _ => return Err("cat: invalid option. Supported options: -nbAETvet"),
+                }
+            }
+        }
+        Ok((options, operands))
+    }
+
+    /// Whether the input has to be decorated rather than copied as is.
+    pub fn decorates_lines(&self) -> bool {
+        self.number
+            || self.number_nonblank
+            || self.show_ends
+            || self.show_tabs
+            || self.show_nonprinting
+    }
+
+    /// Appends `chunk` to `output` with line numbers and markers. Lines may
+    /// span chunks: `at_line_start` tells whether the previous chunk ended a
+    /// line and is updated for the next one. `line_number` carries on across
+    /// files.
+    pub fn format_chunk(
+        &self,
+        chunk: &[u8],
+        at_line_start: &mut bool,
+        line_number: &mut usize,
+        output: &mut Vec<u8>,
+    ) {
+        for &byte in chunk {
+            if *at_line_start {
+                let numbered = if self.number_nonblank {
+                    byte != b'\n'
+                } else {
+                    self.number
+                };
+                if numbered {
+                    *line_number += 1;
+                    output.extend_from_slice(format!("{:>6}\t", line_number).as_bytes());
+                }
+                *at_line_start = false;
+            }
+            match byte {
+                b'\n' => {
+                    if self.show_ends {
+                        output.push(b'$');
+                    }
+                    output.push(b'\n');
+                    *at_line_start = true;
+                }
+                b'\t' if self.show_tabs => output.extend_from_slice(b"^I"),
+                b'\t' => output.push(byte),
+                _ if self.show_nonprinting => push_visible(byte, output),
+                _ => output.push(byte),
+            }
+        }
+    }
+}
+
+// `^X` for control characters, `^?` for DEL and `M-` for bytes above 127.
+fn push_visible(byte: u8, output: &mut Vec<u8>) {
+    let byte = if byte >= 128 {
+        output.extend_from_slice(b"M-");
+        byte - 128
+    } else {
+        byte
+    };
+    match byte {
+        0..=31 => output.extend_from_slice(&[b'^', byte + 64]),
+        127 => output.extend_from_slice(b"^?"),
+        _ => output.push(byte),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_chunk() {
+        let test_cases: [(&str, &[u8], &[u8]); 5] = [
+            ("-n", b"a\n", b"     1\ta\n"),
+            ("-b", b"\n", b"\n"),
+            ("-E", b"a b\n", b"a b$\n"),
+            ("-T", b"a\tb", b"a^Ib"),
+            ("-v", b"\x01\x7f\xe9\t\n", b"^A^?M-i\t\n"),
+        ];
+        for (flag, line, expected) in test_cases.iter() {
+            let (options, _) = CatOptions::parse(&[flag.to_string()]).unwrap();
+            let mut output = Vec::new();
+            options.format_chunk(line, &mut true, &mut 0, &mut output);
+            assert_eq!(output, *expected, "{}", flag);
+        }
+
+        // A line split across chunks is numbered once.
+        let (options, _) = CatOptions::parse(&["-bE".to_string()]).unwrap();
+        let (mut output, mut at_line_start, mut line_number) = (Vec::new(), true, 0);
+        for chunk in [&b"ab"[..], b"c\n", b"", b"\nd"] {
+            options.format_chunk(chunk, &mut at_line_start, &mut line_number, &mut output);
+        }
+        assert_eq!(output, b"     1\tabc$\n$\n     2\td");
+        assert!(!at_line_start);
+    }
+}
@@ -0,0 +1,359 @@
+// Conditional expressions of `test`, `[` and `[[`. Words come with a flag per
+// character telling whether it was quoted, which only matters to `[[`: there
+// the unquoted parts of the right side of `==` and `=~` are a pattern.
+
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::glob::Pattern;
+
+pub type Word = Vec<(char, bool)>;
+
+pub struct Condition<'a> {
+    words: &'a [Word],
+    index: usize,
+    // `[[`: `&&`, `||`, `<`, `>`, patterns and `=~` instead of `-a` and `-o`.
+    extended: bool,
+    base: &'a Path,
+    // What the last `=~` matched: the whole match, then each group.
+    matches: Option<Vec<String>>,
+    // Set while parsing the side of `&&` or `||` that the other side already
+    // decided: it is checked for syntax but not evaluated.
+    skipping: bool,
+}
+
+impl Condition<'_> {
+    /// Evaluates `words` with files looked up relative to `base`. An error
+    /// means the expression itself is malformed.
+    pub fn evaluate(
+        words: &[Word],
+        extended: bool,
+        base: &Path,
+    ) -> Result<(bool, Option<Vec<String>>), String> {
+        let mut condition = Condition {
+            words,
+            index: 0,
+            extended,
+            base,
+            matches: None,
+            skipping: false,
+        };
+        // No expression at all is false.
+        if words.is_empty() {
+            return Ok((false, None));
+        }
+        if !extended {
+            if let Some(value) = condition.by_count(0, words.len()) {
+                return value.map(|value| (value, None));
+            }
+        }
+        let value = condition.or()?;
+        if let Some(word) = condition.text(condition.index) {
+            return Err(format!("{}: unexpected argument", word));
+        }
+        Ok((value, condition.matches))
+    }
+
+    // `test` decides by the number of arguments first, as POSIX says, so that
+    // `[ ( ]` and `[ ! ]` test a string instead of failing. `None` leaves
+    // longer expressions to the parser.
+    fn by_count(&mut self, start: usize, count: usize) -> Option<Result<bool, String>> {
+        let text: Vec<String> = (start..start + count)
+            .map(|index| self.text(index).unwrap_or_default())
+            .collect();
+        let negate = |value: Option<Result<bool, String>>| value.map(|x| x.map(|x| !x));
+        match count {
+            1 => Some(Ok(!text[0].is_empty())),
+            2 if text[0] == "!" => Some(Ok(text[1].is_empty())),
+            2 if is_unary(&text[0]) => Some(Ok(self.unary(&text[0], &text[1]))),
+            2 => Some(Err(format!("{}: unary operator expected", text[0]))),
+            3 if self.is_binary(start + 1) => {
+                let right = self.words[start + 2].clone();
+                Some(self.binary(&text[0], &text[1], &right))
+            }
+            3 if text[1] == "-a" => Some(Ok(!text[0].is_empty() && !text[2].is_empty())),
+            3 if text[1] == "-o" => Some(Ok(!text[0].is_empty() || !text[2].is_empty())),
+            3 if text[0] == "!" => negate(self.by_count(start + 1, 2)),
+            3 if text[0] == "(" && text[2] == ")" => Some(Ok(!text[1].is_empty())),
+            4 if text[0] == "!" => negate(self.by_count(start + 1, 3)),
+            4 if text[0] == "(" && text[3] == ")" => self.by_count(start + 1, 2),
+            _ => None,
+        }
+    }
+
+    fn text(&self, index: usize) -> Option<String> {
+        self.words
+            .get(index)
+            .map(|word| word.iter().map(|(c, _)| *c).collect())
+    }
+
+    fn next_is(&self, operators: &[&str]) -> bool {
+        self.text(self.index)
+            .is_some_and(|word| operators.contains(&word.as_str()))
+    }
+
+    fn is_binary(&self, index: usize) -> bool {
+        let Some(word) = self.text(index) else {
+            return false;
+        };
+        match word.as_str() {
+            "=" | "==" | "!=" | "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => true,
+            "<" | ">" | "=~" => self.extended,
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let operator = if self.extended { "||" } else { "-o" };
+        let mut value = self.and()?;
+        while self.next_is(&[operator]) {
+            self.index += 1;
+            let skipping = self.skipping;
+            self.skipping |= value;
+            let right = self.and();
+            self.skipping = skipping;
+            value = value || right?;
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let operator = if self.extended { "&&" } else { "-a" };
+        let mut value = self.not()?;
+        while self.next_is(&[operator]) {
+            self.index += 1;
+            let skipping = self.skipping;
+            self.skipping |= !value;
+            let right = self.not();
+            self.skipping = skipping;
+            value = value && right?;
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        // `! = x` compares the string `!`.
+        if self.next_is(&["!"]) && !self.is_binary(self.index + 1) {
+            if self.index + 1 == self.words.len() {
+                return Err("argument expected".to_string());
+            }
+            self.index += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let Some(word) = self.text(self.index) else {
+            return Err("argument expected".to_string());
+        };
+        if self.is_binary(self.index + 1) && self.index + 2 < self.words.len() {
+            let left = word;
+            let operator = self.text(self.index + 1).unwrap_or_default();
+            let right = self.words[self.index + 2].clone();
+            self.index += 3;
+            if self.skipping {
+                return Ok(false);
+            }
+            return self.binary(&left, &operator, &right);
+        }
+        if word == "(" {
+            self.index += 1;
+            let value = self.or()?;
+            if !self.next_is(&[")"]) {
+                return Err("`)' expected".to_string());
+            }
+            self.index += 1;
+            return Ok(value);
+        }
+        if let Some(operand) = self.text(self.index + 1).filter(|_| is_unary(&word)) {
+            self.index += 2;
+            return Ok(!self.skipping && self.unary(&word, &operand));
+        }
+        self.index += 1;
+        Ok(!word.is_empty())
+    }
+
+    fn unary(&self, operator: &str, operand: &str) -> bool {
+        match operator {
+            "-z" => return operand.is_empty(),
+            "-n" => return !operand.is_empty(),
+            _ => {}
+        }
+        let path = self.base.join(operand);
+        if operator == "-L" || operator == "-h" {
+            return fs::symlink_metadata(&path).is_ok_and(|x| x.file_type().is_symlink());
+        }
+        let Ok(metadata) = fs::metadata(&path) else {
+            return false;
+        };
+        match operator {
+            "-e" => true,
+            "-f" => metadata.is_file(),
+            "-d" => metadata.is_dir(),
+            "-p" => metadata.file_type().is_fifo(),
+            "-S" => metadata.file_type().is_socket(),
+            "-s" => metadata.len() > 0,
+            "-r" => accessible(&path, libc::R_OK),
+            "-w" => accessible(&path, libc::W_OK),
+            _ => accessible(&path, libc::X_OK),
+        }
+    }
+
+    fn binary(&mut self, left: &str, operator: &str, right: &Word) -> Result<bool, String> {
+        let text: String = right.iter().map(|(c, _)| *c).collect();
+        match operator {
+            "=" | "==" | "!=" => {
+                let equal = match self.extended {
+                    true => Pattern::from_chars(right).matches(left, false),
+                    false => left == text,
+                };
+                Ok(equal == (operator != "!="))
+            }
+            "<" => Ok(left < text.as_str()),
+            ">" => Ok(left > text.as_str()),
+            "=~" => {
+                // Quoted parts of the regular expression match literally.
+                let pattern: String = right
+                    .iter()
+                    .map(|&(c, quoted)| match quoted {
+                        true => regex::escape(&c.to_string()),
+                        false => c.to_string(),
+                    })
+                    .collect();
+                let regex = Regex::new(&pattern)
+                    .map_err(|_| format!("{}: invalid regular expression", text))?;
+                let captures = regex.captures(left);
+                self.matches = Some(match &captures {
+                    Some(captures) => captures
+                        .iter()
+                        .map(|x| x.map_or("", |x| x.as_str()).to_string())
+                        .collect(),
+                    None => Vec::new(),
+                });
+                Ok(captures.is_some())
+            }
+            _ => {
+                let left = integer(left)?;
+                let right = integer(&text)?;
+                Ok(match operator {
+                    "-eq" => left == right,
+                    "-ne" => left != right,
+                    "-lt" => left < right,
+                    "-le" => left <= right,
+                    "-gt" => left > right,
+                    _ => left >= right,
+                })
+            }
+        }
+    }
+}
+
+fn is_unary(operator: &str) -> bool {
+    matches!(
+        operator,
+        "-z" | "-n" | "-e" | "-f" | "-d" | "-r" | "-w" | "-x" | "-s" | "-L" | "-h" | "-p" | "-S"
+    )
+}
+
+fn integer(value: &str) -> Result<i64, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{}: integer expression expected", value))
+}
+
+// Whether the user running the shell may read, write or execute `path`.
+fn accessible(path: &Path, mode: libc::c_int) -> bool {
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), mode) == 0 },
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unquoted words, except for the parts between single quotes.
+    fn words(line: &str) -> Vec<Word> {
+        line.split(' ')
+            .map(|word| {
+                let mut quoted = false;
+                let mut chars = Vec::new();
+                for c in word.chars() {
+                    match c {
+                        '\'' => quoted = !quoted,
+                        c => chars.push((c, quoted)),
+                    }
+                }
+                chars
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        fs::write(temp_dir.path().join("full"), "x").unwrap();
+        fs::write(temp_dir.path().join("empty"), "").unwrap();
+        std::os::unix::fs::symlink("full", temp_dir.path().join("link")).unwrap();
+
+        let test_cases = [
+            ("-f full -a -s full", false, true),
+            ("-s empty -o -d .", false, true),
+            ("! -e missing", false, true),
+            ("-L link -a ! -L full", false, true),
+            ("-n '' -o -z x", false, false),
+            ("10 -gt 9 -a -3 -le -3", false, true),
+            ("a != b", false, true),
+            ("( a = b -o c ) -a d", false, true),
+            ("! = !", false, true),
+            ("-n", false, true),
+            ("(", false, true),
+            ("!", false, true),
+            ("! (", false, false),
+            ("! ! x", false, true),
+            ("( ! )", false, true),
+            ("! ( '' )", false, true),
+            ("x -a ''", false, false),
+            ("-f -o ''", false, true),
+            ("abc == a*", true, true),
+            ("abc == 'a*'", true, false),
+            ("a < b && ( -z x || b > a )", true, true),
+            ("-d full || x != x", true, false),
+        ];
+        for (line, extended, expected) in test_cases.iter() {
+            let result = Condition::evaluate(&words(line), *extended, temp_dir.path());
+            assert_eq!(result.unwrap().0, *expected, "{}", line);
+        }
+
+        let (value, matches) = Condition::evaluate(
+            &words("key=42 =~ ^([a-z]+)'='([0-9]+)(x)?$"),
+            true,
+            Path::new("/"),
+        )
+        .unwrap();
+        assert!(value);
+        assert_eq!(matches.unwrap(), ["key=42", "key", "42", ""]);
+
+        // The side that isn't needed is not evaluated.
+        let (value, matches) =
+            Condition::evaluate(&words("a == a || x =~ (x)"), true, Path::new("/")).unwrap();
+        assert!(value && matches.is_none());
+        let (value, _) =
+            Condition::evaluate(&words("a == b && 1 -eq x"), true, Path::new("/")).unwrap();
+        assert!(!value);
+
+        for invalid in ["1 -eq x", "( a", "a b", "( )", "a b c d e"] {
+            assert!(Condition::evaluate(&words(invalid), false, Path::new("/")).is_err());
+        }
+        temp_dir.close().unwrap();
+    }
+}
@@ -1,27 +1,95 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CString;
 use std::fs::File;
-use std::io::{self, BufRead, Read, Write};
-use std::os::unix::fs::PermissionsExt;
-use std::time::UNIX_EPOCH;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
 
-use chrono::{DateTime, Utc};
+use crate::arithmetic::{self, Variables};
+use crate::brace::expand_braces;
+use crate::cat::CatOptions;
+use crate::condition::{Condition, Word};
+use crate::files::{copy_tree, move_path, FileCommand, FileOptions};
+use crate::find::{self, FindOptions};
+use crate::follow::{self, Followed, InterruptGuard};
+use crate::glob::{self, GlobOptions};
+use crate::grep::{self, GrepOptions};
+use crate::head::{self, HeadOptions};
+use crate::ls::{
+    display_name, format_time, human_size, layout_columns, mode_string, size_column, sort_entries,
+    ColorMode, Entry, ListOptions, LsColors,
+};
+use crate::mkdir::MakeDirectoryOptions;
+use crate::parser::{find_redirection, find_unquoted, split_words, unquote_command_name};
+use crate::printf;
+use crate::rm::{file_kind, RemoveOptions};
+use crate::sort::SortOptions;
+use crate::tail::{self, Follow, TailOptions};
+use crate::touch::{TimeSource, TouchOptions};
+use crate::trash::Trash;
+use crate::users;
+use crate::wc::{Counts, WcOptions};
 
 const HISTORY_SIZE: usize = 10;
+// Size of the chunks commands copy their input in.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+// How often `tail -f` checks for Ctrl-C while waiting.
+const FOLLOW_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+// Terminal width assumed when neither COLUMNS nor the terminal tell us.
+const DEFAULT_WIDTH: usize = 80;
 
 pub struct Emulator {
     writer: io::BufWriter<io::Stdout>,
     reader: io::BufReader<io::Stdin>,
     path: std::path::PathBuf,
+    // Directories saved by `pushd`, most recent first; `dirs` shows `path`
+    // followed by these.
+    directory_stack: Vec<std::path::PathBuf>,
     history: VecDeque<String>,
+    variables: HashMap<String, String>,
+    // Array variables, like `BASH_REMATCH` set by `[[ ... =~ ... ]]`.
+    arrays: HashMap<String, Vec<String>>,
+    glob_options: GlobOptions,
+    // `shopt -s saferm`: `rm` and `rmdir` move things to the trash.
+    safe_rm: bool,
+    // Status of the command currently running and of the last finished one
+    // (`$?`). Commands report failure without an error message by setting
+    // `exit_status`.
+    exit_status: i32,
+    last_exit_status: i32,
+    // The file given with `>`, written instead of stdout while the command
+    // runs.
+    output_redirect: Option<File>,
+    // Set by commands that write their output directly (`write_output`)
+    // instead of returning it.
+    output_streamed: bool,
+    // The file given with `<`, read instead of stdin by commands that take
+    // their input from it.
+    input_redirect: Option<File>,
 }
 
 impl Emulator {
     pub fn new() -> Emulator {
+        // The directory the shell was started in may already be gone.
+        let path = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("/"));
+        let mut variables = HashMap::new();
+        variables.insert("PWD".to_string(), path.to_string_lossy().into_owned());
         Emulator {
             writer: io::BufWriter::new(io::stdout()),
             reader: io::BufReader::new(io::stdin()),
-            path: std::env::current_dir().unwrap(),
+            path,
+            directory_stack: Vec::new(),
             history: VecDeque::with_capacity(HISTORY_SIZE),
+            variables,
+            arrays: HashMap::new(),
+            glob_options: GlobOptions::default(),
+            safe_rm: false,
+            exit_status: 0,
+            last_exit_status: 0,
+            output_redirect: None,
+            output_streamed: false,
+            input_redirect: None,
         }
     }
 
@@ -30,7 +98,17 @@ impl Emulator {
             writer: io::BufWriter::new(io::stdout()),
             reader: io::BufReader::new(io::stdin()),
             path: self.path.clone(),
+            directory_stack: self.directory_stack.clone(),
             history: self.history.clone(),
+            variables: self.variables.clone(),
+            arrays: self.arrays.clone(),
+            glob_options: self.glob_options.clone(),
+            safe_rm: self.safe_rm,
+            exit_status: 0,
+            last_exit_status: self.last_exit_status,
+            output_redirect: None,
+            output_streamed: false,
+            input_redirect: None,
         }
     }
 
@@ -51,41 +129,93 @@ impl Emulator {
             let input_buffer = input_buffer.clone();
             let input_buffer_trimmed = input_buffer.trim().trim_end_matches(" &").to_string(); // Remove the trailing `&`
             let mut emulator = self.clone(); // Clone the emulator to be used in the spawned thread
-            std::thread::spawn(
-                move || match emulator.process_command(&input_buffer_trimmed) {
-                    Ok(result) => emulator.print_to_stdout(&result, true),
-                    Err(err) => {
-                        emulator.print_to_stdout(format!("mini-shell: {}", err).as_str(), true)
-                    }
-                },
-            );
+            std::thread::spawn(move || {
+                let result = emulator.run_command(&input_buffer_trimmed);
+                emulator.print_result(result);
+            });
             return;
         }
 
-        match self.process_command(&input_buffer) {
+        let result = self.run_command(&input_buffer);
+        self.print_result(result);
+    }
+
+    fn print_result(&mut self, result: Result<String, &'static str>) {
+        match result {
+            // The output has already been written, byte for byte.
+            Ok(_) if self.output_streamed => self.print_to_stdout("", false),
             Ok(result) => self.print_to_stdout(&result, true),
             Err(err) => self.print_to_stdout(format!("mini-shell: {}", err).as_str(), true),
         }
     }
 
+    fn run_command(&mut self, command: &str) -> Result<String, &'static str> {
+        self.exit_status = 0;
+        self.output_streamed = false;
+        let result = self.process_command(command);
+        if result.is_err() {
+            self.exit_status = 1;
+        }
+        self.last_exit_status = self.exit_status;
+        result
+    }
+
     fn process_command(&mut self, command: &str) -> Result<String, &'static str> {
         self.record_history(command);
+        self.execute(command)
+    }
+
+    // Runs a command without adding it to the history, as `find -exec` does
+    // for each file.
+    fn execute(&mut self, command: &str) -> Result<String, &'static str> {
+        if let Some(command) = unquote_command_name(command) {
+            return self.execute(&command);
+        }
         match command.trim() {
             "exit" => std::process::exit(0),
             "history" => self.history(),
-            "pwd" => Ok((self.path.to_str().unwrap()).to_string()),
-            cmd if cmd.contains(">") => self.process_command_with_output_redirection(command),
-            cmd if cmd.contains("<") => self.process_command_with_input_redirection(command),
+            cmd if cmd == "pwd" || cmd.starts_with("pwd ") => self.print_working_directory(cmd),
+            cmd if cmd.starts_with("((") && cmd.ends_with("))") => {
+                self.process_arithmetic_command(cmd)
+            }
+            cmd if find_redirection(cmd, '>').is_some() => {
+                self.process_command_with_output_redirection(command)
+            }
+            cmd if find_redirection(cmd, '<').is_some() => {
+                self.process_command_with_input_redirection(command)
+            }
+            cmd if cmd.starts_with("[[ ") || cmd == "[[" => self.extended_test(command),
+            cmd if cmd == "let" || cmd.starts_with("let ") => self.let_command(command),
+            cmd if cmd == "shopt" || cmd.starts_with("shopt ") => self.shopt(command),
+            cmd if cmd == "pushd" || cmd.starts_with("pushd ") => self.pushd(command),
+            cmd if cmd == "popd" || cmd.starts_with("popd ") => self.popd(command),
+            cmd if cmd == "dirs" || cmd.starts_with("dirs ") => self.dirs(command),
             cmd if cmd.starts_with("ls") => self.list_directory(command),
             cmd if cmd.starts_with("echo") => self.echo(command),
+            cmd if cmd == "printf" || cmd.starts_with("printf ") => self.printf(command),
             cmd if cmd.starts_with("cd") => self.change_directory(command),
             cmd if cmd.starts_with("sleep") => self.sleep(command),
             cmd if cmd.starts_with("cat") => self.cat(command),
-            cmd if cmd.starts_with("rmdir") => self.rm(command, true),
-            cmd if cmd.starts_with("rm") => self.rm(command, false),
+            cmd if cmd.starts_with("rmdir") => self.rmdir(command),
+            cmd if cmd.starts_with("rm") => self.rm(command),
+            cmd if cmd == "trash" || cmd.starts_with("trash ") => self.trash(command),
             cmd if cmd.starts_with("touch ") => self.create_new_file(command),
             cmd if cmd.starts_with("mkdir") => self.create_new_directory(command),
             cmd if cmd.starts_with("grep") => self.process_grep_command(command),
+            cmd if cmd == "cp" || cmd.starts_with("cp ") => {
+                self.copy_or_move(command, FileCommand::Copy)
+            }
+            cmd if cmd == "mv" || cmd.starts_with("mv ") => {
+                self.copy_or_move(command, FileCommand::Move)
+            }
+            cmd if cmd == "ln" || cmd.starts_with("ln ") => self.link(command),
+            cmd if cmd == "sort" || cmd.starts_with("sort ") => self.sort(command),
+            cmd if cmd == "head" || cmd.starts_with("head ") => self.head(command),
+            cmd if cmd == "tail" || cmd.starts_with("tail ") => self.tail(command),
+            cmd if cmd == "wc" || cmd.starts_with("wc ") => self.wc(command),
+            cmd if cmd == "find" || cmd.starts_with("find ") => self.find(command),
+            cmd if cmd == "test" || cmd.starts_with("test ") => self.test(command),
+            cmd if cmd == "[" || cmd.starts_with("[ ") => self.test(command),
             _ => Err("mini-shell: command not found"),
         }
     }
@@ -94,419 +224,2905 @@ impl Emulator {
         &mut self,
         command: &str,
     ) -> Result<String, &'static str> {
-        let count = command.chars().filter(|&c| c == '<').count();
-        if count != 1 {
-            return Err("Invalid command. Correct usage `command < file`");
-        }
-        let (operation, file_name) = match command.trim().split_once('<') {
-            Some((op, file)) => (op.trim(), file.trim()),
+        let command = command.trim();
+        let (operation, file_name) = match find_redirection(command, '<') {
+            Some(index) => (command[..index].trim(), command[index + 1..].trim()),
             None => ("", ""),
         };
-        if operation.trim().is_empty() || file_name.trim().is_empty() {
+        if find_unquoted(file_name, '<').is_some() {
             return Err("Invalid command. Correct usage `command < file`");
         }
-        let file = std::fs::OpenOptions::new().read(true).open(file_name);
-        match file {
-            Ok(mut file) => {
-                let mut buffer = String::new();
-                if let Err(_) = file.read_to_string(&mut buffer) {
-                    return Err("Failed to read from file");
-                }
-                match operation.trim() {
-                    "sort" => self.process_sort_command(buffer.as_str()),
-                    _ => Err("mini-shell: command not found"),
-                }
-            }
+        if operation.is_empty() || file_name.is_empty() {
+            return Err("Invalid command. Correct usage `command < file`");
+        }
+        let file = self.open_for_reading(file_name)?;
+        self.input_redirect = Some(file);
+        let result = self.process_command(operation);
+        self.input_redirect = None;
+        result
+    }
+
+    // Reads the whole standard input of the running command: the `<` file
+    // if there is one, otherwise the shell's stdin up to end of file.
+    fn read_standard_input(&mut self) -> Result<Vec<u8>, &'static str> {
+        let mut buffer = Vec::new();
+        let result = match self.input_redirect.as_mut() {
+            Some(file) => file.read_to_end(&mut buffer),
+            None => self.reader.read_to_end(&mut buffer),
+        };
+        if result.is_err() {
+            return Err("Failed to read from stdin");
+        }
+        Ok(buffer)
+    }
+
+    // Opens a file operand for reading. The open itself never blocks: a FIFO
+    // without a writer reads as empty instead of waiting for one.
+    fn open_for_reading(&self, file_name: &str) -> Result<File, &'static str> {
+        let file_path = self.path.join(file_name);
+        match std::fs::metadata(&file_path) {
+            Ok(metadata) if metadata.is_dir() => return Err("Is a directory"),
+            Ok(_) => {}
+            Err(_) => return Err("Failed to open file"),
+        }
+        let file = match std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(file_path)
+        {
+            Ok(file) => file,
             Err(_) => return Err("Failed to open file"),
+        };
+        // Reads should still wait for data from a writer that is connected.
+        unsafe {
+            let flags = libc::fcntl(file.as_raw_fd(), libc::F_GETFL);
+            libc::fcntl(file.as_raw_fd(), libc::F_SETFL, flags & !libc::O_NONBLOCK);
         }
+        Ok(file)
     }
 
-    fn process_sort_command(&mut self, buffer: &str) -> Result<String, &'static str> {
-        let mut lines: Vec<&str> = buffer.split('\n').collect();
-        lines.sort();
-        Ok(lines.join("\n"))
+    fn sort(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (options, mut operands) = SortOptions::parse(&arguments[1..])?;
+        if operands.is_empty() {
+            operands.push("-".to_string());
+        }
+        let mut content = Vec::new();
+        for operand in operands.iter() {
+            let mut input = if operand == "-" {
+                self.read_standard_input()?
+            } else {
+                let mut buffer = Vec::new();
+                let mut file = self.open_for_reading(operand)?;
+                if file.read_to_end(&mut buffer).is_err() {
+                    return Err("Failed to read from file");
+                }
+                buffer
+            };
+            // Every file ends a line, even without a trailing newline.
+            if !input.is_empty() && input.last() != Some(&b'\n') {
+                input.push(b'\n');
+            }
+            content.append(&mut input);
+        }
+        let content = String::from_utf8_lossy(&content);
+        let mut lines: Vec<&str> = content.split_terminator('\n').collect();
+        options.sort(&mut lines);
+        if options.unique {
+            lines.dedup_by(|line, previous| options.equal(previous, line));
+        }
+        let sorted = lines.join("\n");
+        // The input is fully read first, so `-o` may name one of the inputs.
+        if let Some(output) = options.output.as_ref() {
+            let contents = if lines.is_empty() {
+                String::new()
+            } else {
+                format!("{}\n", sorted)
+            };
+            if std::fs::write(self.path.join(output), contents).is_err() {
+                return Err("Failed to write to file");
+            }
+            return Ok("".to_string());
+        }
+        Ok(sorted)
     }
 
     fn process_command_with_output_redirection(
         &mut self,
         command: &str,
     ) -> Result<String, &'static str> {
-        let count = command.chars().filter(|&c| c == '>').count();
-        if count > 2 {
+        let command = command.trim();
+        let (operation, file_name, append) = match find_redirection(command, '>') {
+            Some(index) => match command[index + 1..].strip_prefix('>') {
+                Some(file) => (command[..index].trim(), file.trim(), true),
+                None => (command[..index].trim(), command[index + 1..].trim(), false),
+            },
+            None => ("", "", false),
+        };
+        if find_unquoted(file_name, '>').is_some() {
             return Err("Invalid command. Correct usage `command > file` OR `command >> file`");
         }
-        let (operation, file_name) = match count {
-            1 => {
-                let parts = command.trim().split_once('>');
-                if let Some((op, file)) = parts {
-                    (op.trim(), file.trim())
-                } else {
-                    // Handle case when '>' is missing
-                    ("", "")
-                }
-            }
-            2 => {
-                let parts = command.trim().split_once(">>");
-                if let Some((op, file)) = parts {
-                    (op.trim(), file.trim())
-                } else {
-                    ("", "")
-                }
-            }
-            _ => ("", ""),
-        };
         if operation.is_empty() || file_name.is_empty() {
             return Err("Invalid command. Correct usage `command > file` OR `command >> file`");
         }
-        match self.process_command(operation) {
-            Ok(result) => {
-                let file = std::fs::OpenOptions::new()
-                    .create(true)
-                    .append(count == 2)
-                    .write(true)
-                    .open(file_name);
-                match file {
-                    Ok(mut file) => {
-                        if let Err(_) = file.write_all((format!("{}\n", result)).as_bytes()) {
-                            return Err("Failed to write to file");
-                        }
-                    }
-                    Err(_) => return Err("Failed to open file"),
-                }
-            }
-            Err(err) => return Err(err),
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .write(true)
+            .open(self.path.join(file_name));
+        let file = match file {
+            Ok(file) => file,
+            Err(_) => return Err("Failed to open file"),
+        };
+        self.output_redirect = Some(file);
+        let result = self.process_command(operation);
+        let streamed = std::mem::replace(&mut self.output_streamed, false);
+        let Some(mut file) = self.output_redirect.take() else {
+            return Err("Failed to write to file");
         };
+        let result = result?;
+        if !streamed && file.write_all(format!("{}\n", result).as_bytes()).is_err() {
+            return Err("Failed to write to file");
+        }
         Ok("".to_string())
     }
 
-    fn list_directory(&mut self, command: &str) -> Result<String, &'static str> {
-        if command.trim() != "ls" && command.trim() != "ls -l" {
-            return Err("Invalid ls command. Only `ls` and `ls -l` are supported");
-        }
-        let list_format = command.trim() == "ls -l";
-        if list_format {
-            return self.list_directory_with_args();
-        } else {
-            return self.list_directory_simple();
+    // Writes part of a command's output straight to the `>` file or stdout.
+    // Runs `read` on the command's standard input: the `<` file, or stdin.
+    // The reader is swapped out of `self` while it is used so that output
+    // can still be written through `self`.
+    fn with_standard_input<T>(&mut self, read: impl FnOnce(&mut Self, &mut dyn BufRead) -> T) -> T {
+        match self.input_redirect.take() {
+            Some(file) => {
+                let mut reader = io::BufReader::new(file);
+                let result = read(self, &mut reader);
+                self.input_redirect = Some(reader.into_inner());
+                result
+            }
+            None => {
+                let mut reader =
+                    std::mem::replace(&mut self.reader, io::BufReader::new(io::stdin()));
+                let result = read(self, &mut reader);
+                self.reader = reader;
+                result
+            }
         }
     }
 
-    fn list_directory_simple(&mut self) -> Result<String, &'static str> {
-        let mut result: String = String::new();
-        for entry in std::fs::read_dir(&self.path).unwrap() {
-            let entry = entry.unwrap();
-            result.push_str(&entry.file_name().to_str().unwrap());
-            result.push_str("\t");
+    // Ends a command that wrote its output with `write_output`.
+    fn finish_streaming(&mut self) -> Result<String, &'static str> {
+        if self.output_redirect.is_none() && self.writer.flush().is_err() {
+            return Err("Failed to write output");
         }
-        Ok(result.trim().to_string())
+        self.output_streamed = true;
+        Ok("".to_string())
+    }
+
+    fn write_output(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        self.output_streamed = true;
+        let written = match self.output_redirect.as_mut() {
+            Some(file) => file.write_all(bytes),
+            None => self.writer.write_all(bytes),
+        };
+        written.map_err(|_| "Failed to write output")
     }
 
-    fn list_directory_with_args(&mut self) -> Result<String, &'static str> {
-        let mut result: String = String::new();
-        result.push_str("Type\tMode\tSize\tModification Time\tName\n");
-        for entry in std::fs::read_dir(&self.path).unwrap() {
-            let entry = entry.unwrap();
-            let metadata = entry.metadata().unwrap();
-            let file_name = entry.file_name();
-            let file_name_str = file_name.to_string_lossy();
-            let file_type = if metadata.is_dir() {
-                "d"
-            } else if metadata.is_file() {
-                "f"
+    fn list_directory(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (mut options, mut operands) = ListOptions::parse(&arguments[1..])?;
+        let terminal = self.stdout_is_terminal();
+        // Like `ls`, fall back to one name per line when writing to a pipe
+        // or file, unless `-C` asks for columns explicitly.
+        if options.columns || (terminal && !options.one_per_line) {
+            options.width = Some(self.terminal_width());
+        }
+        if options.color == ColorMode::Always || (options.color == ColorMode::Auto && terminal) {
+            options.colors = Some(LsColors::parse(self.get_variable("LS_COLORS").as_deref()));
+        }
+        if operands.is_empty() {
+            operands.push(".".to_string());
+        }
+        // File operands are listed together first, then each directory.
+        let mut files = Vec::new();
+        let mut directories = Vec::new();
+        for operand in operands.iter() {
+            let path = self.path.join(operand);
+            // A symlink given as an operand is followed, except in long and
+            // `-d` listings, which describe the link itself.
+            // A dangling link can't be followed, so it is listed as a link.
+            let metadata = if options.long || options.directory {
+                std::fs::symlink_metadata(&path)
             } else {
-                "?"
+                std::fs::metadata(&path).or_else(|_| std::fs::symlink_metadata(&path))
             };
-
-            let permissions = metadata.permissions();
-            let mode = permissions.mode();
-
-            // Format permissions using Unix file mode
-            let mode_string = format!("{:04o}", mode & 0o7777);
-
-            let size = metadata.len();
-
-            // Format the DateTime<Utc> to a human-readable string
-            let modification_time = metadata.modified().unwrap();
-            let datetime: DateTime<Utc> = match modification_time.duration_since(UNIX_EPOCH) {
-                Ok(duration) => (UNIX_EPOCH + duration).into(),
+            match metadata {
+                Ok(metadata) => {
+                    let entry = Entry {
+                        name: operand.clone(),
+                        path,
+                        metadata,
+                    };
+                    if entry.metadata.is_dir() && !options.directory {
+                        directories.push(entry);
+                    } else {
+                        files.push(entry);
+                    }
+                }
                 Err(_) => {
-                    return Err("Error while formatting SystemTime to DateTime<Utc>");
+                    self.print_error(&format!(
+                        "ls: cannot access '{}': No such file or directory",
+                        operand
+                    ));
+                    self.exit_status = 2;
                 }
-            };
-            let formatted_time = datetime.format("%Y-%m-%d %H:%M:%S").to_string();
+            }
+        }
+        sort_entries(&mut files, &options);
+        sort_entries(&mut directories, &options);
 
-            // Append the formatted string to the result
-            result.push_str(
-                format!(
-                    "{}\t{}\t{}\t{}\t{}",
-                    file_type, mode_string, size, formatted_time, file_name_str
-                )
-                .as_str(),
-            );
-            result.push_str("\n");
+        let mut sections = Vec::new();
+        if !files.is_empty() {
+            sections.push(self.format_entries(&files, &options, false)?);
+        }
+        let show_headers = operands.len() > 1 || options.recursive;
+        for directory in directories.iter() {
+            self.list_directory_contents(directory, show_headers, &options, &mut sections)?;
         }
-        Ok(result.trim().to_string())
+        Ok(sections.join("\n\n"))
     }
 
-    fn echo(&mut self, command: &str) -> Result<String, &'static str> {
-        if command.trim() == "echo" {
-            return Ok("".to_string());
+    fn list_directory_contents(
+        &mut self,
+        directory: &Entry,
+        show_header: bool,
+        options: &ListOptions,
+        sections: &mut Vec<String>,
+    ) -> Result<(), &'static str> {
+        let read_dir = match std::fs::read_dir(&directory.path) {
+            Ok(read_dir) => read_dir,
+            Err(_) => {
+                self.print_error(&format!("ls: cannot open directory '{}'", directory.name));
+                self.exit_status = 2;
+                return Ok(());
+            }
+        };
+        let mut entries = Vec::new();
+        if options.all {
+            for name in [".", ".."] {
+                let path = directory.path.join(name);
+                if let Ok(metadata) = std::fs::metadata(&path) {
+                    entries.push(Entry {
+                        name: name.to_string(),
+                        path,
+                        metadata,
+                    });
+                }
+            }
         }
-        if !command.starts_with("echo ") {
-            return Err("Invalid echo command. Correct usage: `echo <message>`");
+        for entry in read_dir.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !options.shows(&name) {
+                continue;
+            }
+            if let Ok(metadata) = entry.metadata() {
+                entries.push(Entry {
+                    name,
+                    path: entry.path(),
+                    metadata,
+                });
+            }
         }
-        Ok(command.trim().strip_prefix("echo ").unwrap().to_string())
-    }
+        sort_entries(&mut entries, options);
 
-    fn change_directory(&mut self, command: &str) -> Result<String, &'static str> {
-        let new_directory_path = command.trim().strip_prefix("cd ").unwrap();
-        let new_path = std::path::Path::new(new_directory_path);
-        if !new_path.exists() {
-            return Err("Path does not exist");
+        let mut section = String::new();
+        if show_header {
+            section.push_str(&format!("{}:\n", directory.name));
         }
-        if !new_path.is_dir() {
-            return Err("Path is not a directory");
+        section.push_str(&self.format_entries(&entries, options, true)?);
+        sections.push(section.trim_end().to_string());
+
+        if options.recursive {
+            for entry in entries.iter() {
+                if entry.name == "." || entry.name == ".." || !entry.metadata.is_dir() {
+                    continue;
+                }
+                let subdirectory = Entry {
+                    name: format!("{}/{}", directory.name.trim_end_matches('/'), entry.name),
+                    path: entry.path.clone(),
+                    metadata: entry.metadata.clone(),
+                };
+                self.list_directory_contents(&subdirectory, true, options, sections)?;
+            }
         }
-        self.path = self.path.join(new_path);
-        Ok("".to_string())
+        Ok(())
     }
 
-    fn print_to_stdout(&mut self, output: &str, new_line: bool) {
-        let output = if new_line {
-            format!("{}\n", output)
+    fn format_entries(
+        &mut self,
+        entries: &[Entry],
+        options: &ListOptions,
+        show_total: bool,
+    ) -> Result<String, &'static str> {
+        if options.long {
+            self.list_directory_with_args(entries, options, show_total)
         } else {
-            output.to_string()
-        };
-        if let Err(err) = write!(self.writer, "{}", output) {
-            panic!("Failed to write to stdout: {}", err);
-        }
-        if let Err(err) = self.writer.flush() {
-            panic!("Failed to flush stdout: {}", err);
+            self.list_directory_simple(entries, options)
         }
     }
 
-    fn record_history(&mut self, command: &str) {
-        if command.trim().is_empty() {
-            return;
-        }
-        if self.history.len() == HISTORY_SIZE {
-            self.history.pop_front();
+    fn list_directory_simple(
+        &mut self,
+        entries: &[Entry],
+        options: &ListOptions,
+    ) -> Result<String, &'static str> {
+        let cells: Vec<(String, usize)> = entries
+            .iter()
+            .map(|entry| display_name(entry, options, false))
+            .collect();
+        match options.width {
+            Some(width) => Ok(layout_columns(&cells, width)),
+            None => {
+                let names: Vec<String> = cells.into_iter().map(|(name, _)| name).collect();
+                Ok(names.join("\n"))
+            }
         }
-        self.history.push_back(command.trim().to_string());
     }
 
-    fn history(&mut self) -> Result<String, &'static str> {
-        Ok(self
-            .history
-            .iter()
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>()
-            .join("\n"))
+    fn stdout_is_terminal(&self) -> bool {
+        self.output_redirect.is_none() && io::stdout().is_terminal()
     }
 
-    fn sleep(&mut self, command: &str) -> Result<String, &'static str> {
-        if command.trim() == "sleep" {
-            return Err("correct usage: `sleep <duration>`");
+    // COLUMNS takes precedence over the size reported by the terminal.
+    fn terminal_width(&self) -> usize {
+        if let Some(width) = self
+            .get_variable("COLUMNS")
+            .and_then(|columns| columns.parse::<usize>().ok())
+            .filter(|width| *width > 0)
+        {
+            return width;
         }
-        let duration = command.trim().strip_prefix("sleep ").unwrap();
-        match duration.parse::<u64>() {
-            Ok(value) => std::thread::sleep(std::time::Duration::from_secs(value)),
-            Err(_) => return Err("sleep duration should be a positive integer"),
+        let mut size = libc::winsize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if result == 0 && size.ws_col > 0 {
+            size.ws_col as usize
+        } else {
+            DEFAULT_WIDTH
         }
-        Ok("".to_string())
     }
 
-    fn cat(&mut self, command: &str) -> Result<String, &'static str> {
-        if command.trim() == "cat" {
-            return Err("correct usage: `cat <file>`");
-        }
-        let file_name = command.trim().strip_prefix("cat ").unwrap();
-        let file_path = self.path.join(file_name);
-        let file = std::fs::OpenOptions::new().read(true).open(file_path);
-        match file {
-            Ok(mut file) => {
-                let mut buffer = String::new();
-                if let Err(_) = file.read_to_string(&mut buffer) {
-                    return Err("Failed to read from file");
+    // Lists entries in the POSIX long format:
+    //   drwxr-xr-x 2 user group 4096 Jan  1 12:00 name
+    // preceded by the total size in 1K blocks when listing a directory.
+    fn list_directory_with_args(
+        &mut self,
+        entries: &[Entry],
+        options: &ListOptions,
+        show_total: bool,
+    ) -> Result<String, &'static str> {
+        let now = std::time::SystemTime::now();
+        let mut user_names: HashMap<u32, String> = HashMap::new();
+        let mut group_names: HashMap<u32, String> = HashMap::new();
+        let mut rows: Vec<[String; 6]> = Vec::new();
+        let mut names = Vec::new();
+        let mut total_blocks = 0;
+        for entry in entries {
+            let metadata = &entry.metadata;
+            total_blocks += metadata.blocks();
+            let owner = user_names
+                .entry(metadata.uid())
+                .or_insert_with(|| {
+                    users::user_name(metadata.uid()).unwrap_or(metadata.uid().to_string())
+                })
+                .clone();
+            let group = group_names
+                .entry(metadata.gid())
+                .or_insert_with(|| {
+                    users::group_name(metadata.gid()).unwrap_or(metadata.gid().to_string())
+                })
+                .clone();
+            let file_type = metadata.file_type();
+            let size = size_column(metadata, options.human_readable);
+            let modification_time = match metadata.modified() {
+                Ok(time) => format_time(time, &options.time_style, now),
+                Err(_) => "?".to_string(),
+            };
+            rows.push([
+                mode_string(metadata),
+                metadata.nlink().to_string(),
+                owner,
+                group,
+                size,
+                modification_time,
+            ]);
+            let (mut name, _) = display_name(entry, options, true);
+            if file_type.is_symlink() {
+                if let Ok(target) = std::fs::read_link(&entry.path) {
+                    name.push_str(&format!(" -> {}", target.to_string_lossy()));
                 }
-                Ok(buffer)
             }
-            Err(_) => return Err("Failed to open file"),
+            names.push(name);
         }
+
+        let mut widths = [0; 6];
+        for row in rows.iter() {
+            for (width, column) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(column.chars().count());
+            }
+        }
+        let mut lines = Vec::new();
+        if show_total {
+            // st_blocks counts 512-byte blocks; `ls` reports 1K blocks.
+            lines.push(if options.human_readable {
+                format!("total {}", human_size(total_blocks * 512))
+            } else {
+                format!("total {}", total_blocks.div_ceil(2))
+            });
+        }
+        for (row, name) in rows.iter().zip(names.iter()) {
+            lines.push(format!(
+                "{:<w0$} {:>w1$} {:<w2$} {:<w3$} {:>w4$} {:<w5$} {}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                row[4],
+                row[5],
+                name,
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+                w4 = widths[4],
+                w5 = widths[5],
+            ));
+        }
+        Ok(lines.join("\n"))
     }
 
-    fn rm(&mut self, command: &str, is_directory: bool) -> Result<String, &'static str> {
-        if command.trim() == "rm" || command.trim() == "rmdir" {
-            return Err("correct usage: `rm <file> OR rmdir <directory>`");
+    fn echo(&mut self, command: &str) -> Result<String, &'static str> {
+        if command.trim() == "echo" {
+            return Ok("".to_string());
         }
-        let prefix = if is_directory { "rmdir " } else { "rm " };
-        let file_name = command.trim().strip_prefix(prefix).unwrap();
-        let file_path = self.path.join(file_name);
-        let file = File::open(file_path);
-        match file {
-            Ok(_) => {
-                if file.unwrap().metadata().unwrap().is_dir() && !is_directory {
-                    return Err("rm: cannot remove directory. Use `rmdir` instead");
+        if !command.starts_with("echo ") {
+            return Err("Invalid echo command. Correct usage: `echo <message>`");
+        }
+        let arguments = self.expand_arguments(command)?;
+        // Leading words made only of `n`, `e` and `E` flags are options;
+        // anything else starts the text.
+        let (mut newline, mut escapes) = (true, false);
+        let mut start = 1;
+        for argument in arguments[1..].iter() {
+            let flags = argument.strip_prefix('-').unwrap_or_default();
+            if flags.is_empty() || !flags.chars().all(|c| matches!(c, 'n' | 'e' | 'E')) {
+                break;
+            }
+            for flag in flags.chars() {
+                match flag {
+                    'n' => newline = false,
+                    'e' => escapes = true,
+                    _ => escapes = false,
                 }
-                let file_path = self.path.join(file_name);
-                if is_directory {
-                    std::fs::remove_dir(file_path).unwrap();
-                } else {
-                    std::fs::remove_file(file_path).unwrap();
+            }
+            start += 1;
+        }
+        let text = arguments[start..].join(" ");
+        let mut output = text.clone().into_bytes();
+        if escapes {
+            let (bytes, stop) = printf::unescape(&text);
+            output = bytes;
+            // `\c` also drops the newline.
+            newline &= !stop;
+        }
+        match String::from_utf8(output) {
+            Ok(text) if newline => Ok(text),
+            Ok(text) => {
+                self.write_output(text.as_bytes())?;
+                self.finish_streaming()
+            }
+            Err(err) => {
+                let mut output = err.into_bytes();
+                if newline {
+                    output.push(b'\n');
                 }
+                self.write_output(&output)?;
+                self.finish_streaming()
             }
-            Err(_) => return Err("Failed to open file"),
         }
-        Ok("".to_string())
     }
 
-    fn create_new_file(&mut self, command: &str) -> Result<String, &'static str> {
-        if command.trim() == "touch" {
-            return Err("correct usage: `touch <file>`");
-        }
-        let file_name = command.trim().strip_prefix("touch ").unwrap();
-        if file_name.is_empty() {
-            return Err("Invalid file name. correct usage: `touch <file>`");
+    fn printf(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let mut operands = &arguments[1..];
+        if operands.first().is_some_and(|x| x == "--") {
+            operands = &operands[1..];
         }
-        let file_path = self.path.join(file_name);
-        let file = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(file_path);
-        match file {
-            Ok(_) => Ok("".to_string()),
-            Err(_) => return Err("Failed to create file"),
+        let Some((format, operands)) = operands.split_first() else {
+            return Err("printf: usage: printf format [arguments]");
+        };
+        let mut errors = Vec::new();
+        let output = printf::format(format, operands, &mut errors);
+        for error in errors.iter() {
+            self.print_error(error);
+            self.exit_status = 1;
         }
+        self.write_output(&output)?;
+        self.finish_streaming()
     }
 
-    fn create_new_directory(&mut self, command: &str) -> Result<String, &'static str> {
-        if command.trim() == "mkdir" {
-            return Err("correct usage: `mkdir <directory>`");
-        }
-        let dir_name = command.trim().strip_prefix("mkdir ").unwrap();
-        if dir_name.is_empty() {
-            return Err("Invalid directory name. correct usage: `mkdir <directory>`");
-        }
-        let dir_path = self.path.join(dir_name);
-        let file = File::open(&dir_path);
-        if file.is_ok() {
-            return Err("Directory already exists");
+    fn process_arithmetic_command(&mut self, command: &str) -> Result<String, &'static str> {
+        let expression = &command[2..command.len() - 2];
+        let value = arithmetic::evaluate(expression, self)?;
+        self.exit_status = if value == 0 { 1 } else { 0 };
+        Ok("".to_string())
+    }
+
+    fn let_command(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        if arguments.len() == 1 {
+            return Err("let: expression expected");
         }
-        match std::fs::create_dir(dir_path) {
-            Ok(_) => Ok("".to_string()),
-            Err(_) => return Err("Failed to create directory"),
+        let mut value = 0;
+        for expression in arguments.iter().skip(1) {
+            value = arithmetic::evaluate(expression, self)?;
         }
+        self.exit_status = if value == 0 { 1 } else { 0 };
+        Ok("".to_string())
     }
 
-    fn process_grep_command(&mut self, command: &str) -> Result<String, &'static str> {
-        let parts: Vec<&str> = command.trim().split(' ').collect();
-        if parts.len() != 3 {
-            return Err("Invalid grep command. Correct usage: `grep <pattern> <file>`");
+    fn shopt(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (value, names) = match arguments.get(1).map(|x| x.as_str()) {
+            Some("-s") => (Some(true), &arguments[2..]),
+            Some("-u") => (Some(false), &arguments[2..]),
+            _ => (None, &arguments[1..]),
+        };
+        for name in names {
+            if self.shell_option_mut(name).is_none() {
+                return Err("shopt: invalid shell option name");
+            }
         }
-        let pattern = parts[1];
-        let file_name = parts[2];
-        let file_path = self.path.join(file_name);
-        let file = std::fs::OpenOptions::new().read(true).open(file_path);
-        match file {
-            Ok(mut file) => {
-                let mut buffer = String::new();
-                if let Err(_) = file.read_to_string(&mut buffer) {
-                    return Err("Failed to read from file");
+        let mut lines = Vec::new();
+        if let (Some(value), false) = (value, names.is_empty()) {
+            for name in names {
+                *self.shell_option_mut(name).unwrap() = value;
+            }
+            return Ok("".to_string());
+        }
+        // Without names, `-s`/`-u` list the options that are on/off.
+        let mut all_names = GlobOptions::NAMES.to_vec();
+        all_names.push("saferm");
+        all_names.sort();
+        for name in all_names {
+            let enabled = *self.shell_option_mut(name).unwrap();
+            if (names.is_empty() && value.is_none_or(|value| value == enabled))
+                || names.iter().any(|x| x == name)
+            {
+                if !enabled {
+                    self.exit_status = 1;
                 }
-                let result = self.find_pattern_in_file(pattern, buffer.as_str());
-                Ok(result.join("\n"))
+                lines.push(format!(
+                    "{:<15}\t{}",
+                    name,
+                    if enabled { "on" } else { "off" }
+                ));
             }
-            Err(_) => return Err("Failed to open file"),
         }
+        Ok(lines.join("\n"))
     }
 
-    fn find_pattern_in_file(&mut self, pattern: &str, file: &str) -> Vec<String> {
-        let lines: Vec<&str> = file.split('\n').collect();
-        lines
-            .iter()
-            .filter(|line| line.contains(pattern))
-            .map(|line| line.to_string())
-            .collect()
+    fn shell_option_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "saferm" => Some(&mut self.safe_rm),
+            _ => self.glob_options.option_mut(name),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // Splits a command into words and expands each of them, braces first; the
+    // command name is the first argument.
+    fn expand_arguments(&mut self, command: &str) -> Result<Vec<String>, &'static str> {
+        let mut arguments = Vec::new();
+        for word in split_words(command.trim())? {
+            for word in expand_braces(&word) {
+                arguments.extend(self.expand_word(&word)?);
+            }
+        }
+        Ok(arguments)
+    }
 
-    #[test]
-    fn test_process_command() {
-        let mut emulator = Emulator::new();
-        let result = emulator.process_command("test_input");
-        match result {
-            Ok(_) => panic!("expected error, got Ok"),
-            Err(err) => assert_eq!(err, "mini-shell: command not found"),
+    // Expands a single word into zero or more arguments: parameters and
+    // arithmetic first, then filename globbing on the unquoted wildcards.
+    fn expand_word(&mut self, word: &str) -> Result<Vec<String>, &'static str> {
+        let chars = self.expand_parameters(word)?;
+        let literal: String = chars.iter().map(|(c, _)| *c).collect();
+        if !glob::has_wildcards(&chars) {
+            return Ok(vec![literal]);
+        }
+        let matches = glob::expand(&chars, &self.path, &self.glob_options);
+        if !matches.is_empty() {
+            Ok(matches)
+        } else if self.glob_options.failglob {
+            Err("no match")
+        } else if self.glob_options.nullglob {
+            Ok(Vec::new())
+        } else {
+            Ok(vec![literal])
         }
     }
 
-    #[test]
-    fn test_process_command_pwd() {
-        let mut emulator = Emulator::new();
-        let result = emulator.process_command("pwd");
-        match result {
-            Ok(value) => assert_eq!(value, (emulator.path.to_str().unwrap()).to_string()),
-            Err(_) => panic!("[test_process_command_pwd] expected Ok, got error"),
+    // Removes quotes and expands a leading `~` as well as `$name`, `${name}`,
+    // `$?` and `$((expression))` outside single quotes. Each resulting
+    // character is paired with whether it was quoted.
+    fn expand_parameters(&mut self, word: &str) -> Result<Vec<(char, bool)>, &'static str> {
+        let chars: Vec<char> = word.chars().collect();
+        let mut result = Vec::new();
+        let mut in_double_quotes = false;
+        let mut i = 0;
+        if chars.first() == Some(&'~') {
+            let end = chars.iter().position(|&c| c == '/').unwrap_or(chars.len());
+            let prefix: String = chars[1..end].iter().collect();
+            if let Some(directory) = self.expand_tilde(&prefix) {
+                // The expansion is not subject to globbing, so mark it quoted.
+                result.extend(directory.chars().map(|c| (c, true)));
+                i = end;
+            }
+        }
+        while i < chars.len() {
+            match chars[i] {
+                '\'' if !in_double_quotes => {
+                    i += 1;
+                    while i < chars.len() && chars[i] != '\'' {
+                        result.push((chars[i], true));
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                '"' => {
+                    in_double_quotes = !in_double_quotes;
+                    i += 1;
+                }
+                '\\' if i + 1 < chars.len() => {
+                    // Inside double quotes a backslash only escapes characters
+                    // that would otherwise be special there.
+                    if in_double_quotes && !matches!(chars[i + 1], '$' | '"' | '\\' | '`') {
+                        result.push(('\\', true));
+                    }
+                    result.push((chars[i + 1], true));
+                    i += 2;
+                }
+                '$' => {
+                    let (value, consumed) = self.expand_dollar(&chars[i..])?;
+                    result.extend(value.chars().map(|c| (c, in_double_quotes)));
+                    i += consumed;
+                }
+                c => {
+                    result.push((c, in_double_quotes));
+                    i += 1;
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    // Expands `~`, `~+`, `~-`, `~N` (directory stack entries) and `~user`;
+    // `None` leaves the word unchanged.
+    fn expand_tilde(&self, prefix: &str) -> Option<String> {
+        match prefix {
+            "" => self.get_variable("HOME"),
+            "+" => self.get_variable("PWD"),
+            "-" => self.get_variable("OLDPWD"),
+            index
+                if index
+                    .trim_start_matches(['+', '-'])
+                    .chars()
+                    .all(|c| c.is_ascii_digit()) =>
+            {
+                let index = self.directory_stack_index(index)?;
+                let entry = &self.directory_stack_entries()[index];
+                Some(entry.to_string_lossy().into_owned())
+            }
+            user if user
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) =>
+            {
+                users::home_directory(user).map(|path| path.to_string_lossy().into_owned())
+            }
+            _ => None,
+        }
+    }
+
+    // Expands the `$` expression at the start of `chars`, returning the value
+    // and the number of characters consumed.
+    fn expand_dollar(&mut self, chars: &[char]) -> Result<(String, usize), &'static str> {
+        let is_name_char = |c: &char| c.is_ascii_alphanumeric() || *c == '_';
+        match chars.get(1) {
+            Some('(') if chars.get(2) == Some(&'(') => {
+                let mut depth = 0;
+                for (end, c) in chars.iter().enumerate().skip(1) {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => continue,
+                    }
+                    if depth == 0 {
+                        if chars[end - 1] != ')' {
+                            return Err("syntax error: missing `))`");
+                        }
+                        let expression: String = chars[3..end - 1].iter().collect();
+                        let value = arithmetic::evaluate(&expression, self)?;
+                        return Ok((value.to_string(), end + 1));
+                    }
+                }
+                Err("syntax error: missing `))`")
+            }
+            Some('(') => Err("command substitution is not supported"),
+            Some('{') => match chars.iter().position(|&c| c == '}') {
+                Some(end) => {
+                    let name: String = chars[2..end].iter().collect();
+                    // `${name[N]}` is one element of an array, `${name[@]}`
+                    // all of them.
+                    let (name, index) = match name.strip_suffix(']').and_then(|x| x.split_once('['))
+                    {
+                        Some((name, index)) => (name.to_string(), Some(index.to_string())),
+                        None => (name, None),
+                    };
+                    if name.is_empty() || !name.chars().all(|c| is_name_char(&c)) {
+                        return Err("bad substitution");
+                    }
+                    let value = match index.as_deref() {
+                        None => self.get_variable(&name).unwrap_or_default(),
+                        Some("@" | "*") => match self.arrays.get(&name) {
+                            Some(values) => values.join(" "),
+                            None => self.get_variable(&name).unwrap_or_default(),
+                        },
+                        Some(index) => {
+                            let index: usize = index.parse().map_err(|_| "bad array subscript")?;
+                            match self.arrays.get(&name) {
+                                Some(values) => values.get(index).cloned().unwrap_or_default(),
+                                None if index == 0 => self.get_variable(&name).unwrap_or_default(),
+                                None => String::new(),
+                            }
+                        }
+                    };
+                    Ok((value, end + 1))
+                }
+                None => Err("bad substitution"),
+            },
+            Some('?') => Ok((self.last_exit_status.to_string(), 2)),
+            Some(c) if is_name_char(c) => {
+                let length = chars[1..].iter().take_while(|c| is_name_char(c)).count();
+                let name: String = chars[1..=length].iter().collect();
+                Ok((self.get_variable(&name).unwrap_or_default(), length + 1))
+            }
+            _ => Ok(("$".to_string(), 1)),
+        }
+    }
+
+    fn print_working_directory(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let mut physical = false;
+        for argument in arguments.iter().skip(1) {
+            match argument.as_str() {
+                "-L" => physical = false,
+                "-P" => physical = true,
+                _ => return Err("pwd: invalid option. Correct usage: `pwd [-L|-P]`"),
+            }
+        }
+        if physical {
+            return match std::fs::canonicalize(&self.path) {
+                Ok(path) => Ok(path.to_string_lossy().into_owned()),
+                Err(_) => Err("pwd: cannot resolve current directory"),
+            };
+        }
+        Ok(self.path.to_string_lossy().into_owned())
+    }
+
+    fn change_directory(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        // With `-P` symlinks are resolved; by default (`-L`) `..` simply drops
+        // the previous component of the path as typed.
+        let mut physical = false;
+        let mut operands = &arguments[1..];
+        while let Some(option) = operands.first() {
+            match option.as_str() {
+                "-L" => physical = false,
+                "-P" => physical = true,
+                "--" => {
+                    operands = &operands[1..];
+                    break;
+                }
+                option if option.starts_with('-') && option != "-" => {
+                    return Err("cd: invalid option. Correct usage: `cd [-L|-P] [directory]`");
+                }
+                _ => break,
+            }
+            operands = &operands[1..];
+        }
+        if operands.len() > 1 {
+            return Err("cd: too many arguments");
+        }
+        // `cd -` and directories found through CDPATH print the new directory.
+        let mut print_directory = false;
+        let new_directory_path = match operands.first().map(|x| x.as_str()) {
+            None => match self.get_variable("HOME") {
+                Some(home) if !home.is_empty() => home,
+                _ => return Err("cd: HOME not set"),
+            },
+            Some("-") => match self.get_variable("OLDPWD") {
+                Some(old_path) if !old_path.is_empty() => {
+                    print_directory = true;
+                    old_path
+                }
+                _ => return Err("cd: OLDPWD not set"),
+            },
+            Some(directory) => directory.to_string(),
+        };
+        let (new_path, found_in_cdpath) = self.resolve_directory(&new_directory_path, physical)?;
+        self.set_current_directory(new_path);
+        if print_directory || found_in_cdpath {
+            return Ok(self.path.to_string_lossy().into_owned());
+        }
+        Ok("".to_string())
+    }
+
+    fn pushd(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        if arguments.len() > 2 {
+            return Err("pushd: too many arguments");
+        }
+        match arguments.get(1).map(|x| x.as_str()) {
+            // Without arguments the top two directories are exchanged.
+            None => {
+                if self.directory_stack.is_empty() {
+                    return Err("pushd: no other directory");
+                }
+                let top = std::mem::replace(&mut self.directory_stack[0], self.path.clone());
+                self.set_current_directory(top);
+            }
+            // `+N`/`-N` rotate the stack so that entry N ends up on top.
+            Some(rotation) if rotation.len() > 1 && rotation.starts_with(['+', '-']) => {
+                let index = match self.directory_stack_index(rotation) {
+                    Some(index) => index,
+                    None => return Err("pushd: directory stack index out of range"),
+                };
+                let mut entries = self.directory_stack_entries();
+                entries.rotate_left(index);
+                let top = entries.remove(0);
+                self.directory_stack = entries;
+                self.set_current_directory(top);
+            }
+            Some(directory) => {
+                let (new_path, _) = self.resolve_directory(directory, false)?;
+                self.directory_stack.insert(0, self.path.clone());
+                self.set_current_directory(new_path);
+            }
+        }
+        Ok(self.format_directory_stack())
+    }
+
+    fn popd(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        if arguments.len() > 2 {
+            return Err("popd: too many arguments");
+        }
+        if self.directory_stack.is_empty() {
+            return Err("popd: directory stack empty");
+        }
+        let index = match arguments.get(1) {
+            None => 0,
+            Some(argument) => match self.directory_stack_index(argument) {
+                Some(index) => index,
+                None => return Err("popd: directory stack index out of range"),
+            },
+        };
+        if index == 0 {
+            // A directory that is gone stays on the stack.
+            if !self.directory_stack[0].is_dir() {
+                return Err("popd: directory no longer exists");
+            }
+            let top = self.directory_stack.remove(0);
+            self.set_current_directory(top);
+        } else {
+            self.directory_stack.remove(index - 1);
+        }
+        Ok(self.format_directory_stack())
+    }
+
+    fn dirs(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (mut clear, mut long, mut per_line, mut verbose) = (false, false, false, false);
+        let mut index = None;
+        for argument in arguments.iter().skip(1) {
+            let digits = argument.trim_start_matches(['+', '-']);
+            if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+                match self.directory_stack_index(argument) {
+                    Some(value) => index = Some(value),
+                    None => return Err("dirs: directory stack index out of range"),
+                }
+                continue;
+            }
+            let Some(flags) = argument.strip_prefix('-') else {
+                return Err("dirs: invalid option. Correct usage: `dirs [-clpv] [+N|-N]`");
+            };
+            for flag in flags.chars() {
+                match flag {
+                    'c' => clear = true,
+                    'l' => long = true,
+                    'p' => per_line = true,
+                    'v' => verbose = true,
+                    _ => return Err("dirs: invalid option. Correct usage: `dirs [-clpv] [+N|-N]`"),
+                }
+            }
+        }
+        if clear {
+            self.directory_stack.clear();
+            return Ok("".to_string());
+        }
+        let entries: Vec<String> = self
+            .directory_stack_entries()
+            .iter()
+            .map(|entry| {
+                if long {
+                    entry.to_string_lossy().into_owned()
+                } else {
+                    self.abbreviate_home(entry)
+                }
+            })
+            .collect();
+        if let Some(index) = index {
+            return Ok(entries[index].clone());
+        }
+        if verbose {
+            return Ok(entries
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| format!("{:2}  {}", i, entry))
+                .collect::<Vec<String>>()
+                .join("\n"));
+        }
+        Ok(entries.join(if per_line { "\n" } else { " " }))
+    }
+
+    fn directory_stack_entries(&self) -> Vec<std::path::PathBuf> {
+        let mut entries = vec![self.path.clone()];
+        entries.extend(self.directory_stack.iter().cloned());
+        entries
+    }
+
+    // Converts `+N` (counting from the left of `dirs`, also written `N`) or
+    // `-N` (counting from the right) into an index into the full stack.
+    fn directory_stack_index(&self, argument: &str) -> Option<usize> {
+        let size = self.directory_stack.len() + 1;
+        let (from_right, number) = match argument.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, argument.strip_prefix('+').unwrap_or(argument)),
+        };
+        let number = number.parse::<usize>().ok()?;
+        if number >= size {
+            return None;
+        }
+        Some(if from_right {
+            size - 1 - number
+        } else {
+            number
+        })
+    }
+
+    // The stack as printed by `pushd`, `popd` and `dirs`.
+    fn format_directory_stack(&self) -> String {
+        self.directory_stack_entries()
+            .iter()
+            .map(|entry| self.abbreviate_home(entry))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    fn abbreviate_home(&self, path: &std::path::Path) -> String {
+        if let Some(home) = self.get_variable("HOME").filter(|home| !home.is_empty()) {
+            if let Ok(rest) = path.strip_prefix(&home) {
+                if rest.as_os_str().is_empty() {
+                    return "~".to_string();
+                }
+                return format!("~/{}", rest.to_string_lossy());
+            }
+        }
+        path.to_string_lossy().into_owned()
+    }
+
+    // Finds the directory `cd` would change to, also reporting whether it was
+    // found through CDPATH.
+    fn resolve_directory(
+        &self,
+        directory: &str,
+        physical: bool,
+    ) -> Result<(std::path::PathBuf, bool), &'static str> {
+        let (new_path, found_in_cdpath) = match self.search_cdpath(directory, physical) {
+            Some(path) => (path, true),
+            None => (self.join_directory(directory, physical), false),
+        };
+        if !new_path.exists() {
+            return Err("Path does not exist");
+        }
+        if !new_path.is_dir() {
+            return Err("Path is not a directory");
+        }
+        if !physical {
+            return Ok((new_path, found_in_cdpath));
+        }
+        match std::fs::canonicalize(&new_path) {
+            Ok(path) => Ok((path, found_in_cdpath)),
+            Err(_) => Err("Path does not exist"),
+        }
+    }
+
+    // Looks a relative directory name up in the colon-separated CDPATH. Names
+    // starting with `/`, `.` or `..` are never searched, and an empty CDPATH
+    // entry stands for the current directory, which `cd` tries anyway.
+    fn search_cdpath(&self, directory: &str, physical: bool) -> Option<std::path::PathBuf> {
+        let first = std::path::Path::new(directory).components().next();
+        if directory.starts_with('/')
+            || matches!(
+                first,
+                Some(std::path::Component::CurDir | std::path::Component::ParentDir)
+            )
+        {
+            return None;
+        }
+        let cdpath = self.get_variable("CDPATH")?;
+        cdpath
+            .split(':')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| self.join_directory(&format!("{}/{}", entry, directory), physical))
+            .find(|candidate| candidate.is_dir())
+    }
+
+    // Resolves `path` against the shell's current directory and normalizes it
+    // lexically: `.` components are dropped and `..` removes the component
+    // before it, without looking at symlinks.
+    fn resolve_path(&self, path: &str) -> std::path::PathBuf {
+        let mut resolved = std::path::PathBuf::new();
+        for component in self.path.join(path).components() {
+            match component {
+                std::path::Component::CurDir => {}
+                std::path::Component::ParentDir => {
+                    resolved.pop();
+                }
+                component => resolved.push(component),
+            }
+        }
+        resolved
+    }
+
+    // With `-P` the path is left as typed so that `..` follows symlinks when
+    // it is canonicalized; otherwise it is normalized lexically.
+    fn join_directory(&self, path: &str, physical: bool) -> std::path::PathBuf {
+        match physical {
+            true => self.path.join(path),
+            false => self.resolve_path(path),
+        }
+    }
+
+    fn set_current_directory(&mut self, new_path: std::path::PathBuf) {
+        let old_path = std::mem::replace(&mut self.path, new_path);
+        self.set_variable("OLDPWD", old_path.to_string_lossy().into_owned());
+        let new_path = self.path.to_string_lossy().into_owned();
+        self.set_variable("PWD", new_path);
+        // Keep the process directory in sync so that anything started from the
+        // shell sees the same working directory.
+        let _ignored = std::env::set_current_dir(&self.path);
+    }
+
+    // Reports a problem with one operand of a command that carries on with the
+    // remaining ones; the command's own result is still printed afterwards.
+    fn print_error(&mut self, message: &str) {
+        eprintln!("mini-shell: {}", message);
+    }
+
+    fn print_to_stdout(&mut self, output: &str, new_line: bool) {
+        let output = if new_line {
+            format!("{}\n", output)
+        } else {
+            output.to_string()
+        };
+        if let Err(err) = write!(self.writer, "{}", output) {
+            panic!("Failed to write to stdout: {}", err);
+        }
+        if let Err(err) = self.writer.flush() {
+            panic!("Failed to flush stdout: {}", err);
+        }
+    }
+
+    fn record_history(&mut self, command: &str) {
+        if command.trim().is_empty() {
+            return;
+        }
+        if self.history.len() == HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back(command.trim().to_string());
+    }
+
+    fn history(&mut self) -> Result<String, &'static str> {
+        Ok(self
+            .history
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+            .join("\n"))
+    }
+
+    fn sleep(&mut self, command: &str) -> Result<String, &'static str> {
+        if command.trim() == "sleep" {
+            return Err("correct usage: `sleep <duration>`");
+        }
+        let duration = command.trim().strip_prefix("sleep ").unwrap();
+        match duration.parse::<u64>() {
+            Ok(value) => std::thread::sleep(std::time::Duration::from_secs(value)),
+            Err(_) => return Err("sleep duration should be a positive integer"),
+        }
+        Ok("".to_string())
+    }
+
+    fn cat(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (options, mut operands) = CatOptions::parse(&arguments[1..])?;
+        if operands.is_empty() {
+            operands.push("-".to_string());
+        }
+        let mut line_number = 0;
+        for operand in operands.iter() {
+            if operand == "-" {
+                self.with_standard_input(|emulator, reader| {
+                    emulator.cat_stream(reader, &options, &mut line_number)
+                })?;
+                continue;
+            }
+            let file = match self.open_for_reading(operand) {
+                Ok(file) => file,
+                Err(err) => {
+                    self.print_error(&format!("cat: {}: {}", operand, err));
+                    self.exit_status = 1;
+                    continue;
+                }
+            };
+            let mut reader = io::BufReader::with_capacity(COPY_BUFFER_SIZE, file);
+            if let Err(err) = self.cat_stream(&mut reader, &options, &mut line_number) {
+                self.print_error(&format!("cat: {}: {}", operand, err));
+                self.exit_status = 1;
+            }
+        }
+        self.finish_streaming()
+    }
+
+    fn head(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (options, mut operands) = HeadOptions::parse(&arguments[1..])?;
+        if operands.is_empty() {
+            operands.push("-".to_string());
+        }
+        let headers = options.headers.unwrap_or(operands.len() > 1);
+        let mut first = true;
+        for operand in operands.iter() {
+            let result = if operand == "-" {
+                if headers {
+                    self.write_header("standard input", &mut first)?;
+                }
+                self.with_standard_input(|emulator, reader| {
+                    head::head(reader, options.count, &mut OutputWriter(emulator))
+                })
+            } else {
+                let file = match self.open_for_reading(operand) {
+                    Ok(file) => file,
+                    Err(err) => {
+                        self.print_error(&format!("head: {}: {}", operand, err));
+                        self.exit_status = 1;
+                        continue;
+                    }
+                };
+                if headers {
+                    self.write_header(operand, &mut first)?;
+                }
+                let mut reader = io::BufReader::with_capacity(COPY_BUFFER_SIZE, file);
+                head::head(&mut reader, options.count, &mut OutputWriter(self))
+            };
+            if let Err(err) = result {
+                self.print_error(&format!("head: {}: {}", operand, error_reason(&err)));
+                self.exit_status = 1;
+            }
+        }
+        self.finish_streaming()
+    }
+
+    fn tail(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (options, mut operands) = TailOptions::parse(&arguments[1..])?;
+        if operands.is_empty() {
+            operands.push("-".to_string());
+        }
+        let headers = options.headers.unwrap_or(operands.len() > 1);
+        let mut first = true;
+        // Installed before anything is printed, so that Ctrl-C can't fall
+        // between the initial output and following.
+        let _guard = options.follow.map(|_| InterruptGuard::install());
+        let mut followed = Vec::new();
+        // The followed file whose header was printed last, if any.
+        let mut shown = None;
+        for operand in operands.iter() {
+            let result = if operand == "-" {
+                if headers {
+                    self.write_header("standard input", &mut first)?;
+                    shown = None;
+                }
+                self.with_standard_input(|emulator, reader| {
+                    tail::tail_stream(reader, &options, &mut OutputWriter(emulator))
+                })
+            } else {
+                let mut file = match self.open_for_reading(operand) {
+                    Ok(file) => file,
+                    Err(err) => {
+                        self.print_error(&format!("tail: {}: {}", operand, err));
+                        self.exit_status = 1;
+                        // `-F` waits for the file to appear.
+                        if options.follow == Some(Follow::Name) {
+                            let path = self.path.join(operand);
+                            match Followed::new(operand, path, None) {
+                                Ok(file) => followed.push(file),
+                                Err(err) => self.print_error(&format!(
+                                    "tail: {}: {}",
+                                    operand,
+                                    error_reason(&err)
+                                )),
+                            }
+                        }
+                        continue;
+                    }
+                };
+                if headers {
+                    self.write_header(operand, &mut first)?;
+                    shown = None;
+                }
+                // Only regular files can be read from the end, or followed.
+                match file.metadata().is_ok_and(|metadata| metadata.is_file()) {
+                    true => {
+                        let result = tail::tail_file(&mut file, &options, &mut OutputWriter(self));
+                        match result {
+                            Ok(()) if options.follow.is_some() => {
+                                let path = self.path.join(operand);
+                                Followed::new(operand, path, Some(file)).map(|file| {
+                                    followed.push(file);
+                                    shown = Some(followed.len() - 1);
+                                })
+                            }
+                            result => result,
+                        }
+                    }
+                    false => {
+                        let mut reader = io::BufReader::with_capacity(COPY_BUFFER_SIZE, file);
+                        tail::tail_stream(&mut reader, &options, &mut OutputWriter(self))
+                    }
+                }
+            };
+            if let Err(err) = result {
+                self.print_error(&format!("tail: {}: {}", operand, error_reason(&err)));
+                self.exit_status = 1;
+            }
+        }
+        if !followed.is_empty() {
+            self.follow_files(&mut followed, &options, headers, shown, first)?;
+        }
+        self.finish_streaming()
+    }
+
+    // `tail -f`: prints what gets appended to the files until Ctrl-C.
+    // `shown` is the file whose header was printed last, and `first` whether
+    // no header has been printed yet.
+    fn follow_files(
+        &mut self,
+        followed: &mut [Followed],
+        options: &TailOptions,
+        headers: bool,
+        mut shown: Option<usize>,
+        mut first: bool,
+    ) -> Result<(), &'static str> {
+        let by_name = options.follow == Some(Follow::Name);
+        let (mut data, mut notices) = (Vec::new(), Vec::new());
+        while !follow::interrupted() {
+            for (index, file) in followed.iter_mut().enumerate() {
+                data.clear();
+                notices.clear();
+                if let Err(err) = file.poll(by_name, &mut data, &mut notices) {
+                    notices.push(format!("tail: {}: {}", file.name, error_reason(&err)));
+                }
+                for notice in notices.iter() {
+                    self.print_error(notice);
+                }
+                if data.is_empty() {
+                    continue;
+                }
+                if headers && shown != Some(index) {
+                    self.write_header(&file.name, &mut first)?;
+                    shown = Some(index);
+                }
+                self.write_output(&data)?;
+            }
+            if self.output_redirect.is_none() && self.writer.flush().is_err() {
+                return Err("Failed to write output");
+            }
+            // Sleep in slices so that Ctrl-C is noticed quickly.
+            let mut slept = std::time::Duration::ZERO;
+            while slept < options.sleep_interval && !follow::interrupted() {
+                let nap = FOLLOW_CHECK_INTERVAL.min(options.sleep_interval - slept);
+                std::thread::sleep(nap);
+                slept += nap;
+            }
+        }
+        // Like a command killed by SIGINT.
+        self.exit_status = 130;
+        Ok(())
+    }
+
+    // The `==> name <==` line `head` and `tail` put before each file.
+    fn write_header(&mut self, name: &str, first: &mut bool) -> Result<(), &'static str> {
+        let separator = if *first { "" } else { "\n" };
+        *first = false;
+        self.write_output(format!("{}==> {} <==\n", separator, name).as_bytes())
+    }
+
+    // `test` and `[`: the result is the exit status, 2 for a malformed
+    // expression.
+    fn test(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let mut operands = &arguments[1..];
+        if arguments[0] == "[" {
+            match operands.split_last() {
+                Some((last, rest)) if last == "]" => operands = rest,
+                _ => return Ok(self.test_error("[", "missing `]'")),
+            }
+        }
+        // The operands are plain strings: nothing in them is a pattern.
+        let words: Vec<Word> = operands
+            .iter()
+            .map(|operand| operand.chars().map(|c| (c, true)).collect())
+            .collect();
+        match Condition::evaluate(&words, false, &self.path) {
+            Ok((value, _)) => {
+                self.exit_status = if value { 0 } else { 1 };
+                Ok("".to_string())
+            }
+            Err(message) => Ok(self.test_error(&arguments[0], &message)),
+        }
+    }
+
+    // `[[ ]]`: the words are neither split nor globbed, and what `=~`
+    // matched goes into `BASH_REMATCH`.
+    fn extended_test(&mut self, command: &str) -> Result<String, &'static str> {
+        let words = split_words(command.trim())?;
+        if words.len() < 2 || words[words.len() - 1] != "]]" {
+            return Ok(self.test_error("[[", "missing `]]'"));
+        }
+        let mut expanded = Vec::new();
+        for word in words[1..words.len() - 1].iter() {
+            expanded.push(self.expand_parameters(word)?);
+        }
+        match Condition::evaluate(&expanded, true, &self.path) {
+            Ok((value, matches)) => {
+                if let Some(matches) = matches {
+                    self.arrays.insert("BASH_REMATCH".to_string(), matches);
+                }
+                self.exit_status = if value { 0 } else { 1 };
+                Ok("".to_string())
+            }
+            Err(message) => Ok(self.test_error("[[", &message)),
+        }
+    }
+
+    fn test_error(&mut self, command: &str, message: &str) -> String {
+        self.print_error(&format!("{}: {}", command, message));
+        self.exit_status = 2;
+        "".to_string()
+    }
+
+    fn find(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let base = self.path.clone();
+        let (options, roots) = FindOptions::parse(&arguments[1..], &base)?;
+        let mut visitor = FindVisitor(self);
+        for root in roots.iter() {
+            options.walk(root, &base, &mut visitor);
+        }
+        self.finish_streaming()
+    }
+
+    fn wc(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (options, operands) = WcOptions::parse(&arguments[1..])?;
+        // Standard input without an operand is counted but not named.
+        let inputs = match operands.is_empty() {
+            true => vec![("-".to_string(), String::new())],
+            false => operands.iter().map(|x| (x.clone(), x.clone())).collect(),
+        };
+        let mut rows = Vec::new();
+        let mut total = Counts::default();
+        for (operand, name) in inputs.iter() {
+            let counts = if operand == "-" {
+                self.with_standard_input(|_, reader| Counts::count(reader))
+            } else {
+                match self.open_for_reading(operand) {
+                    Ok(file) => {
+                        Counts::count(&mut io::BufReader::with_capacity(COPY_BUFFER_SIZE, file))
+                    }
+                    Err(err) => {
+                        self.print_error(&format!("wc: {}: {}", operand, err));
+                        self.exit_status = 1;
+                        continue;
+                    }
+                }
+            };
+            match counts {
+                Ok(counts) => {
+                    total.add(&counts);
+                    rows.push((options.columns(&counts), name.as_str()));
+                }
+                Err(err) => {
+                    self.print_error(&format!("wc: {}: {}", operand, error_reason(&err)));
+                    self.exit_status = 1;
+                }
+            }
+        }
+        if inputs.len() > 1 {
+            rows.push((options.columns(&total), "total"));
+        }
+        // Columns are as wide as the largest count; when reading a pipe its
+        // size isn't known beforehand, so `wc` leaves room for it.
+        let columns = options.columns(&total);
+        let mut width = columns.iter().max().map_or(1, |x| x.to_string().len());
+        if columns.len() > 1 && inputs.iter().any(|(operand, _)| operand == "-") {
+            width = width.max(7);
+        }
+        let lines: Vec<String> = rows
+            .iter()
+            .map(|(columns, name)| {
+                let mut line = columns
+                    .iter()
+                    .map(|count| format!("{:>width$}", count))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if !name.is_empty() {
+                    line.push(' ');
+                    line.push_str(name);
+                }
+                line
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    // Copies the input to the output unchanged, or line by line when lines
+    // have to be numbered or marked.
+    fn cat_stream(
+        &mut self,
+        reader: &mut dyn BufRead,
+        options: &CatOptions,
+        line_number: &mut usize,
+    ) -> Result<(), &'static str> {
+        let mut buffer = Vec::with_capacity(COPY_BUFFER_SIZE);
+        let mut at_line_start = true;
+        loop {
+            buffer.clear();
+            let length = match reader.fill_buf() {
+                Ok([]) => return Ok(()),
+                Ok(chunk) if options.decorates_lines() => {
+                    options.format_chunk(chunk, &mut at_line_start, line_number, &mut buffer);
+                    chunk.len()
+                }
+                Ok(chunk) => {
+                    buffer.extend_from_slice(chunk);
+                    chunk.len()
+                }
+                Err(_) => return Err("Failed to read from file"),
+            };
+            reader.consume(length);
+            self.write_output(&buffer)?;
+        }
+    }
+
+    fn rm(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (options, operands) = RemoveOptions::parse(&arguments[1..])?;
+        if operands.is_empty() {
+            if options.force {
+                return Ok("".to_string());
+            }
+            return Err("rm: missing operand");
+        }
+        let home = self
+            .get_variable("HOME")
+            .and_then(|home| std::fs::canonicalize(home).ok());
+        let mut output = Vec::new();
+        for operand in operands.iter() {
+            let file_path = self.path.join(operand);
+            // The operand itself is removed: a symlink is never followed, so
+            // dangling links and links to directories go through `rm`.
+            let metadata = match std::fs::symlink_metadata(&file_path) {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    if !options.force {
+                        self.print_error(&format!(
+                            "rm: cannot remove '{}': No such file or directory",
+                            operand
+                        ));
+                        self.exit_status = 1;
+                    }
+                    continue;
+                }
+            };
+            if metadata.is_dir() {
+                let name = operand.trim_end_matches('/');
+                let refusal = if !options.recursive {
+                    Some(format!("rm: cannot remove '{}': Is a directory", operand))
+                } else if name.ends_with("/.")
+                    || name.ends_with("/..")
+                    || name == "."
+                    || name == ".."
+                {
+                    Some(format!(
+                        "rm: refusing to remove '.' or '..' directory: skipping '{}'",
+                        operand
+                    ))
+                } else if options.preserve_root
+                    && std::fs::canonicalize(&file_path)
+                        .is_ok_and(|path| path == std::path::Path::new("/"))
+                {
+                    Some(format!(
+                        "rm: it is dangerous to operate recursively on '{}'",
+                        operand
+                    ))
+                } else if options.preserve_root
+                    && home.is_some()
+                    && std::fs::canonicalize(&file_path).ok() == home
+                {
+                    Some(format!(
+                        "rm: refusing to remove the home directory '{}'",
+                        operand
+                    ))
+                } else {
+                    None
+                };
+                if let Some(refusal) = refusal {
+                    self.print_error(&refusal);
+                    self.exit_status = 1;
+                    continue;
+                }
+            }
+            let removed = if self.safe_rm {
+                self.move_to_trash(&file_path, operand, &metadata, &options, &mut output)?
+            } else {
+                self.remove_path(&file_path, operand, &metadata, &options, &mut output)
+            };
+            if !removed {
+                self.exit_status = 1;
+            }
+        }
+        Ok(output.join("\n"))
+    }
+
+    // The `saferm` version of `remove_path`: the whole operand goes to the
+    // trash in one piece.
+    fn move_to_trash(
+        &mut self,
+        path: &std::path::Path,
+        name: &str,
+        metadata: &std::fs::Metadata,
+        options: &RemoveOptions,
+        output: &mut Vec<String>,
+    ) -> Result<bool, &'static str> {
+        let trash = self.trash_can()?;
+        if options.interactive
+            && !self.confirm(&format!("rm: remove {} '{}'?", file_kind(metadata), name))
+        {
+            return Ok(false);
+        }
+        // Record a clean absolute path, without `.` or `..` in it.
+        let original_path = match (path.parent().map(std::fs::canonicalize), path.file_name()) {
+            (Some(Ok(parent)), Some(file_name)) => parent.join(file_name),
+            _ => path.to_path_buf(),
+        };
+        match trash.put(&original_path) {
+            Ok(_) => {
+                if options.verbose {
+                    output.push(format!("trashed '{}'", name));
+                }
+                Ok(true)
+            }
+            Err(err) => {
+                self.print_error(&format!(
+                    "rm: cannot move '{}' to trash: {}",
+                    name,
+                    error_reason(&err)
+                ));
+                Ok(false)
+            }
+        }
+    }
+
+    fn trash_can(&self) -> Result<Trash, &'static str> {
+        match self.get_variable("HOME").filter(|home| !home.is_empty()) {
+            Some(home) => Ok(Trash::new(
+                &home,
+                self.get_variable("XDG_DATA_HOME").as_deref(),
+            )),
+            None => Err("trash: HOME not set"),
+        }
+    }
+
+    fn trash(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let trash = self.trash_can()?;
+        match (arguments.get(1).map(|x| x.as_str()), arguments.len()) {
+            (Some("list"), 2) => {
+                let entries = trash.list().map_err(|_| "trash: cannot read the trash")?;
+                let lines: Vec<String> = entries
+                    .iter()
+                    .map(|entry| {
+                        format!(
+                            "{}  {}  {}",
+                            entry.deletion_date.replacen('T', " ", 1),
+                            entry.name,
+                            entry.original_path.display()
+                        )
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            // Without a name, undo the last removal.
+            (Some("restore"), 2..) => {
+                let names = match arguments.get(2) {
+                    Some(_) => arguments[2..].to_vec(),
+                    None => match trash.list() {
+                        Ok(entries) if !entries.is_empty() => {
+                            vec![entries[entries.len() - 1].name.clone()]
+                        }
+                        _ => return Err("trash: nothing to restore"),
+                    },
+                };
+                let mut output = Vec::new();
+                for name in names {
+                    match trash.restore(&name) {
+                        Ok(path) => output.push(format!("restored '{}'", path.display())),
+                        Err(err) => {
+                            self.print_error(&err);
+                            self.exit_status = 1;
+                        }
+                    }
+                }
+                Ok(output.join("\n"))
+            }
+            (Some("empty"), 2) => match trash.empty() {
+                Ok(()) => Ok("".to_string()),
+                Err(_) => Err("trash: cannot empty the trash"),
+            },
+            _ => Err("trash: usage: trash list | trash restore [name...] | trash empty"),
+        }
+    }
+
+    // Removes a file, or a directory and everything in it. Returns `false`
+    // if anything could not be removed (or was kept at a prompt).
+    fn remove_path(
+        &mut self,
+        path: &std::path::Path,
+        name: &str,
+        metadata: &std::fs::Metadata,
+        options: &RemoveOptions,
+        output: &mut Vec<String>,
+    ) -> bool {
+        if !metadata.is_dir() {
+            if options.interactive
+                && !self.confirm(&format!("rm: remove {} '{}'?", file_kind(metadata), name))
+            {
+                return false;
+            }
+            if let Err(err) = std::fs::remove_file(path) {
+                self.print_error(&format!(
+                    "rm: cannot remove '{}': {}",
+                    name,
+                    error_reason(&err)
+                ));
+                return false;
+            }
+            if options.verbose {
+                output.push(format!("removed '{}'", name));
+            }
+            return true;
+        }
+
+        if options.interactive && !self.confirm(&format!("rm: descend into directory '{}'?", name))
+        {
+            return false;
+        }
+        let mut removed_all = true;
+        match std::fs::read_dir(path) {
+            Ok(read_dir) => {
+                let mut entries: Vec<_> = read_dir.filter_map(|entry| entry.ok()).collect();
+                entries.sort_by_key(|entry| entry.file_name());
+                for entry in entries {
+                    let entry_name = format!(
+                        "{}/{}",
+                        name.trim_end_matches('/'),
+                        entry.file_name().to_string_lossy()
+                    );
+                    match std::fs::symlink_metadata(entry.path()) {
+                        Ok(entry_metadata) => {
+                            removed_all &= self.remove_path(
+                                &entry.path(),
+                                &entry_name,
+                                &entry_metadata,
+                                options,
+                                output,
+                            );
+                        }
+                        Err(_) => removed_all = false,
+                    }
+                }
+            }
+            Err(err) => {
+                self.print_error(&format!(
+                    "rm: cannot remove '{}': {}",
+                    name,
+                    error_reason(&err)
+                ));
+                return false;
+            }
+        }
+        // A directory whose contents were kept can't be removed either.
+        if !removed_all {
+            return false;
+        }
+        if options.interactive && !self.confirm(&format!("rm: remove directory '{}'?", name)) {
+            return false;
+        }
+        if let Err(err) = std::fs::remove_dir(path) {
+            self.print_error(&format!(
+                "rm: cannot remove '{}': {}",
+                name,
+                error_reason(&err)
+            ));
+            return false;
+        }
+        if options.verbose {
+            output.push(format!("removed directory '{}'", name));
+        }
+        true
+    }
+
+    // Asks a yes/no question on stderr and reads the answer from the
+    // command's standard input.
+    fn confirm(&mut self, question: &str) -> bool {
+        eprint!("{} ", question);
+        let answer = self.read_input_line().unwrap_or_default();
+        answer.trim_start().starts_with(['y', 'Y'])
+    }
+
+    // Reads one line from the `<` file, or from stdin.
+    fn read_input_line(&mut self) -> Option<String> {
+        let mut line = Vec::new();
+        match self.input_redirect.as_mut() {
+            Some(file) => {
+                // Byte by byte, so that the rest stays for the next question.
+                let mut byte = [0];
+                while let Ok(1) = file.read(&mut byte) {
+                    if byte[0] == b'\n' {
+                        break;
+                    }
+                    line.push(byte[0]);
+                }
+                if line.is_empty() && byte[0] != b'\n' {
+                    return None;
+                }
+            }
+            None => match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {}
+            },
+        }
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    fn rmdir(&mut self, command: &str) -> Result<String, &'static str> {
+        if command.trim() == "rmdir" {
+            return Err("correct usage: `rmdir <directory>`");
+        }
+        let arguments = self.expand_arguments(command)?;
+        for file_name in arguments.iter().skip(1) {
+            let file_path = self.path.join(file_name);
+            let failure =
+                |reason: &str| format!("rmdir: failed to remove '{}': {}", file_name, reason);
+            let metadata = match std::fs::symlink_metadata(&file_path) {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    self.print_error(&failure("No such file or directory"));
+                    self.exit_status = 1;
+                    continue;
+                }
+            };
+            if !metadata.is_dir() {
+                self.print_error(&failure("Not a directory"));
+                self.exit_status = 1;
+                continue;
+            }
+            if self.safe_rm {
+                let is_empty = std::fs::read_dir(&file_path)
+                    .is_ok_and(|mut read_dir| read_dir.next().is_none());
+                if !is_empty {
+                    self.print_error(&failure("Directory not empty"));
+                    self.exit_status = 1;
+                    continue;
+                }
+                let options = RemoveOptions::parse(&[]).unwrap().0;
+                if !self.move_to_trash(
+                    &file_path,
+                    file_name,
+                    &metadata,
+                    &options,
+                    &mut Vec::new(),
+                )? {
+                    self.exit_status = 1;
+                }
+                continue;
+            }
+            if let Err(err) = std::fs::remove_dir(file_path) {
+                self.print_error(&failure(&error_reason(&err)));
+                self.exit_status = 1;
+            }
+        }
+        Ok("".to_string())
+    }
+
+    fn create_new_file(&mut self, command: &str) -> Result<String, &'static str> {
+        if command.trim() == "touch" {
+            return Err("correct usage: `touch <file>`");
+        }
+        let arguments = self.expand_arguments(command)?;
+        let (options, operands) = TouchOptions::parse(&arguments[1..])?;
+        if operands.is_empty() {
+            return Err("Invalid file name. correct usage: `touch <file>`");
+        }
+        let timespec = |tv_sec, tv_nsec| libc::timespec { tv_sec, tv_nsec };
+        let (mut access, mut modification) = match &options.time {
+            None => {
+                let now = timespec(0, libc::UTIME_NOW);
+                (now, now)
+            }
+            Some(TimeSource::At(time)) => {
+                let time = timespec(time.timestamp(), time.timestamp_subsec_nanos() as i64);
+                (time, time)
+            }
+            Some(TimeSource::Reference(name)) => match std::fs::metadata(self.path.join(name)) {
+                Ok(metadata) => (
+                    timespec(metadata.atime(), metadata.atime_nsec()),
+                    timespec(metadata.mtime(), metadata.mtime_nsec()),
+                ),
+                Err(_) => return Err("touch: failed to get attributes of the reference file"),
+            },
+        };
+        if !options.sets_access() {
+            access = timespec(0, libc::UTIME_OMIT);
+        }
+        if !options.sets_modification() {
+            modification = timespec(0, libc::UTIME_OMIT);
+        }
+        for file_name in operands.iter() {
+            let file_path = self.path.join(file_name);
+            // Opening an existing FIFO for writing would wait for a reader,
+            // so existing files only get their timestamps updated.
+            if !file_path.exists() {
+                if options.no_create {
+                    continue;
+                }
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .write(true)
+                    .open(&file_path);
+                if let Err(err) = file {
+                    self.print_error(&format!(
+                        "touch: cannot touch '{}': {}",
+                        file_name,
+                        error_reason(&err)
+                    ));
+                    self.exit_status = 1;
+                    continue;
+                }
+            }
+            let c_path = match CString::new(file_path.as_os_str().as_bytes()) {
+                Ok(c_path) => c_path,
+                Err(_) => return Err("Invalid file name"),
+            };
+            let times = [access, modification];
+            let status =
+                unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), 0) };
+            if status != 0 {
+                self.print_error(&format!(
+                    "touch: setting times of '{}': {}",
+                    file_name,
+                    error_reason(&io::Error::last_os_error())
+                ));
+                self.exit_status = 1;
+            }
+        }
+        Ok("".to_string())
+    }
+
+    fn create_new_directory(&mut self, command: &str) -> Result<String, &'static str> {
+        if command.trim() == "mkdir" {
+            return Err("correct usage: `mkdir <directory>`");
+        }
+        let arguments = self.expand_arguments(command)?;
+        let (options, operands) = MakeDirectoryOptions::parse(&arguments[1..])?;
+        if operands.is_empty() {
+            return Err("Invalid directory name. correct usage: `mkdir <directory>`");
+        }
+        let mut output = Vec::new();
+        for dir_name in operands.iter() {
+            // With `-p` every missing ancestor is created as well, but only
+            // the operand itself gets the `-m` mode.
+            let mut targets = Vec::new();
+            if options.parents {
+                let mut ancestor = std::path::PathBuf::new();
+                for component in std::path::Path::new(dir_name).components() {
+                    ancestor.push(component);
+                    targets.push(ancestor.clone());
+                }
+            } else {
+                targets.push(std::path::PathBuf::from(dir_name));
+            }
+            let last = targets.len() - 1;
+            for (index, target) in targets.iter().enumerate() {
+                let dir_path = self.path.join(target);
+                if options.parents && dir_path.is_dir() {
+                    continue;
+                }
+                if let Err(err) = std::fs::create_dir(&dir_path) {
+                    let reason = match err.kind() {
+                        io::ErrorKind::AlreadyExists => "File exists".to_string(),
+                        _ => error_reason(&err),
+                    };
+                    self.print_error(&format!(
+                        "mkdir: cannot create directory '{}': {}",
+                        target.display(),
+                        reason
+                    ));
+                    self.exit_status = 1;
+                    break;
+                }
+                if let (Some(mode), true) = (options.mode, index == last) {
+                    let permissions = std::os::unix::fs::PermissionsExt::from_mode(mode);
+                    if std::fs::set_permissions(&dir_path, permissions).is_err() {
+                        self.print_error(&format!(
+                            "mkdir: cannot set permissions of '{}'",
+                            target.display()
+                        ));
+                        self.exit_status = 1;
+                    }
+                }
+                if options.verbose {
+                    output.push(format!("mkdir: created directory '{}'", target.display()));
+                }
+            }
+        }
+        Ok(output.join("\n"))
+    }
+
+    // Pairs each source operand with the name it is copied, moved or linked
+    // to: inside the last operand if that is a directory, otherwise the last
+    // operand itself. A lone `ln` operand is linked into the current
+    // directory.
+    fn file_destinations(
+        &self,
+        command: FileCommand,
+        operands: &[String],
+    ) -> Result<Vec<(String, String)>, &'static str> {
+        let (sources, target) = match operands {
+            [] => return Err(command.missing_operand()),
+            [_] if command == FileCommand::Link => (operands, "."),
+            [_] => return Err(command.missing_destination()),
+            [sources @ .., target] => (sources, target.as_str()),
+        };
+        if !self.path.join(target).is_dir() {
+            if sources.len() > 1 {
+                return Err(command.not_a_directory());
+            }
+            return Ok(vec![(sources[0].clone(), target.to_string())]);
+        }
+        let directory = match target.trim_end_matches('/') {
+            "." if operands.len() == 1 => String::new(),
+            directory => format!("{}/", directory),
+        };
+        Ok(sources
+            .iter()
+            .map(|source| {
+                let name = std::path::Path::new(source)
+                    .file_name()
+                    .map_or(source.clone(), |name| name.to_string_lossy().into_owned());
+                (source.clone(), format!("{}{}", directory, name))
+            })
+            .collect())
+    }
+
+    fn copy_or_move(&mut self, command: &str, kind: FileCommand) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (options, operands) = FileOptions::parse(kind, &arguments[1..])?;
+        let name = kind.name();
+        let mut output = Vec::new();
+        for (source, destination) in self.file_destinations(kind, &operands)? {
+            let source_path = self.path.join(&source);
+            let destination_path = self.path.join(&destination);
+            // `cp` copies what a link points to unless copying recursively;
+            // `mv` always moves the link itself.
+            let metadata = match kind == FileCommand::Copy && !options.recursive {
+                true => std::fs::metadata(&source_path),
+                false => std::fs::symlink_metadata(&source_path),
+            };
+            let metadata = match metadata {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    self.print_error(&format!(
+                        "{}: cannot stat '{}': {}",
+                        name,
+                        source,
+                        error_reason(&err)
+                    ));
+                    self.exit_status = 1;
+                    continue;
+                }
+            };
+            let existing = std::fs::symlink_metadata(&destination_path).ok();
+            // Compared by inode, so that two hard links to one file are
+            // caught too; the destination is looked at the way the source is.
+            let same_file = match kind == FileCommand::Copy && !options.recursive {
+                true => std::fs::metadata(&destination_path).ok(),
+                false => existing.clone(),
+            }
+            .is_some_and(|x| (x.dev(), x.ino()) == (metadata.dev(), metadata.ino()));
+            let canonical_source = std::fs::canonicalize(&source_path).ok();
+            let canonical_destination = match destination_path.parent().map(std::fs::canonicalize) {
+                Some(Ok(parent)) => destination_path
+                    .file_name()
+                    .map(|file_name| parent.join(file_name)),
+                _ => None,
+            };
+            let error = if kind == FileCommand::Copy && metadata.is_dir() && !options.recursive {
+                Some(format!(
+                    "cp: -r not specified; omitting directory '{}'",
+                    source
+                ))
+            } else if same_file {
+                Some(format!(
+                    "{}: '{}' and '{}' are the same file",
+                    name, source, destination
+                ))
+            } else if metadata.is_dir()
+                && canonical_source
+                    .as_ref()
+                    .zip(canonical_destination.as_ref())
+                    .is_some_and(|(source, destination)| destination.starts_with(source))
+            {
+                Some(format!(
+                    "{}: cannot {} '{}' into itself, '{}'",
+                    name,
+                    if kind == FileCommand::Copy {
+                        "copy a directory"
+                    } else {
+                        "move"
+                    },
+                    source,
+                    destination
+                ))
+            } else {
+                match &existing {
+                    Some(existing) if existing.is_dir() && !metadata.is_dir() => Some(format!(
+                        "{}: cannot overwrite directory '{}' with non-directory",
+                        name, destination
+                    )),
+                    Some(existing) if !existing.is_dir() && metadata.is_dir() => Some(format!(
+                        "{}: cannot overwrite non-directory '{}' with directory '{}'",
+                        name, destination, source
+                    )),
+                    _ => None,
+                }
+            };
+            if let Some(error) = error {
+                self.print_error(&error);
+                self.exit_status = 1;
+                continue;
+            }
+            if let Some(existing) = &existing {
+                if options.no_clobber
+                    || (options.interactive
+                        && !existing.is_dir()
+                        && !self.confirm(&format!("{}: overwrite '{}'?", name, destination)))
+                {
+                    continue;
+                }
+            }
+
+            let result = if kind == FileCommand::Move {
+                move_path(&source_path, &destination_path)
+            } else {
+                // A link or FIFO can't be written over like a regular file.
+                let replaced = match &existing {
+                    Some(existing) if !existing.is_dir() && !metadata.is_file() => {
+                        std::fs::remove_file(&destination_path)
+                    }
+                    _ => Ok(()),
+                };
+                let source_path = match options.recursive {
+                    true => Ok(source_path),
+                    false => std::fs::canonicalize(&source_path),
+                };
+                replaced.and(source_path).and_then(|source_path| {
+                    copy_tree(&source_path, &destination_path, options.preserve)
+                })
+            };
+            match result {
+                Ok(()) if options.verbose => output.push(match kind {
+                    FileCommand::Move => format!("renamed '{}' -> '{}'", source, destination),
+                    _ => format!("'{}' -> '{}'", source, destination),
+                }),
+                Ok(()) => {}
+                Err(err) => {
+                    self.print_error(&format!(
+                        "{}: cannot {} '{}' to '{}': {}",
+                        name,
+                        if kind == FileCommand::Copy {
+                            "copy"
+                        } else {
+                            "move"
+                        },
+                        source,
+                        destination,
+                        error_reason(&err)
+                    ));
+                    self.exit_status = 1;
+                }
+            }
+        }
+        Ok(output.join("\n"))
+    }
+
+    fn link(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (options, operands) = FileOptions::parse(FileCommand::Link, &arguments[1..])?;
+        let kind = if options.symbolic { "symbolic" } else { "hard" };
+        let mut output = Vec::new();
+        for (source, destination) in self.file_destinations(FileCommand::Link, &operands)? {
+            let destination_path = self.path.join(&destination);
+            if let Ok(existing) = std::fs::symlink_metadata(&destination_path) {
+                let removed = match (options.force, existing.is_dir()) {
+                    (true, false) => std::fs::remove_file(&destination_path).is_ok(),
+                    _ => false,
+                };
+                if !removed {
+                    self.print_error(&format!(
+                        "ln: failed to create {} link '{}': File exists",
+                        kind, destination
+                    ));
+                    self.exit_status = 1;
+                    continue;
+                }
+            }
+            // A symbolic link stores the operand as written, relative to the
+            // link's own directory.
+            let result = if options.symbolic {
+                std::os::unix::fs::symlink(&source, &destination_path)
+            } else {
+                let source_path = self.path.join(&source);
+                match std::fs::symlink_metadata(&source_path) {
+                    Ok(metadata) if metadata.is_dir() => {
+                        self.print_error(&format!(
+                            "ln: '{}': hard link not allowed for directory",
+                            source
+                        ));
+                        self.exit_status = 1;
+                        continue;
+                    }
+                    _ => std::fs::hard_link(&source_path, &destination_path),
+                }
+            };
+            match result {
+                Ok(()) if options.verbose => output.push(format!(
+                    "'{}' {} '{}'",
+                    destination,
+                    if options.symbolic { "->" } else { "=>" },
+                    source
+                )),
+                Ok(()) => {}
+                Err(err) => {
+                    self.print_error(&format!(
+                        "ln: failed to create {} link '{}': {}",
+                        kind,
+                        destination,
+                        error_reason(&err)
+                    ));
+                    self.exit_status = 1;
+                }
+            }
+        }
+        Ok(output.join("\n"))
+    }
+
+    fn process_grep_command(&mut self, command: &str) -> Result<String, &'static str> {
+        let arguments = self.expand_arguments(command)?;
+        let (mut options, mut operands) = GrepOptions::parse(&arguments[1..])?;
+        options.colored = options.color == ColorMode::Always
+            || (options.color == ColorMode::Auto && self.stdout_is_terminal());
+        let regex = options.regex()?;
+        if operands.is_empty() {
+            // `grep -r` without files searches the shell's directory, showing
+            // names relative to it.
+            operands.push(if options.recursive { "" } else { "-" }.to_string());
+        }
+        let mut sources = Vec::new();
+        let mut errors = Vec::new();
+        let mut recursed = false;
+        let mut stdin = Vec::new();
+        for operand in operands.iter() {
+            if operand == "-" {
+                stdin = self.read_standard_input()?;
+                sources.push(grep::Source {
+                    name: "(standard input)".to_string(),
+                    input: grep::Input::Stdin,
+                });
+                continue;
+            }
+            let path = self.path.join(operand);
+            if options.recursive && path.is_dir() {
+                recursed = true;
+                grep::collect_files(&path, operand, &options, &mut sources, &mut errors);
+                continue;
+            }
+            match self.open_for_reading(operand) {
+                Ok(file) => sources.push(grep::Source {
+                    name: operand.clone(),
+                    input: grep::Input::File(file),
+                }),
+                Err(err) => errors.push(format!("grep: {}: {}", operand, err)),
+            }
+        }
+        let with_filename = options
+            .with_filename
+            .unwrap_or(operands.len() > 1 || recursed);
+
+        let mut failed = !errors.is_empty();
+        for error in errors.iter() {
+            self.print_error(error);
+        }
+        let names: Vec<String> = sources.iter().map(|source| source.name.clone()).collect();
+        let mut writer = OutputWriter(self);
+        let results = grep::search_all(
+            sources,
+            &stdin,
+            with_filename,
+            &regex,
+            &options,
+            &mut writer,
+        )
+        .map_err(|_| "Failed to write output")?;
+        let mut matched = false;
+        for (name, result) in names.iter().zip(results) {
+            if let Some(err) = result.error {
+                self.print_error(&format!("grep: {}: {}", name, err));
+                failed = true;
+            }
+            matched |= result.matched;
+        }
+        // 0 if a line was selected, 1 if none was, 2 on errors unless `-q`
+        // already found a match.
+        self.exit_status = match (matched, failed) {
+            (true, _) if options.quiet => 0,
+            (_, true) => 2,
+            (true, false) => 0,
+            (false, false) => 1,
+        };
+        self.finish_streaming()
+    }
+}
+
+// Lets the streaming commands write through `write_output`.
+struct OutputWriter<'a>(&'a mut Emulator);
+
+// Carries out the actions of `find` on each file it reaches.
+struct FindVisitor<'a>(&'a mut Emulator);
+
+impl find::Visitor for FindVisitor<'_> {
+    fn act(&mut self, action: &find::Action, entry: &find::Entry) -> bool {
+        match action {
+            find::Action::Print => self
+                .0
+                .write_output(format!("{}\n", entry.path).as_bytes())
+                .is_ok(),
+            find::Action::Print0 => self
+                .0
+                .write_output(format!("{}\0", entry.path).as_bytes())
+                .is_ok(),
+            find::Action::Delete => {
+                // The starting directory itself is never removed.
+                if entry.depth == 0 && entry.path == "." {
+                    return true;
+                }
+                let removed = match entry.metadata.is_dir() {
+                    true => std::fs::remove_dir(&entry.full_path),
+                    false => std::fs::remove_file(&entry.full_path),
+                };
+                match removed {
+                    Ok(()) => true,
+                    Err(err) => {
+                        self.error(format!(
+                            "find: cannot delete '{}': {}",
+                            entry.path,
+                            error_reason(&err)
+                        ));
+                        false
+                    }
+                }
+            }
+            find::Action::Exec(words) => {
+                // The file name is quoted wherever it goes, so that it is
+                // passed as is.
+                let quoted = entry.path.replace('\'', "'\\''");
+                let mut command = words[0].replace("{}", &format!("'{}'", quoted));
+                for word in words[1..].iter() {
+                    let word = format!("'{}'", word.replace('\'', "'\\''"));
+                    command.push(' ');
+                    command.push_str(&word.replace("{}", &quoted));
+                }
+                // The command's status is the action's value; `find` itself
+                // only fails on its own errors.
+                let status = std::mem::replace(&mut self.0.exit_status, 0);
+                let streamed = std::mem::replace(&mut self.0.output_streamed, false);
+                let result = self.0.execute(&command);
+                let succeeded = match result {
+                    // Commands like `rm` that print nothing leave no blank
+                    // line behind.
+                    Ok(mut output) if !self.0.output_streamed && !output.is_empty() => {
+                        if !output.ends_with('\n') {
+                            output.push('\n');
+                        }
+                        self.0.write_output(output.as_bytes()).is_ok()
+                    }
+                    Ok(_) => true,
+                    Err(err) => {
+                        self.0.print_error(err);
+                        false
+                    }
+                };
+                let succeeded = succeeded && self.0.exit_status == 0;
+                self.0.exit_status = status;
+                self.0.output_streamed = streamed;
+                succeeded
+            }
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.0.print_error(&message);
+        self.0.exit_status = 1;
+    }
+}
+
+impl Write for OutputWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.write_output(bytes).map_err(io::Error::other)?;
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The reason part of an error message, like `strerror` without the code.
+fn error_reason(err: &io::Error) -> String {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied => "Permission denied".to_string(),
+        io::ErrorKind::NotFound => "No such file or directory".to_string(),
+        io::ErrorKind::DirectoryNotEmpty => "Directory not empty".to_string(),
+        _ => err.to_string(),
+    }
+}
+
+impl Variables for Emulator {
+    fn get_variable(&self, name: &str) -> Option<String> {
+        // An array used as a plain variable is its first element.
+        match self.variables.get(name) {
+            Some(value) => Some(value.clone()),
+            None => match self.arrays.get(name) {
+                Some(values) => Some(values.first().cloned().unwrap_or_default()),
+                None => std::env::var(name).ok(),
+            },
+        }
+    }
+
+    fn set_variable(&mut self, name: &str, value: String) {
+        self.variables.insert(name.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `cd` also moves the process, which the tests share. Tests that change
+    // directory hold this lock and the directory is put back when it drops.
+    static PROCESS_DIRECTORY: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    struct ProcessDirectoryGuard {
+        original: std::path::PathBuf,
+        _lock: std::sync::MutexGuard<'static, ()>,
+    }
+
+    impl ProcessDirectoryGuard {
+        fn acquire() -> ProcessDirectoryGuard {
+            let lock = PROCESS_DIRECTORY
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            ProcessDirectoryGuard {
+                original: std::env::current_dir().unwrap(),
+                _lock: lock,
+            }
+        }
+    }
+
+    impl Drop for ProcessDirectoryGuard {
+        fn drop(&mut self) {
+            let _ignored = std::env::set_current_dir(&self.original);
+        }
+    }
+
+    #[test]
+    fn test_process_command() {
+        let mut emulator = Emulator::new();
+        let result = emulator.process_command("test_input");
+        match result {
+            Ok(_) => panic!("expected error, got Ok"),
+            Err(err) => assert_eq!(err, "mini-shell: command not found"),
+        }
+    }
+
+    #[test]
+    fn test_process_command_pwd() {
+        let mut emulator = Emulator::new();
+        let result = emulator.process_command("pwd");
+        match result {
+            Ok(value) => assert_eq!(value, (emulator.path.to_str().unwrap()).to_string()),
+            Err(_) => panic!("[test_process_command_pwd] expected Ok, got error"),
+        }
+    }
+
+    #[test]
+    fn test_process_command_ls() {
+        use tempfile::tempdir;
+        // `ls` prints one line, for the file.
+        // `ls -l` starts with the POSIX `total N` line before the file.
+        let test_cases = [("ls", 1), ("ls -l", 2)];
+
+        for (input, expected) in test_cases.iter() {
+            // create a temp directory and add a file
+            let temp_dir = tempdir().unwrap();
+            // Create a file within the temporary directory
+            let file_path = temp_dir.path().join("sample.txt");
+            std::fs::File::create(&file_path).unwrap();
+            // create an emulator and set the path to the temp directory
+            let mut emulator = Emulator::new();
+            emulator.path = temp_dir.path().to_path_buf();
+
+            match emulator.process_command(input) {
+                Ok(value) => {
+                    let lines: Vec<&str> = value.split('\n').collect();
+                    assert_eq!(lines.len(), *expected);
+                }
+                Err(_) => panic!("[test_process_command_ls] expected Ok, got error"),
+            }
+            temp_dir.close().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_process_command_ls_options() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        std::fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        for (name, size) in [
+            ("b.txt", 10),
+            ("a.txt", 30),
+            (".hidden", 20),
+            ("dir/c.txt", 0),
+        ] {
+            std::fs::write(temp_dir.path().join(name), "x".repeat(size)).unwrap();
+        }
+
+        // The default layout depends on whether stdout is a terminal, so
+        // columns are requested explicitly with `-C`.
+        emulator.set_variable("COLUMNS", "80".to_string());
+        let test_cases = [
+            ("ls -C", "a.txt  b.txt  dir"),
+            ("ls -1A", ".hidden\na.txt\nb.txt\ndir"),
+            ("ls -Ca", ".  ..  .hidden  a.txt  b.txt  dir"),
+            ("ls -CS *.txt", "a.txt  b.txt"),
+            ("ls -CSr *.txt", "b.txt  a.txt"),
+            ("ls -d dir", "dir"),
+            ("ls -1 dir b.txt", "b.txt\n\ndir:\nc.txt"),
+            ("ls -CR", ".:\na.txt  b.txt  dir\n\n./dir:\nc.txt"),
+            ("ls -1F", "a.txt\nb.txt\ndir/"),
+            ("ls -1 --color=never dir", "c.txt"),
+            ("ls -1d --color=always dir", "\x1b[01;34mdir\x1b[0m"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.process_command(input) {
+                Ok(value) => assert_eq!(value, *expected, "{}", input),
+                Err(err) => panic!("[test_process_command_ls_options] expected Ok, got {}", err),
+            }
+        }
+
+        emulator.set_variable("COLUMNS", "12".to_string());
+        match emulator.process_command("ls -C") {
+            Ok(value) => assert_eq!(value, "a.txt  dir\nb.txt"),
+            Err(err) => panic!("[test_process_command_ls_options] expected Ok, got {}", err),
+        }
+
+        let _ignored = emulator.run_command("ls missing");
+        assert_eq!(emulator.last_exit_status, 2);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_ls_long_format() {
+        use std::os::unix::fs::PermissionsExt;
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        let file_path = temp_dir.path().join("run.sh");
+        std::fs::write(&file_path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o4755)).unwrap();
+        std::os::unix::fs::symlink("run.sh", temp_dir.path().join("link")).unwrap();
+
+        match emulator.process_command("ls -l --time-style=+%Y") {
+            Ok(value) => {
+                let lines: Vec<&str> = value.split('\n').collect();
+                assert_eq!(lines.len(), 3);
+                assert!(lines[0].starts_with("total "));
+                assert!(lines[1].starts_with("lrwxrwxrwx 1 "));
+                assert!(lines[1].ends_with("link -> run.sh"));
+                assert!(lines[2].starts_with("-rwsr-xr-x 1 "));
+                let year = chrono::Local::now().format("%Y").to_string();
+                assert!(lines[2].ends_with(&format!(" 10 {} run.sh", year)));
+            }
+            Err(err) => panic!(
+                "[test_process_command_ls_long_format] expected Ok, got {}",
+                err
+            ),
+        }
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_echo() {
+        let test_cases = [
+            ("echo hello", "hello"),
+            ("echo", ""),
+            ("echo -e 'a\\tb\\x41\\u00e9'", "a\tbA\u{e9}"),
+            ("echo -E 'a\\tb'", "a\\tb"),
+            ("echo -eE 'a\\tb'", "a\\tb"),
+            ("echo -x -e", "-x -e"),
+        ];
+
+        let mut emulator = Emulator::new();
+
+        for (input, expected) in test_cases.iter() {
+            match emulator.process_command(input) {
+                Ok(value) => assert_eq!(value, *expected),
+                Err(_) => panic!("[test_process_command_echo] expected Ok, got error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_command_echo_n_and_printf() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+
+        let test_cases = [
+            ("echo -n hello", "hello"),
+            ("echo -e 'a\\nb\\c ignored'", "a\nb"),
+            ("printf '%s=%d\\n' a 1 b 2", "a=1\nb=2\n"),
+            ("printf '[%-4s|%5.1f|%#o]' ab 2.25 8", "[ab  |  2.2|010]"),
+            ("printf '%x %e %c%%' 255 1500 yes", "ff 1.500000e+03 y%"),
+            ("printf -- '%b|%q' 'x\\ty' \"it's\"", "x\ty|it\\'s"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            let command = format!("{} > out.txt", input);
+            match emulator.run_command(&command) {
+                Ok(value) => assert_eq!(value, ""),
+                Err(err) => panic!(
+                    "[test_process_command_echo_n_and_printf] expected Ok, got {}",
+                    err
+                ),
+            }
+            let output = std::fs::read_to_string(temp_dir.path().join("out.txt")).unwrap();
+            assert_eq!(output, *expected, "{}", input);
+        }
+
+        assert!(emulator.run_command("printf '%d' x > out.txt").is_ok());
+        assert_eq!(emulator.last_exit_status, 1);
+        assert!(emulator.run_command("printf").is_err());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_arithmetic() {
+        let mut emulator = Emulator::new();
+        let test_cases = [
+            ("let i=4 'j = i * 2'", ""),
+            ("((i += 1))", ""),
+            ("echo $((i ** 2)) $(( j % 3 )) \"$((i > j))\"", "25 2 0"),
+            ("echo $i '$i'", "5 $i"),
+        ];
+
+        for (input, expected) in test_cases.iter() {
+            match emulator.run_command(input) {
+                Ok(value) => assert_eq!(value, *expected),
+                Err(err) => panic!("[test_process_command_arithmetic] expected Ok, got {}", err),
+            }
+        }
+
+        let _ignored = emulator.run_command("((i - 5))");
+        assert_eq!(emulator.last_exit_status, 1);
+        match emulator.run_command("echo $((i / 0))") {
+            Ok(_) => panic!("expected error, got Ok"),
+            Err(err) => assert_eq!(err, "division by zero"),
+        }
+    }
+
+    #[test]
+    fn test_process_command_glob() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        for name in ["b.log", "a.log", ".c.log", "notes.txt"] {
+            std::fs::File::create(temp_dir.path().join(name)).unwrap();
+        }
+
+        let test_cases = [
+            ("echo *.log", "a.log b.log"),
+            ("echo '*.log' \\*.log", "*.log *.log"),
+            ("echo ?.md", "?.md"),
+            ("shopt -s nullglob dotglob", ""),
+            ("echo *.log ?.md", ".c.log a.log b.log"),
+            ("rm *.log", ""),
+            ("echo *", "notes.txt"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.process_command(input) {
+                Ok(value) => assert_eq!(value, *expected),
+                Err(err) => panic!("[test_process_command_glob] expected Ok, got {}", err),
+            }
+        }
+
+        let _ignored = emulator.process_command("shopt -s failglob");
+        match emulator.process_command("echo *.md") {
+            Ok(_) => panic!("expected error, got Ok"),
+            Err(err) => assert_eq!(err, "no match"),
+        }
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_brace_expansion() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+
+        let test_cases = [
+            ("touch file{1..3}.txt", ""),
+            ("echo file*.txt", "file1.txt file2.txt file3.txt"),
+            ("echo {a,b}$((1 + 1)) '{c,d}'", "a2 b2 {c,d}"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.process_command(input) {
+                Ok(value) => assert_eq!(value, *expected),
+                Err(err) => panic!(
+                    "[test_process_command_brace_expansion] expected Ok, got {}",
+                    err
+                ),
+            }
+        }
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_cd() {
+        use tempfile::tempdir;
+        let _guard = ProcessDirectoryGuard::acquire();
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+
+        let result = emulator.process_command("cd /tmp");
+        match result {
+            Ok(_) => assert_eq!(emulator.path, std::path::PathBuf::from("/tmp")),
+            Err(_) => panic!("[test_process_command_cd] expected Ok, got error"),
         }
+        // Commands started from the shell see the same directory.
+        assert_eq!(
+            std::env::current_dir().unwrap(),
+            std::fs::canonicalize("/tmp").unwrap()
+        );
+        temp_dir.close().unwrap();
     }
 
     #[test]
-    fn test_process_command_ls() {
+    fn test_process_command_cd_home_oldpwd_and_cdpath() {
         use tempfile::tempdir;
-        // one line for output + one line for new line
-        // `ls -l` adds an extra line for the header.
-        let test_cases = [("ls", 1), ("ls -l", 2)];
+        let _guard = ProcessDirectoryGuard::acquire();
+        let temp_dir = tempdir().unwrap();
+        let home = temp_dir.path().join("home");
+        let projects = temp_dir.path().join("projects");
+        std::fs::create_dir_all(home.join("notes")).unwrap();
+        std::fs::create_dir_all(projects.join("shell")).unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        emulator.set_variable("HOME", home.to_string_lossy().into_owned());
+        emulator.set_variable("CDPATH", projects.to_string_lossy().into_owned());
 
-        for (input, expected) in test_cases.iter() {
-            // create a temp directory and add a file
-            let temp_dir = tempdir().unwrap();
-            // Create a file within the temporary directory
-            let file_path = temp_dir.path().join("sample.txt");
-            std::fs::File::create(&file_path).unwrap();
-            // create an emulator and set the path to the temp directory
-            let mut emulator = Emulator::new();
-            emulator.path = temp_dir.path().to_path_buf();
+        match emulator.process_command("cd") {
+            Ok(value) => assert_eq!(value, ""),
+            Err(err) => panic!("[test_process_command_cd_home] expected Ok, got {}", err),
+        }
+        assert_eq!(emulator.path, home);
 
-            match emulator.process_command(&input) {
-                Ok(value) => {
-                    let lines: Vec<&str> = value.split('\n').collect();
-                    assert_eq!(lines.len(), *expected);
-                }
-                Err(_) => panic!("[test_process_command_ls] expected Ok, got error"),
-            }
-            temp_dir.close().unwrap();
+        match emulator.process_command("cd ~/notes") {
+            Ok(_) => assert_eq!(emulator.path, home.join("notes")),
+            Err(err) => panic!("[test_process_command_cd_home] expected Ok, got {}", err),
         }
+        assert_eq!(
+            emulator.get_variable("OLDPWD"),
+            Some(home.to_string_lossy().into_owned())
+        );
+
+        match emulator.process_command("cd -") {
+            Ok(value) => assert_eq!(value, home.to_string_lossy()),
+            Err(err) => panic!("[test_process_command_cd_home] expected Ok, got {}", err),
+        }
+
+        match emulator.process_command("cd shell") {
+            Ok(value) => assert_eq!(value, projects.join("shell").to_string_lossy()),
+            Err(err) => panic!("[test_process_command_cd_home] expected Ok, got {}", err),
+        }
+        assert_eq!(
+            emulator.get_variable("PWD"),
+            Some(projects.join("shell").to_string_lossy().into_owned())
+        );
+
+        match emulator.process_command("echo ~ '~' ~+") {
+            Ok(value) => assert_eq!(
+                value,
+                format!("{} ~ {}", home.display(), projects.join("shell").display())
+            ),
+            Err(err) => panic!("[test_process_command_cd_home] expected Ok, got {}", err),
+        }
+        temp_dir.close().unwrap();
     }
 
     #[test]
-    fn test_process_command_echo() {
-        let test_cases = [("echo hello", "hello"), ("echo", "")];
-
+    fn test_process_command_cd_relative_and_physical() {
+        use tempfile::tempdir;
+        let _guard = ProcessDirectoryGuard::acquire();
+        let temp_dir = tempdir().unwrap();
+        let real = temp_dir.path().join("real");
+        std::fs::create_dir_all(real.join("sub")).unwrap();
+        std::os::unix::fs::symlink(&real, temp_dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(real.join("sub"), temp_dir.path().join("deep")).unwrap();
         let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        let physical_root = std::fs::canonicalize(temp_dir.path()).unwrap();
 
+        let test_cases = [
+            ("cd link/sub", temp_dir.path().join("link/sub")),
+            ("cd ..", temp_dir.path().join("link")),
+            ("cd ./sub/../sub/.", temp_dir.path().join("link/sub")),
+            ("cd -P .", physical_root.join("real/sub")),
+            ("cd ../../deep/..", physical_root.clone()),
+            ("cd -P deep/..", physical_root.join("real")),
+            ("cd sub", physical_root.join("real/sub")),
+        ];
         for (input, expected) in test_cases.iter() {
-            match emulator.process_command(&input) {
-                Ok(value) => assert_eq!(value, *expected),
-                Err(_) => panic!("[test_process_command_echo] expected Ok, got error"),
+            match emulator.process_command(input) {
+                Ok(_) => assert_eq!(emulator.path, *expected, "{}", input),
+                Err(err) => panic!(
+                    "[test_process_command_cd_relative] expected Ok, got {}",
+                    err
+                ),
             }
         }
+
+        let _ignored = emulator.process_command("cd ../../link");
+        match emulator.process_command("pwd -P") {
+            Ok(value) => assert_eq!(value, physical_root.join("real").to_string_lossy()),
+            Err(err) => panic!(
+                "[test_process_command_cd_relative] expected Ok, got {}",
+                err
+            ),
+        }
+        match emulator.process_command("pwd") {
+            Ok(value) => assert_eq!(value, temp_dir.path().join("link").to_string_lossy()),
+            Err(err) => panic!(
+                "[test_process_command_cd_relative] expected Ok, got {}",
+                err
+            ),
+        }
+        assert!(emulator.process_command("cd missing").is_err());
+        temp_dir.close().unwrap();
     }
 
     #[test]
-    fn test_process_command_cd() {
+    fn test_process_command_directory_stack() {
         use tempfile::tempdir;
+        let _guard = ProcessDirectoryGuard::acquire();
         let temp_dir = tempdir().unwrap();
+        for name in ["a", "b", "c"] {
+            std::fs::create_dir(temp_dir.path().join(name)).unwrap();
+        }
         let mut emulator = Emulator::new();
         emulator.path = temp_dir.path().to_path_buf();
+        emulator.set_variable("HOME", temp_dir.path().to_string_lossy().into_owned());
 
-        let result = emulator.process_command("cd /tmp");
-        match result {
-            Ok(_) => assert_eq!(emulator.path, std::path::PathBuf::from("/tmp")),
-            Err(_) => panic!("[test_process_command_cd] expected Ok, got error"),
+        let test_cases = [
+            ("pushd a", "~/a ~"),
+            ("pushd ../b", "~/b ~/a ~"),
+            ("pushd ~/c", "~/c ~/b ~/a ~"),
+            ("dirs -v", " 0  ~/c\n 1  ~/b\n 2  ~/a\n 3  ~"),
+            ("echo ~2 ~-0", "{root}/a {root}"),
+            ("pushd +2", "~/a ~ ~/c ~/b"),
+            ("pushd", "~ ~/a ~/c ~/b"),
+            ("popd +1", "~ ~/c ~/b"),
+            ("popd", "~/c ~/b"),
+            ("dirs -l +1", "{root}/b"),
+        ];
+        let root = temp_dir.path().to_string_lossy().into_owned();
+        for (input, expected) in test_cases.iter() {
+            match emulator.process_command(input) {
+                Ok(value) => assert_eq!(value, expected.replace("{root}", &root), "{}", input),
+                Err(err) => panic!(
+                    "[test_process_command_directory_stack] expected Ok, got {}",
+                    err
+                ),
+            }
+        }
+        assert_eq!(emulator.path, temp_dir.path().join("c"));
+
+        // A directory that no longer exists is not popped.
+        std::fs::remove_dir(temp_dir.path().join("b")).unwrap();
+        match emulator.process_command("popd") {
+            Ok(_) => panic!("expected error, got Ok"),
+            Err(err) => assert_eq!(err, "popd: directory no longer exists"),
+        }
+        assert_eq!(emulator.process_command("dirs"), Ok("~/c ~/b".to_string()));
+
+        let _ignored = emulator.process_command("dirs -c");
+        match emulator.process_command("popd") {
+            Ok(_) => panic!("expected error, got Ok"),
+            Err(err) => assert_eq!(err, "popd: directory stack empty"),
         }
         temp_dir.close().unwrap();
     }
@@ -538,21 +3154,74 @@ mod tests {
         let file_path = temp_dir.path().join("sample.txt");
         let file = std::fs::OpenOptions::new()
             .create(true)
+            .truncate(true)
             .write(true)
             .open(&file_path);
         match file {
             Ok(mut file) => {
-                if let Err(_) = file.write_all("hello".as_bytes()) {
+                if file.write_all("hello".as_bytes()).is_err() {
                     panic!("Failed to write to file");
                 }
             }
             Err(_) => panic!("Failed to open file"),
         }
 
-        match emulator.process_command("cat sample.txt") {
-            Ok(value) => assert_eq!(value, "hello"),
+        match emulator.process_command("cat sample.txt > out.txt") {
+            Ok(value) => assert_eq!(value, ""),
             Err(_) => panic!("[test_process_command_cat] expected Ok, got error"),
         }
+        assert_eq!(
+            std::fs::read(temp_dir.path().join("out.txt")).unwrap(),
+            b"hello"
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_cat_options() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        std::fs::write(temp_dir.path().join("a.txt"), "one\n\ttwo\n").unwrap();
+        std::fs::write(temp_dir.path().join("b.bin"), b"\xff\x00\xfe\n\n").unwrap();
+        // Larger than the copy buffer, with no trailing newline.
+        let large: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        std::fs::write(temp_dir.path().join("large.bin"), &large).unwrap();
+
+        let test_cases: [(&str, &[u8]); 6] = [
+            ("cat a.txt b.bin", b"one\n\ttwo\n\xff\x00\xfe\n\n"),
+            ("cat - a.txt < b.bin", b"\xff\x00\xfe\n\none\n\ttwo\n"),
+            (
+                "cat -n a.txt b.bin",
+                b"     1\tone\n     2\t\ttwo\n     3\t\xff\x00\xfe\n     4\t\n",
+            ),
+            (
+                "cat -b b.bin a.txt",
+                b"     1\t\xff\x00\xfe\n\n     2\tone\n     3\t\ttwo\n",
+            ),
+            ("cat -A a.txt b.bin", b"one$\n^Itwo$\nM-^?^@M-~$\n$\n"),
+            ("cat large.bin", &large),
+        ];
+        for (input, expected) in test_cases.iter() {
+            let command = format!("{} > out", input);
+            match emulator.process_command(&command) {
+                Ok(value) => assert_eq!(value, ""),
+                Err(err) => panic!(
+                    "[test_process_command_cat_options] expected Ok, got {}",
+                    err
+                ),
+            }
+            let output = std::fs::read(temp_dir.path().join("out")).unwrap();
+            assert!(output == *expected, "{}", input);
+        }
+
+        let _ignored = emulator.run_command("cat missing.txt a.txt > out");
+        assert_eq!(emulator.last_exit_status, 1);
+        assert_eq!(
+            std::fs::read(temp_dir.path().join("out")).unwrap(),
+            b"one\n\ttwo\n"
+        );
         temp_dir.close().unwrap();
     }
 
@@ -567,6 +3236,7 @@ mod tests {
         let file_path = temp_dir.path().join(file_name);
         std::fs::OpenOptions::new()
             .create(true)
+            .truncate(true)
             .write(true)
             .open(&file_path)
             .unwrap();
@@ -582,6 +3252,115 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_process_command_rm_options() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        for name in [
+            "a.txt",
+            "b.txt",
+            "tree/x.txt",
+            "tree/sub/y.txt",
+            "keep/k.txt",
+        ] {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "x").unwrap();
+        }
+        std::fs::write(temp_dir.path().join("answers"), "y\nn\n").unwrap();
+
+        let test_cases = [
+            ("rm -v a.txt b.txt", "removed 'a.txt'\nremoved 'b.txt'"),
+            ("rm -f missing.txt", ""),
+            (
+                "rm -rv tree",
+                "removed 'tree/sub/y.txt'\nremoved directory 'tree/sub'\n\
+                 removed 'tree/x.txt'\nremoved directory 'tree'",
+            ),
+            // `y` to descending, `n` to the only file, so the directory stays.
+            ("rm -ri keep < answers", ""),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.run_command(input) {
+                Ok(value) => assert_eq!(value, *expected, "{}", input),
+                Err(err) => panic!("[test_process_command_rm_options] expected Ok, got {}", err),
+            }
+        }
+        assert!(!temp_dir.path().join("tree").exists());
+        assert!(temp_dir.path().join("keep/k.txt").exists());
+        assert_eq!(emulator.last_exit_status, 1);
+
+        let refused = [
+            "rm keep",
+            "rm missing.txt",
+            "rm -rf /",
+            "rm -rf ~",
+            "rm -rf .",
+        ];
+        emulator.set_variable(
+            "HOME",
+            temp_dir.path().join("keep").to_string_lossy().into_owned(),
+        );
+        for input in refused.iter() {
+            let _ignored = emulator.run_command(input);
+            assert_eq!(emulator.last_exit_status, 1, "{}", input);
+        }
+        assert!(temp_dir.path().join("keep/k.txt").exists());
+        assert!(emulator.process_command("rm").is_err());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_trash() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        emulator.set_variable(
+            "XDG_DATA_HOME",
+            temp_dir.path().join("data").to_string_lossy().into_owned(),
+        );
+        emulator.set_variable("HOME", temp_dir.path().to_string_lossy().into_owned());
+        std::fs::create_dir_all(temp_dir.path().join("dir/empty")).unwrap();
+        std::fs::write(temp_dir.path().join("dir/a.txt"), "a").unwrap();
+
+        let test_cases = [
+            ("shopt -s saferm", ""),
+            ("rm -v dir/a.txt", "trashed 'dir/a.txt'"),
+            ("rmdir dir/empty", ""),
+            (
+                "trash restore",
+                &format!("restored '{}'", temp_dir.path().join("dir/empty").display()),
+            ),
+            ("rm -r dir", ""),
+            (
+                "trash restore dir",
+                &format!("restored '{}'", temp_dir.path().join("dir").display()),
+            ),
+            (
+                "trash restore a.txt",
+                &format!("restored '{}'", temp_dir.path().join("dir/a.txt").display()),
+            ),
+            ("rm dir/a.txt", ""),
+            ("trash empty", ""),
+            ("trash list", ""),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.run_command(input) {
+                Ok(value) => assert_eq!(value, *expected, "{}", input),
+                Err(err) => panic!("[test_process_command_trash] expected Ok, got {}", err),
+            }
+        }
+        assert!(temp_dir.path().join("dir/empty").is_dir());
+        assert!(!temp_dir.path().join("dir/a.txt").exists());
+        assert!(emulator.process_command("trash restore a.txt").is_ok());
+        assert_eq!(emulator.exit_status, 1);
+        assert!(emulator.process_command("trash").is_err());
+        temp_dir.close().unwrap();
+    }
+
     #[test]
     fn test_process_command_rmdir() {
         use tempfile::tempdir;
@@ -602,6 +3381,72 @@ mod tests {
             Ok(value) => assert!(!value.contains(dir_name)),
             Err(_) => panic!("[test_process_command_rmdir] expected Ok, got error"),
         }
+        // A bad operand doesn't stop the others from being removed.
+        std::fs::create_dir_all(temp_dir.path().join("full/sub")).unwrap();
+        std::fs::create_dir(temp_dir.path().join("empty")).unwrap();
+        assert!(emulator.run_command("rmdir missing full empty").is_ok());
+        assert_eq!(emulator.last_exit_status, 1);
+        assert!(temp_dir.path().join("full").is_dir());
+        assert!(!temp_dir.path().join("empty").exists());
+    }
+
+    #[test]
+    fn test_process_command_special_files() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        std::fs::write(temp_dir.path().join("target.txt"), "x\n").unwrap();
+        std::fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        std::os::unix::fs::symlink("target.txt", temp_dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("dir", temp_dir.path().join("dir_link")).unwrap();
+        std::os::unix::fs::symlink("missing", temp_dir.path().join("dangling")).unwrap();
+        let fifo = CString::new(temp_dir.path().join("fifo").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+        let _socket = std::os::unix::net::UnixListener::bind(temp_dir.path().join("socket"));
+
+        match emulator.process_command("ls -l") {
+            Ok(value) => {
+                let types: Vec<char> = value
+                    .lines()
+                    .skip(1)
+                    .filter_map(|line| line.chars().next())
+                    .collect();
+                assert_eq!(types, vec!['l', 'd', 'l', 'p', 'l', 's', '-']);
+            }
+            Err(err) => panic!(
+                "[test_process_command_special_files] expected Ok, got {}",
+                err
+            ),
+        }
+
+        // None of these may block on the FIFO.
+        let test_cases = [
+            ("ls -1 dangling", "dangling"),
+            ("cat fifo", ""),
+            ("grep x fifo", ""),
+            ("touch fifo", ""),
+            ("rm dangling", ""),
+            ("rm link", ""),
+            ("rm dir_link", ""),
+            ("rm fifo", ""),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.process_command(input) {
+                Ok(value) => assert_eq!(value, *expected, "{}", input),
+                Err(err) => panic!(
+                    "[test_process_command_special_files] expected Ok, got {}",
+                    err
+                ),
+            }
+        }
+        assert!(temp_dir.path().join("target.txt").exists());
+        assert!(temp_dir.path().join("dir").is_dir());
+        assert!(emulator.run_command("rmdir target.txt").is_ok());
+        assert_eq!(emulator.last_exit_status, 1);
+        assert!(emulator.run_command("mkdir socket").is_ok());
+        assert_eq!(emulator.last_exit_status, 1);
+        temp_dir.close().unwrap();
     }
 
     #[test]
@@ -623,6 +3468,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_process_command_touch_options() {
+        use chrono::TimeZone;
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        let times = |name: &str| {
+            let metadata = std::fs::metadata(temp_dir.path().join(name)).unwrap();
+            (metadata.atime(), metadata.mtime())
+        };
+
+        let commands = [
+            "touch a.txt b.txt",
+            "touch -d 2001-02-03T04:05:06Z a.txt",
+            "touch -t 200001010000 b.txt",
+            "touch -a -d @1000 b.txt",
+            "touch -c missing.txt",
+            "touch -r a.txt c.txt",
+            "touch -m -r b.txt a.txt",
+        ];
+        for command in commands.iter() {
+            match emulator.run_command(command) {
+                Ok(value) => assert_eq!(value, "", "{}", command),
+                Err(err) => panic!(
+                    "[test_process_command_touch_options] expected Ok, got {}",
+                    err
+                ),
+            }
+        }
+        let y2k = chrono::Local
+            .with_ymd_and_hms(2000, 1, 1, 0, 0, 0)
+            .unwrap()
+            .timestamp();
+        assert_eq!(times("a.txt"), (981173106, y2k));
+        assert_eq!(times("b.txt"), (1000, y2k));
+        assert_eq!(times("c.txt"), (981173106, 981173106));
+        assert!(!temp_dir.path().join("missing.txt").exists());
+
+        // Without a timestamp, an existing file gets the current time.
+        let _ignored = emulator.run_command("touch a.txt");
+        assert!(times("a.txt").1 > y2k);
+        assert!(emulator
+            .process_command("touch -d tomorrowish a.txt")
+            .is_err());
+        assert!(emulator
+            .process_command("touch -r missing.txt a.txt")
+            .is_err());
+        temp_dir.close().unwrap();
+    }
+
     #[test]
     fn test_process_command_mkdir() {
         use tempfile::tempdir;
@@ -642,6 +3538,395 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_process_command_mkdir_options() {
+        use std::os::unix::fs::PermissionsExt;
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+
+        let test_cases = [
+            (
+                "mkdir -pv a/b c",
+                "mkdir: created directory 'a'\nmkdir: created directory 'a/b'\n\
+                 mkdir: created directory 'c'",
+            ),
+            ("mkdir -p a/b", ""),
+            ("mkdir -m 0700 private", ""),
+            // The missing parent and the existing directory are reported, the
+            // operand in between is still created.
+            ("mkdir -v x/y d c", "mkdir: created directory 'd'"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.run_command(input) {
+                Ok(value) => assert_eq!(value, *expected, "{}", input),
+                Err(err) => panic!(
+                    "[test_process_command_mkdir_options] expected Ok, got {}",
+                    err
+                ),
+            }
+        }
+        assert_eq!(emulator.last_exit_status, 1);
+        assert!(temp_dir.path().join("a/b").is_dir());
+        assert!(!temp_dir.path().join("x").exists());
+        let mode = std::fs::metadata(temp_dir.path().join("private"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o700);
+        assert!(emulator.process_command("mkdir -m 9 e").is_err());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_cp_mv_ln() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().join("work");
+        std::fs::create_dir_all(temp_dir.path().join("work/tree/sub")).unwrap();
+        std::fs::write(temp_dir.path().join("work/a.txt"), "a").unwrap();
+        std::fs::write(temp_dir.path().join("work/b.txt"), "b").unwrap();
+        std::fs::write(temp_dir.path().join("work/tree/sub/c.txt"), "c").unwrap();
+        std::fs::write(temp_dir.path().join("work/answers"), "n\ny\n").unwrap();
+
+        let test_cases = [
+            ("cp -v a.txt copy.txt", "'a.txt' -> 'copy.txt'"),
+            ("mkdir out", ""),
+            (
+                "cp -v a.txt b.txt out",
+                "'a.txt' -> 'out/a.txt'\n'b.txt' -> 'out/b.txt'",
+            ),
+            ("cp -n b.txt copy.txt", ""),
+            ("cp -i b.txt copy.txt < answers", ""),
+            ("cp -r tree tree2", ""),
+            ("mv -v tree2 moved", "renamed 'tree2' -> 'moved'"),
+            ("mv copy.txt moved/sub", ""),
+            ("ln -sv a.txt link", "'link' -> 'a.txt'"),
+            ("ln a.txt hard", ""),
+            ("ln -s ../a.txt out", ""),
+            ("ln -sf b.txt link", ""),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.run_command(input) {
+                Ok(value) => assert_eq!(value, *expected, "{}", input),
+                Err(err) => panic!("[test_process_command_cp_mv_ln] expected Ok, got {}", err),
+            }
+        }
+        let read = |name: &str| std::fs::read_to_string(temp_dir.path().join("work").join(name));
+        assert_eq!(read("out/b.txt").unwrap(), "b");
+        assert_eq!(read("moved/sub/c.txt").unwrap(), "c");
+        // `-n` and the `n` answer kept the copy of `a.txt`.
+        assert_eq!(read("moved/sub/copy.txt").unwrap(), "a");
+        assert!(read("tree2/sub/c.txt").is_err());
+        assert_eq!(read("link").unwrap(), "b");
+        assert_eq!(read("out/a.txt").unwrap(), "a");
+        let hard = std::fs::metadata(temp_dir.path().join("work/hard")).unwrap();
+        assert_eq!(hard.nlink(), 2);
+
+        let failures = [
+            "cp tree elsewhere",
+            "cp -r tree tree/sub",
+            "mv tree tree/sub",
+            "cp a.txt a.txt",
+            "cp a.txt hard",
+            "mv a.txt hard",
+            "ln a.txt b.txt",
+            "mv missing.txt b.txt",
+        ];
+        for input in failures.iter() {
+            assert!(emulator.run_command(input).is_ok(), "{}", input);
+            assert_eq!(emulator.last_exit_status, 1, "{}", input);
+        }
+        // Neither hard link was truncated or removed.
+        assert_eq!(read("a.txt").unwrap(), "a");
+        assert_eq!(read("hard").unwrap(), "a");
+        assert!(emulator.process_command("cp a.txt b.txt missing").is_err());
+        assert!(emulator.process_command("mv a.txt").is_err());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_head_tail_wc() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        let numbers: String = (1..=20).map(|x| format!("{}\n", x)).collect();
+        std::fs::write(temp_dir.path().join("numbers.txt"), numbers).unwrap();
+        std::fs::write(temp_dir.path().join("words.txt"), "hello world\nbye").unwrap();
+
+        let test_cases = [
+            ("head -n 3 numbers.txt", "1\n2\n3\n"),
+            ("head -c 4 < words.txt", "hell"),
+            ("tail -2 numbers.txt", "19\n20\n"),
+            ("tail -n +19 < numbers.txt", "19\n20\n"),
+            ("tail -c 3 words.txt", "bye"),
+            (
+                "head -n1 numbers.txt words.txt",
+                "==> numbers.txt <==\n1\n\n==> words.txt <==\nhello world\n",
+            ),
+            ("tail -qn1 numbers.txt words.txt", "20\nbye"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            let _ignored = std::fs::remove_file(temp_dir.path().join("out.txt"));
+            let command = format!("{} > out.txt", input);
+            match emulator.run_command(&command) {
+                Ok(value) => assert_eq!(value, ""),
+                Err(err) => panic!(
+                    "[test_process_command_head_tail_wc] expected Ok, got {}",
+                    err
+                ),
+            }
+            let output = std::fs::read_to_string(temp_dir.path().join("out.txt")).unwrap();
+            assert_eq!(output, *expected, "{}", input);
+        }
+
+        let test_cases = [
+            ("wc -l numbers.txt", "20 numbers.txt"),
+            ("wc words.txt", " 1  3 15 words.txt"),
+            (
+                "wc -lw numbers.txt words.txt",
+                "20 20 numbers.txt\n 1  3 words.txt\n21 23 total",
+            ),
+            ("wc -c < numbers.txt", "51"),
+            ("wc -m < words.txt", "15"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.run_command(input) {
+                Ok(value) => assert_eq!(value, *expected, "{}", input),
+                Err(err) => panic!(
+                    "[test_process_command_head_tail_wc] expected Ok, got {}",
+                    err
+                ),
+            }
+        }
+        let _ignored = emulator.run_command("wc -l missing.txt numbers.txt");
+        assert_eq!(emulator.last_exit_status, 1);
+        assert!(emulator.process_command("head -n x numbers.txt").is_err());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_tail_follow() {
+        use std::io::Write;
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        let log = temp_dir.path().join("log.txt");
+        let out = temp_dir.path().join("out.txt");
+        std::fs::write(&log, "start\n").unwrap();
+
+        // Appends, truncates and rotates the log, waiting each time until
+        // `tail` has printed the new line, then presses Ctrl-C.
+        let writer = {
+            let (log, out) = (log.clone(), out.clone());
+            std::thread::spawn(move || {
+                let wait_for = |text: &str| {
+                    for _ in 0..200 {
+                        let output = std::fs::read_to_string(&out).unwrap_or_default();
+                        if output.ends_with(text) {
+                            return;
+                        }
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                    }
+                };
+                wait_for("start\n");
+                let mut file = std::fs::OpenOptions::new().append(true).open(&log).unwrap();
+                file.write_all(b"appended\n").unwrap();
+                wait_for("appended\n");
+                std::fs::write(&log, "truncated\n").unwrap();
+                wait_for("truncated\n");
+                std::fs::rename(&log, log.with_extension("1")).unwrap();
+                std::fs::write(&log, "rotated\n").unwrap();
+                wait_for("rotated\n");
+                std::fs::write(log.with_file_name("new.txt"), "new\n").unwrap();
+                wait_for("new\n");
+                unsafe { libc::raise(libc::SIGINT) };
+            })
+        };
+        // `new.txt` only appears later; `log.txt` keeps its header until then.
+        match emulator.run_command("tail -F -s 0.01 log.txt new.txt > out.txt") {
+            Ok(value) => assert_eq!(value, ""),
+            Err(err) => panic!(
+                "[test_process_command_tail_follow] expected Ok, got {}",
+                err
+            ),
+        }
+        writer.join().unwrap();
+        let output = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(
+            output,
+            "==> log.txt <==\nstart\nappended\ntruncated\nrotated\n\n==> new.txt <==\nnew\n"
+        );
+        assert_eq!(emulator.last_exit_status, 130);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_find() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        for name in [
+            "a.txt",
+            "src/b.txt",
+            "src/it's.txt",
+            "skip/c.txt",
+            "old/d.log",
+            "echo/e",
+            "echo x/f",
+        ] {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, name).unwrap();
+        }
+
+        let test_cases = [
+            ("find src -name '*.txt'", "src/b.txt\nsrc/it's.txt\n"),
+            (
+                "find . -maxdepth 1 -type d",
+                ".\n./echo\n./echo x\n./old\n./skip\n./src\n",
+            ),
+            (
+                "find . -path ./skip -prune -o -name '*.txt' -print",
+                "./a.txt\n./src/b.txt\n./src/it's.txt\n",
+            ),
+            ("find src -type f -print0", "src/b.txt\0src/it's.txt\0"),
+            ("find src -type f -exec cat {} \\;", "src/b.txtsrc/it's.txt"),
+            (
+                "find src ! -name 'b*' -type f -exec echo found {} \\;",
+                "found src/it's.txt\n",
+            ),
+            ("find old -name '*.log' -exec rm {} \\;", ""),
+            ("find echo -maxdepth 0 -exec {} hi \\;", "hi\n"),
+            ("find 'echo x' -maxdepth 0 -exec echo {} \\;", "echo x\n"),
+            // The name is one word, not the command `echo` with an argument.
+            ("find 'echo x' -maxdepth 0 -exec {} \\;", ""),
+        ];
+        for (input, expected) in test_cases.iter() {
+            let command = format!("{} > out", input);
+            match emulator.run_command(&command) {
+                Ok(value) => assert_eq!(value, ""),
+                Err(err) => panic!("[test_process_command_find] expected Ok, got {}", err),
+            }
+            let output = std::fs::read_to_string(temp_dir.path().join("out")).unwrap();
+            assert_eq!(output, *expected, "{}", input);
+        }
+        // `-exec` runs are not added to the history.
+        assert!(emulator.history.iter().all(|x| x.starts_with("find")));
+
+        assert!(emulator.run_command("find old -delete").is_ok());
+        assert!(!temp_dir.path().join("old").exists());
+        assert!(emulator.run_command("find missing").is_ok());
+        assert_eq!(emulator.last_exit_status, 1);
+        assert!(emulator.run_command("find . -exec cat").is_err());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_test() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        std::fs::write(temp_dir.path().join("file.txt"), "x").unwrap();
+        emulator.set_variable("NAME", "archive.tar.gz".to_string());
+
+        let test_cases = [
+            ("test -f file.txt", 0),
+            ("[ -d file.txt ]", 1),
+            ("[ ! -e missing -a -s file.txt ]", 0),
+            ("[ -z \"$NAME\" -o 2 -ge 10 ]", 1),
+            ("[ 1 -eq x ]", 2),
+            ("[ -n x", 2),
+            ("[ \"(\" ]", 0),
+            ("[ ! ]", 0),
+            ("[ ! \"(\" ]", 1),
+            ("[[ $NAME == *.tar.* && $NAME != \"*.gz\" ]]", 0),
+            ("[[ a > b || -f file.txt ]]", 0),
+            ("[[ $NAME =~ ^([a-z]+)\\.(.*)$ ]]", 0),
+            ("[[ $NAME =~ \"^.*\" ]]", 1),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.run_command(input) {
+                Ok(value) => assert_eq!(value, ""),
+                Err(err) => panic!("[test_process_command_test] expected Ok, got {}", err),
+            }
+            assert_eq!(emulator.last_exit_status, *expected, "{}", input);
+        }
+
+        emulator
+            .run_command("[[ $NAME =~ ^([a-z]+)\\.(.*)$ ]]")
+            .unwrap();
+        let test_cases = [
+            ("echo ${BASH_REMATCH[1]}", "archive"),
+            ("echo ${BASH_REMATCH[2]}", "tar.gz"),
+            ("echo $BASH_REMATCH", "archive.tar.gz"),
+            ("echo ${BASH_REMATCH[@]}", "archive.tar.gz archive tar.gz"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            assert_eq!(emulator.run_command(input).unwrap(), *expected, "{}", input);
+        }
+
+        // A side that isn't evaluated leaves the captures alone.
+        emulator.run_command("[[ a == a || x =~ (x) ]]").unwrap();
+        assert_eq!(
+            emulator.run_command("echo $BASH_REMATCH").unwrap(),
+            "archive.tar.gz"
+        );
+
+        // `>` after `]]` redirects; inside it compares.
+        emulator.run_command("[[ b > a ]] > out.txt").unwrap();
+        assert_eq!(emulator.last_exit_status, 0);
+        assert!(temp_dir.path().join("out.txt").exists());
+        assert!(!temp_dir.path().join("a").exists());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_command_sort() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        std::fs::write(temp_dir.path().join("a.txt"), "pear\napple\nPear\napple\n").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "banana").unwrap();
+        std::fs::write(
+            temp_dir.path().join("sizes.txt"),
+            "1.5G backups\n12K notes\n300M photos\n",
+        )
+        .unwrap();
+
+        let test_cases = [
+            ("sort a.txt", "Pear\napple\napple\npear"),
+            ("sort < a.txt", "Pear\napple\napple\npear"),
+            ("sort -u a.txt b.txt", "Pear\napple\nbanana\npear"),
+            ("sort -fu a.txt", "apple\npear"),
+            ("sort -r a.txt", "pear\napple\napple\nPear"),
+            ("sort -hr sizes.txt", "1.5G backups\n300M photos\n12K notes"),
+            (
+                "sort -k 2 sizes.txt",
+                "1.5G backups\n12K notes\n300M photos",
+            ),
+            ("sort -o a.txt a.txt", ""),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.process_command(input) {
+                Ok(value) => assert_eq!(value, *expected, "{}", input),
+                Err(err) => panic!("[test_process_command_sort] expected Ok, got {}", err),
+            }
+        }
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("a.txt")).unwrap(),
+            "Pear\napple\napple\npear\n"
+        );
+        temp_dir.close().unwrap();
+    }
+
     #[test]
     fn test_process_grep_command() {
         use tempfile::tempdir;
@@ -653,21 +3938,189 @@ mod tests {
         let file_path = temp_dir.path().join(file_name);
         let file = std::fs::OpenOptions::new()
             .create(true)
+            .truncate(true)
             .write(true)
             .open(&file_path);
         match file {
             Ok(mut file) => {
-                if let Err(_) = file.write_all("hello\nworld\nhello".as_bytes()) {
+                if file.write_all("hello\nworld\nhello".as_bytes()).is_err() {
                     panic!("Failed to write to file");
                 }
             }
             Err(_) => panic!("Failed to open file"),
         }
 
-        match emulator.process_grep_command(format!("grep hello {}", file_name).as_str()) {
-            Ok(value) => assert_eq!(value, "hello\nhello"),
+        match emulator.process_command(format!("grep hello {} > out", file_name).as_str()) {
+            Ok(value) => assert_eq!(value, ""),
             Err(_) => panic!("[test_process_grep_command] expected Ok, got error"),
         }
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("out")).unwrap(),
+            "hello\nhello\n"
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_grep_command_recursive() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        for (name, content) in [
+            ("src/main.rs", "fn main() {}\n"),
+            ("src/lib.rs", "pub fn main_loop() {}\n"),
+            ("src/notes.txt", "main notes\n"),
+            ("target/out.rs", "fn main() {}\n"),
+            ("docs/readme.md", "no match here\n"),
+            (".gitignore", "target/\n*.txt\n"),
+        ] {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        std::fs::write(temp_dir.path().join("src/data.bin"), b"main\0\x01").unwrap();
+        std::os::unix::fs::symlink("src", temp_dir.path().join("link")).unwrap();
+        // Enough files to keep several workers busy; output stays in order.
+        let mut expected_many = Vec::new();
+        for i in 0..40 {
+            let name = format!("many/{:02}.txt", i);
+            let path = temp_dir.path().join(&name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, format!("line {}\n", i)).unwrap();
+            expected_many.push(format!("{}:line {}", name, i));
+        }
+
+        let test_cases = [
+            (
+                "grep -rl main",
+                "src/lib.rs\nsrc/main.rs\nsrc/notes.txt\ntarget/out.rs",
+            ),
+            ("grep -rl --gitignore main", "src/lib.rs\nsrc/main.rs"),
+            (
+                "grep -rl --include=*.rs main src",
+                "src/lib.rs\nsrc/main.rs",
+            ),
+            (
+                "grep -rl --exclude=*.txt --exclude-dir=target main",
+                "src/lib.rs\nsrc/main.rs",
+            ),
+            (
+                "grep -Rl --include=main.rs main",
+                "link/main.rs\nsrc/main.rs",
+            ),
+            ("grep main src/data.bin", "Binary file src/data.bin matches"),
+            ("grep -rh 'main()' src", "fn main() {}"),
+            ("grep -r line many", &expected_many.join("\n")),
+        ];
+        // The output goes outside the directory being searched.
+        let out_dir = tempdir().unwrap();
+        let out = out_dir.path().join("out");
+        for (input, expected) in test_cases.iter() {
+            let command = format!("{} > {}", input, out.display());
+            match emulator.process_command(&command) {
+                Ok(value) => assert_eq!(value, ""),
+                Err(err) => panic!(
+                    "[test_process_grep_command_recursive] expected Ok, got {}",
+                    err
+                ),
+            }
+            let output = std::fs::read_to_string(&out).unwrap();
+            assert_eq!(output, format!("{}\n", expected), "{}", input);
+        }
+        out_dir.close().unwrap();
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_process_grep_command_options() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let mut emulator = Emulator::new();
+        emulator.path = temp_dir.path().to_path_buf();
+        std::fs::write(
+            temp_dir.path().join("a.txt"),
+            "foo bar\nFoo\nfood\nbaz 42\n",
+        )
+        .unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "a.b\naxb\n").unwrap();
+        std::fs::write(temp_dir.path().join("words"), "ab ac-ad @x\n").unwrap();
+
+        let test_cases = [
+            ("grep -i foo a.txt", "foo bar\nFoo\nfood"),
+            ("grep -w foo a.txt", "foo bar"),
+            ("grep -w 'ba.' a.txt", "foo bar\nbaz 42"),
+            ("grep -ow '@x' words", "@x"),
+            ("grep -ow 'a[a-z]' words", "ab\nac\nad"),
+            ("grep -vn foo a.txt", "2:Foo\n4:baz 42"),
+            ("grep -c o a.txt b.txt", "a.txt:3\nb.txt:0"),
+            ("grep -l b *.txt", "a.txt\nb.txt"),
+            ("grep -o '[0-9]\\+' a.txt", "42"),
+            ("grep -oE 'fo+' a.txt", "foo\nfoo"),
+            ("grep -E 'bar|baz' a.txt", "foo bar\nbaz 42"),
+            ("grep 'bar\\|baz' a.txt", "foo bar\nbaz 42"),
+            ("grep 'a.b' b.txt", "a.b\naxb"),
+            ("grep -F 'a.b' b.txt", "a.b"),
+            ("grep -e Foo -e baz a.txt", "Foo\nbaz 42"),
+            ("grep -H bar a.txt", "a.txt:foo bar"),
+            ("grep -q foo a.txt", ""),
+            ("grep foo < a.txt", "foo bar\nfood"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.run_command(&format!("{} > out", input)) {
+                Ok(value) => assert_eq!(value, ""),
+                Err(err) => panic!(
+                    "[test_process_grep_command_options] expected Ok, got {}",
+                    err
+                ),
+            }
+            assert_eq!(emulator.last_exit_status, 0, "{}", input);
+            let output = std::fs::read_to_string(temp_dir.path().join("out")).unwrap();
+            assert_eq!(output.trim_end_matches('\n'), *expected, "{}", input);
+        }
+
+        std::fs::write(
+            temp_dir.path().join("log.txt"),
+            "1\n2\nerror a\n4\n5\n6\n7\nerror b\nerror c\n10\n",
+        )
+        .unwrap();
+        let test_cases = [
+            ("grep -n -A1 error log.txt", "3:error a\n4-4\n--\n8:error b\n9:error c\n10-10"),
+            ("grep -B 2 'error b' log.txt", "6\n7\nerror b"),
+            ("grep -nC1 'error a' log.txt", "2-2\n3:error a\n4-4"),
+            ("grep -3 'error a' log.txt", "1\n2\nerror a\n4\n5\n6"),
+            (
+                "grep -A1 -H 'error c' log.txt b.txt",
+                "log.txt:error c\nlog.txt-10",
+            ),
+            (
+                "grep -A1 -e 'error c' -e axb log.txt b.txt",
+                "log.txt:error c\nlog.txt-10\n--\nb.txt:axb",
+            ),
+            (
+                "grep --color=always -n 'b\\|c' log.txt",
+                "\x1b[32m\x1b[K8\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[Kerror \x1b[01;31m\x1b[Kb\x1b[m\x1b[K\n\
+                 \x1b[32m\x1b[K9\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[Kerror \x1b[01;31m\x1b[Kc\x1b[m\x1b[K",
+            ),
+            ("grep --color=never b log.txt", "error b"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            match emulator.process_command(&format!("{} > out", input)) {
+                Ok(value) => assert_eq!(value, ""),
+                Err(err) => panic!(
+                    "[test_process_grep_command_options] expected Ok, got {}",
+                    err
+                ),
+            }
+            let output = std::fs::read_to_string(temp_dir.path().join("out")).unwrap();
+            assert_eq!(output, format!("{}\n", expected), "{}", input);
+        }
+
+        let _ignored = emulator.run_command("grep nothing a.txt");
+        assert_eq!(emulator.last_exit_status, 1);
+        let _ignored = emulator.run_command("grep foo a.txt missing.txt");
+        assert_eq!(emulator.last_exit_status, 2);
+        assert!(emulator.process_command("grep 'a\\(' a.txt").is_err());
         temp_dir.close().unwrap();
     }
 }
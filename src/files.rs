@@ -0,0 +1,264 @@
+// Option parsing for `cp`, `mv` and `ln`, and the copying both `cp` and a
+// cross-filesystem `mv` are built on.
+
+use std::ffi::CString;
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileCommand {
+    Copy,
+    Move,
+    Link,
+}
+
+impl FileCommand {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileCommand::Copy => "cp",
+            FileCommand::Move => "mv",
+            FileCommand::Link => "ln",
+        }
+    }
+
+    fn supports(self, flag: char) -> bool {
+        match self {
+            FileCommand::Copy => "rRpnifv".contains(flag),
+            FileCommand::Move => "nifv".contains(flag),
+            FileCommand::Link => "sfv".contains(flag),
+        }
+    }
+
+    pub fn missing_operand(self) -> &'static str {
+        match self {
+            FileCommand::Copy => "cp: missing file operand",
+            FileCommand::Move => "mv: missing file operand",
+            FileCommand::Link => "ln: missing file operand",
+        }
+    }
+
+    pub fn missing_destination(self) -> &'static str {
+        match self {
+            FileCommand::Copy => "cp: missing destination file operand",
+            FileCommand::Move => "mv: missing destination file operand",
+            FileCommand::Link => "ln: missing destination file operand",
+        }
+    }
+
+    pub fn not_a_directory(self) -> &'static str {
+        match self {
+            FileCommand::Copy => "cp: target is not a directory",
+            FileCommand::Move => "mv: target is not a directory",
+            FileCommand::Link => "ln: target is not a directory",
+        }
+    }
+
+    fn invalid_option(self) -> &'static str {
+        match self {
+            FileCommand::Copy => "cp: invalid option. Supported options: -rRpnifv",
+            FileCommand::Move => "mv: invalid option. Supported options: -nifv",
+            FileCommand::Link => "ln: invalid option. Supported options: -sfv",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FileOptions {
+    pub recursive: bool,
+    // Keep mode, ownership and timestamps (`cp -p`).
+    pub preserve: bool,
+    pub no_clobber: bool,
+    pub interactive: bool,
+    pub force: bool,
+    pub verbose: bool,
+    pub symbolic: bool,
+}
+
+impl FileOptions {
+    /// Splits the arguments of `command` into options and operands. Of
+    /// `-f`, `-i` and `-n`, the last one given wins.
+    pub fn parse(
+        command: FileCommand,
+        arguments: &[String],
+    ) -> Result<(FileOptions, Vec<String>), &'static str> {
+        let mut options = FileOptions::default();
+        let mut operands = Vec::new();
+        let mut end_of_options = false;
+        for argument in arguments {
+            if end_of_options || argument == "-" || !argument.starts_with('-') {
+                operands.push(argument.clone());
+                continue;
+            }
+            if argument == "--" {
+                end_of_options = true;
+                continue;
+            }
+            let flags: Vec<char> = match argument.as_str() {
+                "--recursive" => vec!['r'],
+                "--preserve" => vec!['p'],
+                "--no-clobber" => vec!['n'],
+                "--interactive" => vec!['i'],
+                "--force" => vec!['f'],
+                "--verbose" => vec!['v'],
+                "--symbolic" => vec!['s'],
+                _ if argument.starts_with("--") => return Err(command.invalid_option()),
+                _ => argument[1..].chars().collect(),
+            };
+            for flag in flags {
+                if !command.supports(flag) {
+                    return Err(command.invalid_option());
+                }
+                match flag {
+                    'r' | 'R' => options.recursive = true,
+                    'p' => options.preserve = true,
+                    's' => options.symbolic = true,
+                    'v' => options.verbose = true,
+                    _ => {
+                        options.no_clobber = flag == 'n';
+                        options.interactive = flag == 'i';
+                        options.force = flag == 'f';
+                    }
+                }
+            }
+        }
+        Ok((options, operands))
+    }
+}
+
+/// Copies a file, symlink, FIFO or (merging into an existing one) a whole
+/// directory. Symlinks are copied as links, never followed. With
+/// `preserve`, ownership and timestamps are kept as well as the mode.
+pub fn copy_tree(from: &Path, to: &Path, preserve: bool) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(from)?, to)?;
+    } else if file_type.is_dir() {
+        match fs::create_dir(to) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && to.is_dir() => {}
+            result => result?,
+        }
+        let mut entries: Vec<_> = fs::read_dir(from)?.collect::<io::Result<_>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            copy_tree(&entry.path(), &to.join(entry.file_name()), preserve)?;
+        }
+        fs::set_permissions(to, metadata.permissions())?;
+    } else if file_type.is_fifo() {
+        // Reading a FIFO would wait for a writer; make a new one instead.
+        let c_path = c_path(to)?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), metadata.mode() & 0o7777) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    } else if file_type.is_file() {
+        fs::copy(from, to)?;
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cannot copy special files",
+        ));
+    }
+    if preserve {
+        preserve_attributes(to, &metadata)?;
+    }
+    Ok(())
+}
+
+// Gives `path` the owner and times of `metadata`. Changing the owner needs
+// privileges, so failing to is not an error.
+fn preserve_attributes(path: &Path, metadata: &Metadata) -> io::Result<()> {
+    let c_path = c_path(path)?;
+    let times = [
+        libc::timespec {
+            tv_sec: metadata.atime(),
+            tv_nsec: metadata.atime_nsec(),
+        },
+        libc::timespec {
+            tv_sec: metadata.mtime(),
+            tv_nsec: metadata.mtime_nsec(),
+        },
+    ];
+    unsafe {
+        libc::lchown(c_path.as_ptr(), metadata.uid(), metadata.gid());
+        let flags = libc::AT_SYMLINK_NOFOLLOW;
+        if libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), flags) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| io::ErrorKind::InvalidInput.into())
+}
+
+/// Renames `from` to `to`, copying and deleting when they are on different
+/// filesystems.
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            copy_tree(from, to, true)?;
+            match fs::symlink_metadata(from)?.is_dir() {
+                true => fs::remove_dir_all(from),
+                false => fs::remove_file(from),
+            }
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        let arguments: Vec<String> = ["-rpv", "-i", "-n", "a", "--", "-b"]
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        let (options, operands) = FileOptions::parse(FileCommand::Copy, &arguments).unwrap();
+        assert!(options.recursive && options.preserve && options.verbose);
+        assert!(options.no_clobber && !options.interactive);
+        assert_eq!(operands, vec!["a", "-b"]);
+        assert!(FileOptions::parse(FileCommand::Move, &["-r".to_string()]).is_err());
+        assert!(FileOptions::parse(FileCommand::Link, &["-sf".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn test_copy_tree() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let source = temp_dir.path().join("source");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("sub/file.txt"), "data").unwrap();
+        std::os::unix::fs::symlink("sub/file.txt", source.join("link")).unwrap();
+        let old = libc::timespec {
+            tv_sec: 1000,
+            tv_nsec: 0,
+        };
+        let c_file = c_path(&source.join("sub/file.txt")).unwrap();
+        unsafe { libc::utimensat(libc::AT_FDCWD, c_file.as_ptr(), [old, old].as_ptr(), 0) };
+
+        let copy = temp_dir.path().join("copy");
+        copy_tree(&source, &copy, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(copy.join("sub/file.txt")).unwrap(),
+            "data"
+        );
+        assert_eq!(
+            fs::read_link(copy.join("link")).unwrap(),
+            Path::new("sub/file.txt")
+        );
+        let metadata = fs::metadata(copy.join("sub/file.txt")).unwrap();
+        assert_eq!(metadata.mtime(), 1000);
+
+        move_path(&copy, &temp_dir.path().join("moved")).unwrap();
+        assert!(!copy.exists());
+        assert!(temp_dir.path().join("moved/sub/file.txt").exists());
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,540 @@
+// Expression parsing and the directory walk for `find`. What the actions do
+// (printing, deleting, running commands) is up to the caller's `Visitor`.
+
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::glob::Pattern;
+
+pub enum Action {
+    Print,
+    Print0,
+    Delete,
+    // The command words, with `{}` still in them.
+    Exec(Vec<String>),
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Less(u64),
+    Equal(u64),
+    Greater(u64),
+}
+
+impl Comparison {
+    // `+N` is more than N, `-N` less than N, `N` exactly N.
+    fn parse(value: &str) -> Option<Comparison> {
+        let (make, number): (fn(u64) -> Comparison, &str) = match value.as_bytes().first() {
+            Some(b'+') => (Comparison::Greater, &value[1..]),
+            Some(b'-') => (Comparison::Less, &value[1..]),
+            _ => (Comparison::Equal, value),
+        };
+        number.parse().ok().map(make)
+    }
+
+    fn test(self, value: u64) -> bool {
+        match self {
+            Comparison::Less(limit) => value < limit,
+            Comparison::Equal(limit) => value == limit,
+            Comparison::Greater(limit) => value > limit,
+        }
+    }
+}
+
+enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    True,
+    Name(Pattern, bool),
+    Path(Pattern, bool),
+    Type(Vec<char>),
+    // Size rounded up to units of this many bytes.
+    Size(Comparison, u64),
+    // Age in units of this many seconds, rounded down.
+    Modified(Comparison, u64),
+    // Modified after this time, as seconds and nanoseconds.
+    Newer(i64, i64),
+    Prune,
+    Action(Action),
+}
+
+/// A file reached by the walk. `path` is how it is shown: the starting
+/// point followed by the names below it.
+pub struct Entry {
+    pub path: String,
+    pub full_path: PathBuf,
+    pub metadata: Metadata,
+    pub depth: usize,
+    // Set by `-prune`: don't descend into this directory.
+    prune: bool,
+}
+
+pub trait Visitor {
+    /// Carries out an action, returning whether it succeeded (which is the
+    /// action's value in the expression).
+    fn act(&mut self, action: &Action, entry: &Entry) -> bool;
+
+    fn error(&mut self, message: String);
+}
+
+pub struct FindOptions {
+    expression: Expression,
+    max_depth: Option<usize>,
+    min_depth: usize,
+    // Visit directory contents before the directory itself, as `-delete`
+    // needs.
+    depth_first: bool,
+    now: SystemTime,
+}
+
+impl FindOptions {
+    /// Splits `find` arguments into the starting points and the expression.
+    /// `-newer` files are looked up relative to `base`.
+    pub fn parse(
+        arguments: &[String],
+        base: &Path,
+    ) -> Result<(FindOptions, Vec<String>), &'static str> {
+        let starts_expression = |x: &String| x.starts_with('-') || x == "!" || x == "(";
+        let split = arguments
+            .iter()
+            .position(starts_expression)
+            .unwrap_or(arguments.len());
+        let mut roots = arguments[..split].to_vec();
+        if roots.is_empty() {
+            roots.push(".".to_string());
+        }
+        let mut parser = Parser {
+            arguments: &arguments[split..],
+            index: 0,
+            base,
+            options: FindOptions {
+                expression: Expression::True,
+                max_depth: None,
+                min_depth: 0,
+                depth_first: false,
+                now: SystemTime::now(),
+            },
+            has_action: false,
+        };
+        let mut expression = match parser.arguments.is_empty() {
+            true => Expression::True,
+            false => parser.or()?,
+        };
+        if let Some(argument) = parser.arguments.get(parser.index) {
+            return Err(match argument.as_str() {
+                ")" => "find: unmatched ')'",
+                _ => "find: paths must precede expression",
+            });
+        }
+        // Without an action, whatever matches is printed.
+        if !parser.has_action {
+            expression = Expression::And(
+                Box::new(expression),
+                Box::new(Expression::Action(Action::Print)),
+            );
+        }
+        let mut options = parser.options;
+        options.expression = expression;
+        Ok((options, roots))
+    }
+
+    /// Walks the tree below `root` (relative to `base`), evaluating the
+    /// expression on every file.
+    pub fn walk(&self, root: &str, base: &Path, visitor: &mut dyn Visitor) {
+        let full_path = base.join(root);
+        match fs::symlink_metadata(&full_path) {
+            Ok(metadata) => self.visit(root.to_string(), full_path, metadata, 0, visitor),
+            Err(_) => visitor.error(format!("find: '{}': No such file or directory", root)),
+        }
+    }
+
+    fn visit(
+        &self,
+        path: String,
+        full_path: PathBuf,
+        metadata: Metadata,
+        depth: usize,
+        visitor: &mut dyn Visitor,
+    ) {
+        let mut entry = Entry {
+            path,
+            full_path,
+            metadata,
+            depth,
+            prune: false,
+        };
+        let in_range = depth >= self.min_depth && self.max_depth.is_none_or(|max| depth <= max);
+        if in_range && !self.depth_first {
+            self.evaluate(&self.expression, &mut entry, visitor);
+        }
+        let descend =
+            entry.metadata.is_dir() && !entry.prune && self.max_depth.is_none_or(|max| depth < max);
+        if descend {
+            match fs::read_dir(&entry.full_path) {
+                Ok(read_dir) => {
+                    let mut children: Vec<_> = read_dir.filter_map(|child| child.ok()).collect();
+                    children.sort_by_key(|child| child.file_name());
+                    for child in children {
+                        let name = child.file_name().to_string_lossy().into_owned();
+                        let path = match entry.path.ends_with('/') {
+                            true => format!("{}{}", entry.path, name),
+                            false => format!("{}/{}", entry.path, name),
+                        };
+                        match fs::symlink_metadata(child.path()) {
+                            Ok(metadata) => {
+                                self.visit(path, child.path(), metadata, depth + 1, visitor)
+                            }
+                            Err(_) => visitor.error(format!("find: '{}': cannot stat", path)),
+                        }
+                    }
+                }
+                Err(err) => visitor.error(format!("find: '{}': {}", entry.path, reason(&err))),
+            }
+        }
+        if in_range && self.depth_first {
+            self.evaluate(&self.expression, &mut entry, visitor);
+        }
+    }
+
+    fn evaluate(
+        &self,
+        expression: &Expression,
+        entry: &mut Entry,
+        visitor: &mut dyn Visitor,
+    ) -> bool {
+        match expression {
+            Expression::And(left, right) => {
+                self.evaluate(left, entry, visitor) && self.evaluate(right, entry, visitor)
+            }
+            Expression::Or(left, right) => {
+                self.evaluate(left, entry, visitor) || self.evaluate(right, entry, visitor)
+            }
+            Expression::Not(inner) => !self.evaluate(inner, entry, visitor),
+            Expression::True => true,
+            Expression::Name(pattern, ignore_case) => {
+                let name = Path::new(&entry.path)
+                    .file_name()
+                    .map_or(entry.path.clone(), |name| {
+                        name.to_string_lossy().into_owned()
+                    });
+                pattern.matches(&name, *ignore_case)
+            }
+            Expression::Path(pattern, ignore_case) => pattern.matches(&entry.path, *ignore_case),
+            Expression::Type(types) => types.contains(&type_letter(&entry.metadata)),
+            Expression::Size(comparison, unit) => {
+                comparison.test(entry.metadata.len().div_ceil(*unit))
+            }
+            Expression::Modified(comparison, unit) => {
+                let modified = UNIX_EPOCH
+                    + std::time::Duration::from_secs(entry.metadata.mtime().max(0) as u64);
+                let age = self
+                    .now
+                    .duration_since(modified)
+                    .unwrap_or_default()
+                    .as_secs();
+                comparison.test(age / unit)
+            }
+            Expression::Newer(seconds, nanoseconds) => {
+                (entry.metadata.mtime(), entry.metadata.mtime_nsec()) > (*seconds, *nanoseconds)
+            }
+            Expression::Prune => {
+                entry.prune = true;
+                true
+            }
+            Expression::Action(action) => visitor.act(action, entry),
+        }
+    }
+}
+
+struct Parser<'a> {
+    arguments: &'a [String],
+    index: usize,
+    base: &'a Path,
+    options: FindOptions,
+    has_action: bool,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.arguments.get(self.index).map(|x| x.as_str())
+    }
+
+    fn value(&mut self) -> Result<&str, &'static str> {
+        let value = self
+            .arguments
+            .get(self.index)
+            .ok_or("find: missing argument")?;
+        self.index += 1;
+        Ok(value)
+    }
+
+    fn or(&mut self) -> Result<Expression, &'static str> {
+        let mut expression = self.and()?;
+        while matches!(self.peek(), Some("-o" | "-or")) {
+            self.index += 1;
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    // `-a` is optional: two expressions next to each other are both tested.
+    fn and(&mut self) -> Result<Expression, &'static str> {
+        let mut expression = self.unary()?;
+        loop {
+            match self.peek() {
+                Some("-a" | "-and") => self.index += 1,
+                None | Some("-o" | "-or" | ")") => return Ok(expression),
+                _ => {}
+            }
+            expression = Expression::And(Box::new(expression), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, &'static str> {
+        match self.peek() {
+            Some("!" | "-not") => {
+                self.index += 1;
+                Ok(Expression::Not(Box::new(self.unary()?)))
+            }
+            Some("(") => {
+                self.index += 1;
+                let expression = self.or()?;
+                if self.value()? != ")" {
+                    return Err("find: missing ')'");
+                }
+                Ok(expression)
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, &'static str> {
+        const INVALID: &str = "find: invalid argument";
+        let primary = self.value()?.to_string();
+        let expression = match primary.as_str() {
+            "-name" | "-iname" => {
+                Expression::Name(Pattern::new(self.value()?), primary == "-iname")
+            }
+            "-path" | "-ipath" | "-wholename" => {
+                Expression::Path(Pattern::new(self.value()?), primary == "-ipath")
+            }
+            "-type" => {
+                let types: Vec<char> = self.value()?.split(',').flat_map(|x| x.chars()).collect();
+                if types.is_empty() || !types.iter().all(|x| "fdlpscb".contains(*x)) {
+                    return Err("find: unknown argument to -type");
+                }
+                Expression::Type(types)
+            }
+            "-size" => {
+                let value = self.value()?;
+                let (number, unit) = match value.chars().last() {
+                    Some('c') => (&value[..value.len() - 1], 1),
+                    Some('w') => (&value[..value.len() - 1], 2),
+                    Some('b') => (&value[..value.len() - 1], 512),
+                    Some('k') => (&value[..value.len() - 1], 1024),
+                    Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+                    Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+                    _ => (value, 512),
+                };
+                Expression::Size(Comparison::parse(number).ok_or(INVALID)?, unit)
+            }
+            "-mtime" | "-mmin" => {
+                let unit = if primary == "-mtime" {
+                    24 * 60 * 60
+                } else {
+                    60
+                };
+                Expression::Modified(Comparison::parse(self.value()?).ok_or(INVALID)?, unit)
+            }
+            "-newer" => {
+                let reference = self.base.join(self.value()?);
+                let metadata =
+                    fs::metadata(reference).map_err(|_| "find: cannot stat the -newer file")?;
+                Expression::Newer(metadata.mtime(), metadata.mtime_nsec())
+            }
+            "-maxdepth" | "-mindepth" => {
+                let depth = self.value()?.parse().map_err(|_| INVALID)?;
+                match primary.as_str() {
+                    "-maxdepth" => self.options.max_depth = Some(depth),
+                    _ => self.options.min_depth = depth,
+                }
+                Expression::True
+            }
+            "-depth" => {
+                self.options.depth_first = true;
+                Expression::True
+            }
+            "-prune" => Expression::Prune,
+            "-true" => Expression::True,
+            "-false" => Expression::Not(Box::new(Expression::True)),
+            "-print" | "-print0" | "-delete" | "-exec" => {
+                self.has_action = true;
+                Expression::Action(match primary.as_str() {
+                    "-print" => Action::Print,
+                    "-print0" => Action::Print0,
+                    "-delete" => {
+                        self.options.depth_first = true;
+                        Action::Delete
+                    }
+                    _ => {
+                        let mut command = Vec::new();
+                        loop {
+                            match self
+                                .value()
+                                .map_err(|_| "find: missing argument to `-exec'")?
+                            {
+                                ";" => break,
+                                word => command.push(word.to_string()),
+                            }
+                        }
+                        if command.is_empty() {
+                            return Err("find: missing argument to `-exec'");
+                        }
+                        Action::Exec(command)
+                    }
+                })
+            }
+            _ => return Err("find: unknown predicate"),
+        };
+        Ok(expression)
+    }
+}
+
+/// The letter `-type` uses for the kind of file.
+fn type_letter(metadata: &Metadata) -> char {
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else {
+        'f'
+    }
+}
+
+fn reason(err: &std::io::Error) -> &'static str {
+    match err.kind() {
+        std::io::ErrorKind::PermissionDenied => "Permission denied",
+        _ => "cannot read directory",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder(Vec<String>);
+
+    impl Visitor for Recorder {
+        fn act(&mut self, _action: &Action, entry: &Entry) -> bool {
+            self.0.push(entry.path.clone());
+            true
+        }
+
+        fn error(&mut self, message: String) {
+            self.0.push(message);
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        for (name, size) in [
+            ("a.txt", 10),
+            ("src/b.RS", 2000),
+            ("src/c.rs", 0),
+            ("target/d.rs", 1),
+        ] {
+            let path = temp_dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![b'x'; size]).unwrap();
+        }
+        std::os::unix::fs::symlink("a.txt", temp_dir.path().join("link")).unwrap();
+
+        let test_cases: [(&[&str], &[&str]); 9] = [
+            (
+                &[],
+                &[
+                    ".",
+                    "./a.txt",
+                    "./link",
+                    "./src",
+                    "./src/b.RS",
+                    "./src/c.rs",
+                    "./target",
+                    "./target/d.rs",
+                ],
+            ),
+            (&["src", "-type", "f"], &["src/b.RS", "src/c.rs"]),
+            (
+                &["-iname", "*.rs"],
+                &["./src/b.RS", "./src/c.rs", "./target/d.rs"],
+            ),
+            (&["-type", "l", "-o", "-name", "a*"], &["./a.txt", "./link"]),
+            (&["-type", "f", "-size", "+1k"], &["./src/b.RS"]),
+            (&["-size", "-1", "-type", "f"], &["./src/c.rs"]),
+            (
+                &[
+                    "-path", "./target", "-prune", "-o", "-name", "*.rs", "-print",
+                ],
+                &["./src/c.rs"],
+            ),
+            (
+                &["-mindepth", "2", "!", "-path", "*/src/*"],
+                &["./target/d.rs"],
+            ),
+            (
+                &[
+                    "-maxdepth",
+                    "1",
+                    "(",
+                    "-type",
+                    "d",
+                    "-a",
+                    "-not",
+                    "-name",
+                    ".",
+                    ")",
+                ],
+                &["./src", "./target"],
+            ),
+        ];
+        for (arguments, expected) in test_cases.iter() {
+            let arguments: Vec<String> = arguments.iter().map(|x| x.to_string()).collect();
+            let (options, roots) = FindOptions::parse(&arguments, temp_dir.path()).unwrap();
+            let mut recorder = Recorder(Vec::new());
+            for root in roots.iter() {
+                options.walk(root, temp_dir.path(), &mut recorder);
+            }
+            assert_eq!(recorder.0, *expected, "{:?}", arguments);
+        }
+
+        for invalid in [
+            &["-bogus"][..],
+            &["-type", "x"],
+            &["-exec", "rm"],
+            &["(", "-true"],
+            &["-true", "src"],
+        ] {
+            let arguments: Vec<String> = invalid.iter().map(|x| x.to_string()).collect();
+            assert!(
+                FindOptions::parse(&arguments, temp_dir.path()).is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+        temp_dir.close().unwrap();
+    }
+}
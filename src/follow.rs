@@ -0,0 +1,188 @@
+// `tail -f`: watching files for appended data, and the Ctrl-C handling that
+// stops it without stopping the shell.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// How many followers are running, and the SIGINT action to put back when
+// the last one stops.
+static HANDLER: Mutex<(usize, Option<libc::sigaction>)> = Mutex::new((0, None));
+
+extern "C" fn handle_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// While alive, Ctrl-C sets a flag (see `interrupted`) instead of killing
+/// the shell.
+pub struct InterruptGuard;
+
+impl InterruptGuard {
+    pub fn install() -> InterruptGuard {
+        let mut handler = HANDLER.lock().unwrap_or_else(|err| err.into_inner());
+        if handler.0 == 0 {
+            INTERRUPTED.store(false, Ordering::SeqCst);
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = handle_interrupt as *const () as libc::sighandler_t;
+                libc::sigemptyset(&mut action.sa_mask);
+                let mut previous: libc::sigaction = std::mem::zeroed();
+                libc::sigaction(libc::SIGINT, &action, &mut previous);
+                handler.1 = Some(previous);
+            }
+        }
+        handler.0 += 1;
+        InterruptGuard
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        let mut handler = HANDLER.lock().unwrap_or_else(|err| err.into_inner());
+        handler.0 -= 1;
+        if handler.0 == 0 {
+            if let Some(previous) = handler.1.take() {
+                unsafe { libc::sigaction(libc::SIGINT, &previous, std::ptr::null_mut()) };
+            }
+        }
+    }
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// A file being followed, by its descriptor or (`-F`) by its name.
+pub struct Followed {
+    pub name: String,
+    path: PathBuf,
+    file: Option<File>,
+    position: u64,
+    // Device and inode of `file`, to notice when the name is given to a new
+    // file.
+    identity: Option<(u64, u64)>,
+}
+
+impl Followed {
+    /// Starts following `file` from where the initial output stopped, or
+    /// waits for `path` to appear when it couldn't be opened.
+    pub fn new(name: &str, path: PathBuf, file: Option<File>) -> io::Result<Followed> {
+        let (file, position, identity) = match file {
+            Some(mut file) => {
+                let metadata = file.metadata()?;
+                let position = file.stream_position()?;
+                (Some(file), position, Some((metadata.dev(), metadata.ino())))
+            }
+            None => (None, 0, None),
+        };
+        Ok(Followed {
+            name: name.to_string(),
+            path,
+            file,
+            position,
+            identity,
+        })
+    }
+
+    /// Appends whatever was written since the last poll to `data`. Changes
+    /// worth telling the user about go to `notices`.
+    pub fn poll(
+        &mut self,
+        by_name: bool,
+        data: &mut Vec<u8>,
+        notices: &mut Vec<String>,
+    ) -> io::Result<()> {
+        if by_name {
+            match fs::metadata(&self.path) {
+                Ok(metadata) if self.identity != Some((metadata.dev(), metadata.ino())) => {
+                    // Whatever was written to the old file before it was
+                    // rotated still belongs to the output.
+                    let notice = match self.file.is_some() {
+                        true => "has been replaced",
+                        false => "has appeared",
+                    };
+                    self.read_appended(data, notices)?;
+                    notices.push(format!(
+                        "tail: '{}' {}; following new file",
+                        self.name, notice
+                    ));
+                    self.file = Some(File::open(&self.path)?);
+                    self.position = 0;
+                    self.identity = Some((metadata.dev(), metadata.ino()));
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    if self.file.is_some() {
+                        self.read_appended(data, notices)?;
+                        notices.push(format!(
+                            "tail: '{}' has become inaccessible: {}",
+                            self.name, err
+                        ));
+                        self.file = None;
+                        self.identity = None;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        self.read_appended(data, notices)
+    }
+
+    fn read_appended(&mut self, data: &mut Vec<u8>, notices: &mut Vec<String>) -> io::Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        let length = file.metadata()?.len();
+        if length < self.position {
+            notices.push(format!("tail: {}: file truncated", self.name));
+            self.position = 0;
+        }
+        if length != self.position {
+            file.seek(SeekFrom::Start(self.position))?;
+            self.position += file.read_to_end(data)? as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll() {
+        use std::io::Write;
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("log.txt");
+        std::fs::write(&path, "old\n").unwrap();
+        let mut file = File::open(&path).unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        let mut followed = Followed::new("log.txt", path.clone(), Some(file)).unwrap();
+        let (mut data, mut notices) = (Vec::new(), Vec::new());
+        let mut poll = |followed: &mut Followed| {
+            data.clear();
+            notices.clear();
+            followed.poll(true, &mut data, &mut notices).unwrap();
+            (String::from_utf8_lossy(&data).into_owned(), notices.len())
+        };
+
+        let mut writer = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        writer.write_all(b"new\n").unwrap();
+        assert_eq!(poll(&mut followed), ("new\n".to_string(), 0));
+        std::fs::write(&path, "cut\n").unwrap();
+        assert_eq!(poll(&mut followed), ("cut\n".to_string(), 1));
+
+        // Rotation: the end of the old file, then the new one from the start.
+        writer.write_all(b"last\n").unwrap();
+        std::fs::rename(&path, temp_dir.path().join("log.1")).unwrap();
+        assert_eq!(poll(&mut followed), ("last\n".to_string(), 1));
+        std::fs::write(&path, "fresh\n").unwrap();
+        assert_eq!(poll(&mut followed), ("fresh\n".to_string(), 1));
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+// Filename globbing: `*`, `?`, `[...]` and, with `globstar`, `**`.
+//
+// Patterns are given as characters paired with a "quoted" flag; quoted
+// characters always match literally.
+
+use std::path::Path;
+
+#[derive(Clone, Default)]
+pub struct GlobOptions {
+    pub nullglob: bool,
+    pub failglob: bool,
+    pub dotglob: bool,
+    pub nocaseglob: bool,
+    pub globstar: bool,
+}
+
+impl GlobOptions {
+    pub const NAMES: [&'static str; 5] =
+        ["dotglob", "failglob", "globstar", "nocaseglob", "nullglob"];
+
+    pub fn option_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "nocaseglob" => Some(&mut self.nocaseglob),
+            "nullglob" => Some(&mut self.nullglob),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    AnyString,
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+}
+
+impl Pattern {
+    /// Compiles a pattern written as text, where a backslash makes the next
+    /// character literal.
+    pub fn new(pattern: &str) -> Pattern {
+        let mut chars = Vec::new();
+        let mut escaped = false;
+        for c in pattern.chars() {
+            if escaped {
+                chars.push((c, true));
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else {
+                chars.push((c, false));
+            }
+        }
+        Pattern::from_chars(&chars)
+    }
+
+    pub fn from_chars(chars: &[(char, bool)]) -> Pattern {
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let (c, quoted) = chars[i];
+            i += 1;
+            if quoted {
+                tokens.push(Token::Literal(c));
+                continue;
+            }
+            match c {
+                '*' => {
+                    // Consecutive stars are equivalent to one.
+                    if !matches!(tokens.last(), Some(Token::AnyString)) {
+                        tokens.push(Token::AnyString);
+                    }
+                }
+                '?' => tokens.push(Token::AnyChar),
+                '[' => match parse_class(&chars[i..]) {
+                    Some((token, consumed)) => {
+                        tokens.push(token);
+                        i += consumed;
+                    }
+                    None => tokens.push(Token::Literal('[')),
+                },
+                c => tokens.push(Token::Literal(c)),
+            }
+        }
+        Pattern { tokens }
+    }
+
+    pub fn matches(&self, name: &str, ignore_case: bool) -> bool {
+        let name: Vec<char> = name.chars().collect();
+        let (mut t, mut n) = (0, 0);
+        // Position to resume from when the last `*` has to absorb one more char.
+        let mut backtrack: Option<(usize, usize)> = None;
+        while n < name.len() {
+            match self.tokens.get(t) {
+                Some(Token::AnyString) => {
+                    backtrack = Some((t, n));
+                    t += 1;
+                    continue;
+                }
+                Some(token) if token_matches(token, name[n], ignore_case) => {
+                    t += 1;
+                    n += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            match backtrack {
+                Some((star, position)) => {
+                    t = star + 1;
+                    n = position + 1;
+                    backtrack = Some((star, position + 1));
+                }
+                None => return false,
+            }
+        }
+        self.tokens[t..]
+            .iter()
+            .all(|token| matches!(token, Token::AnyString))
+    }
+
+    // A leading `.` has to be matched explicitly unless `dotglob` is set.
+    fn starts_with_dot(&self) -> bool {
+        matches!(self.tokens.first(), Some(Token::Literal('.')))
+    }
+}
+
+fn parse_class(chars: &[(char, bool)]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some(('!' | '^', false)));
+    if negated {
+        i += 1;
+    }
+    let mut items = Vec::new();
+    let start = i;
+    while i < chars.len() {
+        let (c, quoted) = chars[i];
+        if c == ']' && !quoted && i > start {
+            return Some((Token::Class { negated, items }, i + 1));
+        }
+        if c == '[' && !quoted && matches!(chars.get(i + 1), Some((':', false))) {
+            let rest: String = chars[i + 2..].iter().map(|(c, _)| *c).collect();
+            if let Some(end) = rest.find(":]") {
+                items.push(ClassItem::Named(rest[..end].to_string()));
+                i += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+        }
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some(('-', false)), Some(&(end, end_quoted))) if end != ']' || end_quoted => {
+                items.push(ClassItem::Range(c, end));
+                i += 3;
+            }
+            _ => {
+                items.push(ClassItem::Char(c));
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+fn token_matches(token: &Token, c: char, ignore_case: bool) -> bool {
+    let fold = |c: char| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    match token {
+        Token::Literal(expected) => fold(*expected) == fold(c),
+        Token::AnyChar => true,
+        Token::AnyString => false,
+        Token::Class { negated, items } => {
+            let found = items.iter().any(|item| match item {
+                ClassItem::Char(expected) => fold(*expected) == fold(c),
+                ClassItem::Range(from, to) => {
+                    (*from..=*to).contains(&c)
+                        || (ignore_case
+                            && ((*from..=*to).contains(&c.to_ascii_lowercase())
+                                || (*from..=*to).contains(&c.to_ascii_uppercase())))
+                }
+                ClassItem::Named(name) => match name.as_str() {
+                    "alpha" => c.is_alphabetic(),
+                    "digit" => c.is_ascii_digit(),
+                    "alnum" => c.is_alphanumeric(),
+                    "upper" => c.is_uppercase() || (ignore_case && c.is_lowercase()),
+                    "lower" => c.is_lowercase() || (ignore_case && c.is_uppercase()),
+                    "space" => c.is_whitespace(),
+                    "blank" => c == ' ' || c == '\t',
+                    "punct" => c.is_ascii_punctuation(),
+                    "xdigit" => c.is_ascii_hexdigit(),
+                    _ => false,
+                },
+            });
+            found != *negated
+        }
+    }
+}
+
+/// Returns `true` if the word contains an unquoted `*`, `?` or `[`.
+pub fn has_wildcards(chars: &[(char, bool)]) -> bool {
+    chars
+        .iter()
+        .any(|(c, quoted)| !quoted && matches!(c, '*' | '?' | '['))
+}
+
+/// Expands a glob pattern to the matching paths, sorted. Relative patterns are
+/// resolved against `base` and returned relative to it.
+pub fn expand(chars: &[(char, bool)], base: &Path, options: &GlobOptions) -> Vec<String> {
+    let components: Vec<&[(char, bool)]> = chars.split(|(c, _)| *c == '/').collect();
+    let mut candidates = vec![if chars.first().map(|(c, _)| *c) == Some('/') {
+        "/".to_string()
+    } else {
+        String::new()
+    }];
+    let last = components.len() - 1;
+    for (index, component) in components.iter().enumerate() {
+        if component.is_empty() {
+            // A trailing `/` restricts the matches to directories.
+            if index == last && index > 0 {
+                candidates.retain(|candidate| base.join(candidate).is_dir());
+                candidates
+                    .iter_mut()
+                    .for_each(|candidate| candidate.push('/'));
+            }
+            continue;
+        }
+        let mut next = Vec::new();
+        let is_globstar = options.globstar
+            && component.len() == 2
+            && component.iter().all(|&c| c == ('*', false));
+        for candidate in candidates.iter() {
+            if is_globstar {
+                if index != last {
+                    next.push(candidate.clone());
+                }
+                walk(base, candidate, index == last, options, &mut next);
+            } else if !has_wildcards(component) {
+                let name: String = component.iter().map(|(c, _)| *c).collect();
+                let path = join(candidate, &name);
+                if index != last || base.join(&path).symlink_metadata().is_ok() {
+                    next.push(path);
+                }
+            } else {
+                let pattern = Pattern::from_chars(component);
+                for name in list_directory(base, candidate) {
+                    if name.starts_with('.') && !options.dotglob && !pattern.starts_with_dot() {
+                        continue;
+                    }
+                    if pattern.matches(&name, options.nocaseglob) {
+                        next.push(join(candidate, &name));
+                    }
+                }
+            }
+        }
+        candidates = next;
+    }
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+// Collects everything below `directory` for `**`: only directories when more
+// components follow, files and directories otherwise.
+fn walk(
+    base: &Path,
+    directory: &str,
+    include_files: bool,
+    options: &GlobOptions,
+    found: &mut Vec<String>,
+) {
+    for name in list_directory(base, directory) {
+        if name.starts_with('.') && !options.dotglob {
+            continue;
+        }
+        let path = join(directory, &name);
+        let full_path = base.join(&path);
+        // Symlinked directories are not followed to avoid cycles.
+        let is_dir = full_path
+            .symlink_metadata()
+            .map(|metadata| metadata.is_dir())
+            .unwrap_or(false);
+        if is_dir {
+            found.push(path.clone());
+            walk(base, &path, include_files, options, found);
+        } else if include_files {
+            found.push(path);
+        }
+    }
+}
+
+fn list_directory(base: &Path, directory: &str) -> Vec<String> {
+    match std::fs::read_dir(base.join(directory)) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn join(directory: &str, name: &str) -> String {
+    if directory.is_empty() {
+        name.to_string()
+    } else if directory.ends_with('/') {
+        format!("{}{}", directory, name)
+    } else {
+        format!("{}/{}", directory, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unquoted(pattern: &str) -> Vec<(char, bool)> {
+        pattern.chars().map(|c| (c, false)).collect()
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        let test_cases = [
+            ("*.txt", "notes.txt", true),
+            ("*.txt", "notes.txt.bak", false),
+            ("file?.log", "file1.log", true),
+            ("file?.log", "file10.log", false),
+            ("[a-c]*", "banana", true),
+            ("[!a-c]*", "banana", false),
+            ("[[:digit:]][[:upper:]]", "1A", true),
+            ("[]x]", "]", true),
+            ("a*b*c", "aXXbYYbc", true),
+            ("[abc", "[abc", true),
+        ];
+
+        for (pattern, name, expected) in test_cases.iter() {
+            let compiled = Pattern::from_chars(&unquoted(pattern));
+            assert_eq!(
+                compiled.matches(name, false),
+                *expected,
+                "{} {}",
+                pattern,
+                name
+            );
+        }
+        assert!(Pattern::from_chars(&unquoted("*.TXT")).matches("a.txt", true));
+        assert!(!Pattern::from_chars(&[('*', true)]).matches("a", false));
+        assert!(Pattern::new(r"\*.rs").matches("*.rs", false));
+        assert!(!Pattern::new(r"\*.rs").matches("main.rs", false));
+    }
+
+    #[test]
+    fn test_expand() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        for name in [
+            "b.txt",
+            "a.txt",
+            ".hidden.txt",
+            "c.log",
+            "sub/d.txt",
+            "sub/deep/e.txt",
+        ] {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::File::create(path).unwrap();
+        }
+
+        let mut options = GlobOptions::default();
+        let test_cases = [
+            ("*.txt", vec!["a.txt", "b.txt"]),
+            (".*.txt", vec![".hidden.txt"]),
+            ("*/*.txt", vec!["sub/d.txt"]),
+            ("*/", vec!["sub/"]),
+            ("*.md", vec![]),
+        ];
+        for (pattern, expected) in test_cases.iter() {
+            assert_eq!(
+                expand(&unquoted(pattern), temp_dir.path(), &options),
+                *expected
+            );
+        }
+
+        options.dotglob = true;
+        assert_eq!(
+            expand(&unquoted("*.txt"), temp_dir.path(), &options),
+            vec![".hidden.txt", "a.txt", "b.txt"]
+        );
+
+        options.dotglob = false;
+        options.globstar = true;
+        assert_eq!(
+            expand(&unquoted("**/*.txt"), temp_dir.path(), &options),
+            vec!["a.txt", "b.txt", "sub/d.txt", "sub/deep/e.txt"]
+        );
+        temp_dir.close().unwrap();
+    }
+}
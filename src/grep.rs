@@ -0,0 +1,978 @@
+// Option parsing, line matching and recursive search for `grep`.
+//
+// Basic (default) and extended (`-E`) POSIX regular expressions are both
+// translated to the syntax of the `regex` crate.
+
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+
+use regex::{Match, Regex, RegexBuilder};
+
+use crate::glob::Pattern;
+use crate::ls::ColorMode;
+
+// The default GREP_COLORS: matches, file names, line numbers and separators.
+const MATCH_COLOR: &str = "01;31";
+const FILE_NAME_COLOR: &str = "35";
+const LINE_NUMBER_COLOR: &str = "32";
+const SEPARATOR_COLOR: &str = "36";
+
+#[derive(Default)]
+pub struct GrepOptions {
+    pub extended: bool,
+    pub fixed_strings: bool,
+    pub ignore_case: bool,
+    pub invert: bool,
+    pub line_number: bool,
+    pub count: bool,
+    pub files_with_matches: bool,
+    pub word: bool,
+    pub only_matching: bool,
+    pub quiet: bool,
+    // `-H`/`-h`; by default file names are shown when there are several files.
+    pub with_filename: Option<bool>,
+    pub patterns: Vec<String>,
+    // `-r` skips symlinks found while recursing, `-R` follows them.
+    pub recursive: bool,
+    pub dereference_recursive: bool,
+    // Globs matched against the base name of files and directories found
+    // while recursing.
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+    pub exclude_dir: Vec<Pattern>,
+    // `--gitignore`: skip what `.gitignore` files ignore, and `.git` itself.
+    pub gitignore: bool,
+    // Lines of context from `-A`/`-B`, falling back to `-C` (or `-NUM`).
+    pub after_context: Option<usize>,
+    pub before_context: Option<usize>,
+    pub context: usize,
+    pub color: ColorMode,
+    // Resolved by the caller from `color` once it knows where output goes.
+    pub colored: bool,
+}
+
+impl GrepOptions {
+    /// Splits `grep` arguments into options and file operands. Unless given
+    /// with `-e`, the first operand is the pattern.
+    pub fn parse(arguments: &[String]) -> Result<(GrepOptions, Vec<String>), &'static str> {
+        let mut options = GrepOptions::default();
+        let mut operands = Vec::new();
+        let mut end_of_options = false;
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            if end_of_options || argument == "-" || !argument.starts_with('-') {
+                operands.push(argument.clone());
+                continue;
+            }
+            if argument == "--" {
+                end_of_options = true;
+                continue;
+            }
+            if let Some(name) = argument.strip_prefix("--") {
+                options.set_long_option(name)?;
+                continue;
+            }
+            let flags = &argument[1..];
+            // `-NUM` is the same as `-C NUM`.
+            if flags.chars().all(|c| c.is_ascii_digit()) {
+                options.context = parse_context(flags)?;
+                continue;
+            }
+            for (index, flag) in flags.char_indices() {
+                if !matches!(flag, 'e' | 'A' | 'B' | 'C') {
+                    options.set_flag(flag)?;
+                    continue;
+                }
+                // These take the rest of the argument or the next one.
+                let value = match &flags[index + 1..] {
+                    "" => arguments
+                        .next()
+                        .ok_or("grep: option requires an argument")?,
+                    rest => rest,
+                };
+                match flag {
+                    'A' => options.after_context = Some(parse_context(value)?),
+                    'B' => options.before_context = Some(parse_context(value)?),
+                    'C' => options.context = parse_context(value)?,
+                    _ => options.patterns.push(value.to_string()),
+                }
+                break;
+            }
+        }
+        if options.patterns.is_empty() {
+            if operands.is_empty() {
+                return Err(
+                    "Invalid grep command. Correct usage: `grep [options] <pattern> [file...]`",
+                );
+            }
+            options.patterns.push(operands.remove(0));
+        }
+        Ok((options, operands))
+    }
+
+    fn set_flag(&mut self, flag: char) -> Result<(), &'static str> {
+        match flag {
+            'E' => self.extended = true,
+            'G' => self.extended = false,
+            'F' => self.fixed_strings = true,
+            'i' => self.ignore_case = true,
+            'v' => self.invert = true,
+            'n' => self.line_number = true,
+            'c' => self.count = true,
+            'l' => self.files_with_matches = true,
+            'w' => self.word = true,
+            'o' => self.only_matching = true,
+            'q' => self.quiet = true,
+            'H' => self.with_filename = Some(true),
+            'h' => self.with_filename = Some(false),
+            'r' => self.recursive = true,
+            'R' => {
+                self.recursive = true;
+                self.dereference_recursive = true;
+            }
+            _ => return Err("grep: invalid option. Supported options: -EFGivncelwoqHhrR"),
+        }
+        Ok(())
+    }
+
+    fn set_long_option(&mut self, name: &str) -> Result<(), &'static str> {
+        if let Some(pattern) = name.strip_prefix("regexp=") {
+            self.patterns.push(pattern.to_string());
+            return Ok(());
+        }
+        if let Some((option, value)) = name.split_once('=') {
+            match option {
+                "include" => self.include.push(Pattern::new(value)),
+                "exclude" => self.exclude.push(Pattern::new(value)),
+                "exclude-dir" => self.exclude_dir.push(Pattern::new(value)),
+                "after-context" => self.after_context = Some(parse_context(value)?),
+                "before-context" => self.before_context = Some(parse_context(value)?),
+                "context" => self.context = parse_context(value)?,
+                "color" | "colour" => {
+                    self.color = match value {
+                        "always" => ColorMode::Always,
+                        "auto" => ColorMode::Auto,
+                        "never" => ColorMode::Never,
+                        _ => return Err("grep: invalid color. Use always, auto or never"),
+                    }
+                }
+                _ => return Err("grep: unrecognized option"),
+            }
+            return Ok(());
+        }
+        match name {
+            "color" | "colour" => self.color = ColorMode::Auto,
+            "extended-regexp" => self.extended = true,
+            "basic-regexp" => self.extended = false,
+            "fixed-strings" => self.fixed_strings = true,
+            "ignore-case" => self.ignore_case = true,
+            "invert-match" => self.invert = true,
+            "line-number" => self.line_number = true,
+            "count" => self.count = true,
+            "files-with-matches" => self.files_with_matches = true,
+            "word-regexp" => self.word = true,
+            "only-matching" => self.only_matching = true,
+            "quiet" | "silent" => self.quiet = true,
+            "with-filename" => self.with_filename = Some(true),
+            "no-filename" => self.with_filename = Some(false),
+            "recursive" => self.recursive = true,
+            "dereference-recursive" => {
+                self.recursive = true;
+                self.dereference_recursive = true;
+            }
+            "gitignore" => self.gitignore = true,
+            _ => return Err("grep: unrecognized option"),
+        }
+        Ok(())
+    }
+
+    pub fn after(&self) -> usize {
+        self.after_context.unwrap_or(self.context)
+    }
+
+    pub fn before(&self) -> usize {
+        self.before_context.unwrap_or(self.context)
+    }
+
+    /// Whether lines are printed in groups separated by `--`.
+    pub fn shows_context(&self) -> bool {
+        (self.after() > 0 || self.before() > 0)
+            && !(self.count || self.files_with_matches || self.quiet || self.only_matching)
+    }
+
+    /// The `--` line between groups of context.
+    pub fn group_separator(&self) -> String {
+        paint("--", SEPARATOR_COLOR, self.colored)
+    }
+
+    /// Compiles the patterns into a single regular expression matching any
+    /// of them. The matched text is its first group: with `-w` the match also
+    /// takes in the characters around the word.
+    pub fn regex(&self) -> Result<Regex, &'static str> {
+        let alternatives: Vec<String> = self
+            .patterns
+            .iter()
+            // A pattern containing newlines is a list of patterns.
+            .flat_map(|pattern| pattern.split('\n'))
+            .map(|pattern| {
+                let pattern = if self.fixed_strings {
+                    regex::escape(pattern)
+                } else {
+                    translate(pattern, self.extended)
+                };
+                format!("(?:{})", pattern)
+            })
+            .collect();
+        // `\b` would need a word character at each end of the pattern, so a
+        // word is told apart by the characters next to it instead.
+        let pattern = match self.word {
+            true => format!(r"(?:^|\W)({})(?:\W|$)", alternatives.join("|")),
+            false => format!("({})", alternatives.join("|")),
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .map_err(|_| "grep: invalid regular expression")
+    }
+}
+
+fn parse_context(value: &str) -> Result<usize, &'static str> {
+    value
+        .parse::<usize>()
+        .map_err(|_| "grep: invalid context length argument")
+}
+
+fn paint(text: &str, color: &str, colored: bool) -> String {
+    if colored {
+        format!("\x1b[{}m\x1b[K{}\x1b[m\x1b[K", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Rewrites a POSIX regular expression in `regex` crate syntax. In basic
+/// syntax `\( \) \{ \} \| \+ \?` are the operators and the bare characters
+/// are literal; extended syntax is the other way round.
+pub fn translate(pattern: &str, extended: bool) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut translated = String::new();
+    let mut i = 0;
+    // Whether a `*` here has nothing to repeat and is therefore literal.
+    let mut at_start = true;
+    while i < chars.len() {
+        let c = chars[i];
+        let was_at_start = at_start;
+        at_start = false;
+        match c {
+            '[' => i = translate_bracket(&chars, i, &mut translated),
+            '\\' if i + 1 < chars.len() => {
+                let next = chars[i + 1];
+                i += 1;
+                match next {
+                    '(' | ')' | '{' | '}' | '|' | '+' | '?' if !extended => {
+                        translated.push(next);
+                        at_start = next == '(' || next == '|';
+                    }
+                    // Back-references are kept so that the regex crate, which
+                    // does not support them, reports the pattern as invalid.
+                    '<' | '>' | 'b' | 'B' | 'w' | 'W' | 's' | 'S' | '1'..='9' => {
+                        translated.push('\\');
+                        translated.push(next);
+                    }
+                    c => translated.push_str(&regex::escape(&c.to_string())),
+                }
+            }
+            '\\' => translated.push_str(r"\\"),
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' if !extended => {
+                translated.push('\\');
+                translated.push(c);
+            }
+            '(' | '|' if extended => {
+                translated.push(c);
+                at_start = true;
+            }
+            '*' if was_at_start => translated.push_str(r"\*"),
+            '^' if !extended && !was_at_start => translated.push_str(r"\^"),
+            '^' => {
+                translated.push('^');
+                at_start = true;
+            }
+            '$' if !extended && i + 1 < chars.len() && !ends_group(&chars[i + 1..]) => {
+                translated.push_str(r"\$")
+            }
+            c => translated.push(c),
+        }
+        i += 1;
+    }
+    translated
+}
+
+// In basic syntax `$` is an anchor only at the end of the pattern or of a
+// group or alternative.
+fn ends_group(rest: &[char]) -> bool {
+    matches!(rest, ['\\', ')' | '|', ..])
+}
+
+// Copies a bracket expression starting at `chars[start]`, escaping what the
+// regex crate would otherwise treat specially. Returns the index of the
+// closing `]`, or treats the `[` as literal when there is none.
+fn translate_bracket(chars: &[char], start: usize, translated: &mut String) -> usize {
+    let mut i = start + 1;
+    let mut body = String::from("[");
+    if chars.get(i) == Some(&'^') {
+        body.push('^');
+        i += 1;
+    }
+    let first = i;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ']' if i > first => {
+                body.push(']');
+                translated.push_str(&body);
+                return i;
+            }
+            '[' if matches!(chars.get(i + 1), Some(':' | '.' | '=')) => {
+                let delimiter = chars[i + 1];
+                let rest: String = chars[i + 2..].iter().collect();
+                let Some(end) = rest.find(&format!("{}]", delimiter)) else {
+                    body.push_str(r"\[");
+                    i += 1;
+                    continue;
+                };
+                let name = &rest[..end];
+                if delimiter == ':' {
+                    body.push_str(&format!("[:{}:]", name));
+                } else {
+                    body.push_str(&regex::escape(name));
+                }
+                i += 2 + name.chars().count() + 2;
+                continue;
+            }
+            // Backslashes are literal inside POSIX brackets.
+            '\\' | '[' | '&' | '~' => {
+                body.push('\\');
+                body.push(c);
+            }
+            ']' => body.push_str(r"\]"),
+            c => body.push(c),
+        }
+        i += 1;
+    }
+    translated.push_str(r"\[");
+    start
+}
+
+pub enum Input {
+    Stdin,
+    // A file opened by the caller, or one found while recursing that is
+    // opened by the worker searching it.
+    File(File),
+    Path(PathBuf),
+}
+
+pub struct Source {
+    pub name: String,
+    pub input: Input,
+}
+
+#[derive(Default)]
+pub struct SearchResult {
+    pub matched: bool,
+    pub error: Option<&'static str>,
+}
+
+// What a worker reports about the source it is searching.
+enum Event {
+    Output(Vec<u8>),
+    Done(SearchResult),
+}
+
+// Passes each line a worker writes on to the thread that puts the output of
+// the sources in order.
+struct EventWriter<'a> {
+    index: usize,
+    sender: &'a Sender<(usize, Event)>,
+}
+
+impl Write for EventWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender
+            .send((self.index, Event::Output(buf.to_vec())))
+            .map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Searches the sources on a pool of worker threads, writing the output to
+/// `output` in the order of `sources` whichever thread finished first. The
+/// output of the first unfinished source is written as it is found; the
+/// others are held back until their turn. Returns the result of each source.
+pub fn search_all(
+    sources: Vec<Source>,
+    stdin: &[u8],
+    show_name: bool,
+    regex: &Regex,
+    options: &GrepOptions,
+    output: &mut impl Write,
+) -> io::Result<Vec<SearchResult>> {
+    let workers = std::thread::available_parallelism()
+        .map(|workers| workers.get())
+        .unwrap_or(1)
+        .min(sources.len());
+    let names: Vec<String> = sources.iter().map(|source| source.name.clone()).collect();
+    let inputs: Vec<Mutex<Option<Input>>> = sources
+        .into_iter()
+        .map(|source| Mutex::new(Some(source.input)))
+        .collect();
+    let next = AtomicUsize::new(0);
+    // With `-q` the first match settles the exit status; the rest is skipped.
+    let done = AtomicBool::new(false);
+    let mut pending: Vec<Vec<u8>> = names.iter().map(|_| Vec::new()).collect();
+    let mut results: Vec<Option<SearchResult>> = names.iter().map(|_| None).collect();
+    let written = std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (names, inputs, next, done) = (&names, &inputs, &next, &done);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= inputs.len() || done.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Some(input) = inputs[index].lock().unwrap().take() else {
+                    continue;
+                };
+                let mut result = SearchResult::default();
+                // Only files found while recursing skip binary content.
+                let reader: io::Result<(Box<dyn BufRead>, bool)> = match input {
+                    Input::Stdin => Ok((Box::new(stdin), false)),
+                    Input::File(file) => Ok((Box::new(BufReader::new(file)), false)),
+                    Input::Path(path) => {
+                        File::open(path).map(|file| (Box::new(BufReader::new(file)) as _, true))
+                    }
+                };
+                match reader {
+                    Ok((reader, skip_binary)) => {
+                        let name = &names[index];
+                        let mut writer = EventWriter {
+                            index,
+                            sender: &sender,
+                        };
+                        match search(
+                            reader,
+                            name,
+                            show_name,
+                            skip_binary,
+                            regex,
+                            options,
+                            &mut writer,
+                        ) {
+                            Ok(matched) => result.matched = matched,
+                            Err(_) => result.error = Some("Failed to read from file"),
+                        }
+                    }
+                    Err(_) => result.error = Some("Failed to open file"),
+                }
+                if result.matched && options.quiet {
+                    done.store(true, Ordering::SeqCst);
+                }
+                let _ignored = sender.send((index, Event::Done(result)));
+            });
+        }
+        drop(sender);
+
+        // The source whose output is being written, and whether it has
+        // written any yet.
+        let mut current = 0;
+        let mut started = false;
+        let mut printed = false;
+        let mut written = Ok(());
+        for (index, event) in receiver {
+            match event {
+                Event::Output(bytes) => pending[index].extend(bytes),
+                Event::Done(result) => results[index] = Some(result),
+            }
+            while current < pending.len() {
+                if !pending[current].is_empty() && written.is_ok() {
+                    // Groups of context from different sources are separated too.
+                    if !started && printed && options.shows_context() {
+                        written = writeln!(output, "{}", options.group_separator());
+                    }
+                    written = written.and_then(|_| output.write_all(&pending[current]));
+                    if written.is_err() {
+                        done.store(true, Ordering::SeqCst);
+                    }
+                    started = true;
+                    printed = true;
+                }
+                pending[current].clear();
+                if results[current].is_none() {
+                    break;
+                }
+                current += 1;
+                started = false;
+            }
+        }
+        written
+    });
+    written?;
+    Ok(results
+        .into_iter()
+        .map(|result| result.unwrap_or_default())
+        .collect())
+}
+
+// The non-empty matches in `line`, which are the first group of `regex`.
+// Searching on from the end of each match rather than of the whole `-w`
+// match lets two words share the character between them.
+fn find_matches<'a>(regex: &Regex, line: &'a str) -> Vec<Match<'a>> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while let Some(found) = regex.captures_at(line, start).and_then(|x| x.get(1)) {
+        if found.is_empty() {
+            match line[found.end()..].chars().next() {
+                Some(c) => start = found.end() + c.len_utf8(),
+                None => break,
+            }
+            continue;
+        }
+        start = found.end();
+        matches.push(found);
+    }
+    matches
+}
+
+// Formats output lines as `name:number:line` for selected lines and
+// `name-number-line` for context lines.
+struct Printer<'a> {
+    name: &'a str,
+    show_name: bool,
+    regex: &'a Regex,
+    options: &'a GrepOptions,
+}
+
+impl Printer<'_> {
+    fn prefix(&self, line_number: usize, separator: &str) -> String {
+        let colored = self.options.colored;
+        let separator = paint(separator, SEPARATOR_COLOR, colored);
+        let mut prefix = String::new();
+        if self.show_name {
+            prefix.push_str(&paint(self.name, FILE_NAME_COLOR, colored));
+            prefix.push_str(&separator);
+        }
+        if self.options.line_number {
+            prefix.push_str(&paint(&line_number.to_string(), LINE_NUMBER_COLOR, colored));
+            prefix.push_str(&separator);
+        }
+        prefix
+    }
+
+    fn line(&self, line_number: usize, line: &str, selected: bool) -> String {
+        let separator = if selected { ":" } else { "-" };
+        format!(
+            "{}{}",
+            self.prefix(line_number, separator),
+            self.highlight(line)
+        )
+    }
+
+    // Colors every non-empty match in the line.
+    fn highlight(&self, line: &str) -> String {
+        if !self.options.colored {
+            return line.to_string();
+        }
+        let mut highlighted = String::new();
+        let mut end = 0;
+        for found in find_matches(self.regex, line) {
+            highlighted.push_str(&line[end..found.start()]);
+            highlighted.push_str(&paint(found.as_str(), MATCH_COLOR, true));
+            end = found.end();
+        }
+        highlighted.push_str(&line[end..]);
+        highlighted
+    }
+}
+
+/// Searches `reader` line by line, writing the output lines to `output` as
+/// they are found. Lines are prefixed with `name` when `show_name` is set.
+/// Returns whether any line was selected.
+///
+/// Input with a NUL byte near the start is treated as binary: it is skipped
+/// when `skip_binary` is set, and otherwise only reported as matching.
+pub fn search(
+    mut reader: impl BufRead,
+    name: &str,
+    show_name: bool,
+    skip_binary: bool,
+    regex: &Regex,
+    options: &GrepOptions,
+    output: &mut impl Write,
+) -> io::Result<bool> {
+    let binary = reader.fill_buf()?.contains(&0);
+    if binary && skip_binary {
+        return Ok(false);
+    }
+    let printer = Printer {
+        name,
+        show_name,
+        regex,
+        options,
+    };
+    let show_context = options.shows_context();
+    // Lines kept for `-B`, and how many more lines `-A` still prints.
+    let mut before: VecDeque<(usize, String)> = VecDeque::new();
+    let mut after_remaining = 0;
+    let mut last_printed: Option<usize> = None;
+    let mut count = 0;
+    let mut line_number = 0;
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        line_number += 1;
+        if buffer.last() == Some(&b'\n') {
+            buffer.pop();
+        }
+        let line = String::from_utf8_lossy(&buffer);
+        if regex.is_match(&line) == options.invert {
+            if !show_context {
+                continue;
+            }
+            if after_remaining > 0 {
+                after_remaining -= 1;
+                writeln!(output, "{}", printer.line(line_number, &line, false))?;
+                last_printed = Some(line_number);
+            } else if options.before() > 0 {
+                if before.len() == options.before() {
+                    before.pop_front();
+                }
+                before.push_back((line_number, line.into_owned()));
+            }
+            continue;
+        }
+        count += 1;
+        if options.quiet || options.files_with_matches || binary {
+            break;
+        }
+        if options.count {
+            continue;
+        }
+        if options.only_matching {
+            if !options.invert {
+                for found in find_matches(regex, &line) {
+                    writeln!(
+                        output,
+                        "{}{}",
+                        printer.prefix(line_number, ":"),
+                        paint(found.as_str(), MATCH_COLOR, options.colored)
+                    )?;
+                }
+            }
+            continue;
+        }
+        if show_context {
+            let first = before.front().map_or(line_number, |(number, _)| *number);
+            if matches!(last_printed, Some(last) if first > last + 1) {
+                writeln!(output, "{}", options.group_separator())?;
+            }
+            for (number, context) in before.drain(..) {
+                writeln!(output, "{}", printer.line(number, &context, false))?;
+            }
+            after_remaining = options.after();
+            last_printed = Some(line_number);
+        }
+        writeln!(output, "{}", printer.line(line_number, &line, true))?;
+    }
+    if options.quiet {
+        return Ok(count > 0);
+    }
+    let colored_name = paint(name, FILE_NAME_COLOR, options.colored);
+    if options.files_with_matches {
+        if count > 0 {
+            writeln!(output, "{}", colored_name)?;
+        }
+    } else if options.count {
+        if show_name {
+            let separator = paint(":", SEPARATOR_COLOR, options.colored);
+            writeln!(output, "{}{}{}", colored_name, separator, count)?;
+        } else {
+            writeln!(output, "{}", count)?;
+        }
+    } else if binary && count > 0 {
+        writeln!(output, "Binary file {} matches", name)?;
+    }
+    Ok(count > 0)
+}
+
+// A line of a `.gitignore` file.
+struct IgnoreRule {
+    pattern: Pattern,
+    negated: bool,
+    directory_only: bool,
+    // Patterns containing a `/` match paths relative to the `.gitignore`,
+    // the others match the name at any depth.
+    anchored: bool,
+}
+
+struct IgnoreFile {
+    directory: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreFile {
+    fn read(directory: &Path) -> Option<IgnoreFile> {
+        let content = std::fs::read_to_string(directory.join(".gitignore")).ok()?;
+        let mut rules = Vec::new();
+        for line in content.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let (directory_only, line) = match line.strip_suffix('/') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            rules.push(IgnoreRule {
+                pattern: Pattern::new(line.trim_start_matches('/')),
+                negated,
+                directory_only,
+                anchored,
+            });
+        }
+        Some(IgnoreFile {
+            directory: directory.to_path_buf(),
+            rules,
+        })
+    }
+}
+
+/// Collects the files below `directory` for `-r`, in name order. `name` is
+/// the directory as shown in the output; errors are appended to `errors`.
+pub fn collect_files(
+    directory: &Path,
+    name: &str,
+    options: &GrepOptions,
+    sources: &mut Vec<Source>,
+    errors: &mut Vec<String>,
+) {
+    let mut walker = Walker {
+        options,
+        ignore_files: Vec::new(),
+        ancestors: HashSet::new(),
+        sources,
+        errors,
+    };
+    walker.walk(directory, name);
+}
+
+struct Walker<'a> {
+    options: &'a GrepOptions,
+    // The `.gitignore` files of the directories being walked, outermost first.
+    ignore_files: Vec<IgnoreFile>,
+    // Device and inode of the directories being walked, so that `-R` does not
+    // loop on a symlink back to one of them.
+    ancestors: HashSet<(u64, u64)>,
+    sources: &'a mut Vec<Source>,
+    errors: &'a mut Vec<String>,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, directory: &Path, name: &str) {
+        let Ok(metadata) = std::fs::metadata(directory) else {
+            return;
+        };
+        let key = (metadata.dev(), metadata.ino());
+        if !self.ancestors.insert(key) {
+            self.errors
+                .push(format!("grep: {}: warning: recursive directory loop", name));
+            return;
+        }
+        self.walk_entries(directory, name);
+        self.ancestors.remove(&key);
+    }
+
+    fn walk_entries(&mut self, directory: &Path, name: &str) {
+        let read_dir = match std::fs::read_dir(directory) {
+            Ok(read_dir) => read_dir,
+            Err(_) => {
+                self.errors
+                    .push(format!("grep: {}: Permission denied", name));
+                return;
+            }
+        };
+        let pushed_ignore_file = match IgnoreFile::read(directory) {
+            Some(ignore_file) if self.options.gitignore => {
+                self.ignore_files.push(ignore_file);
+                true
+            }
+            _ => false,
+        };
+        let mut entries: Vec<_> = read_dir.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let entry_name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            let display = if name.is_empty() {
+                entry_name.clone()
+            } else if name.ends_with('/') {
+                format!("{}{}", name, entry_name)
+            } else {
+                format!("{}/{}", name, entry_name)
+            };
+            let metadata = match entry.file_type() {
+                Ok(file_type) if file_type.is_symlink() => {
+                    if !self.options.dereference_recursive {
+                        continue;
+                    }
+                    match std::fs::metadata(&path) {
+                        Ok(metadata) => metadata,
+                        Err(_) => continue,
+                    }
+                }
+                Ok(_) => match entry.metadata() {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                },
+                Err(_) => continue,
+            };
+            let is_dir = metadata.is_dir();
+            if self.is_ignored(&path, &entry_name, is_dir) {
+                continue;
+            }
+            if is_dir {
+                let excluded = self
+                    .options
+                    .exclude_dir
+                    .iter()
+                    .any(|pattern| pattern.matches(&entry_name, false));
+                if !excluded {
+                    self.walk(&path, &display);
+                }
+                continue;
+            }
+            // FIFOs and devices found while recursing are not read.
+            if !metadata.is_file() {
+                continue;
+            }
+            let included = self.options.include.is_empty()
+                || self
+                    .options
+                    .include
+                    .iter()
+                    .any(|pattern| pattern.matches(&entry_name, false));
+            let excluded = self
+                .options
+                .exclude
+                .iter()
+                .any(|pattern| pattern.matches(&entry_name, false));
+            if included && !excluded {
+                self.sources.push(Source {
+                    name: display,
+                    input: Input::Path(path),
+                });
+            }
+        }
+        if pushed_ignore_file {
+            self.ignore_files.pop();
+        }
+    }
+
+    fn is_ignored(&self, path: &Path, name: &str, is_dir: bool) -> bool {
+        if !self.options.gitignore {
+            return false;
+        }
+        if name == ".git" && is_dir {
+            return true;
+        }
+        // The last matching rule wins, and deeper files override outer ones.
+        let mut ignored = false;
+        for ignore_file in self.ignore_files.iter() {
+            let Ok(relative) = path.strip_prefix(&ignore_file.directory) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy();
+            for rule in ignore_file.rules.iter() {
+                if rule.directory_only && !is_dir {
+                    continue;
+                }
+                let subject = if rule.anchored {
+                    relative.as_ref()
+                } else {
+                    name
+                };
+                if rule.pattern.matches(subject, false) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        let test_cases = [
+            (r"a\(b\|c\)*", false, "a(b|c)*"),
+            ("a(b|c)+", false, r"a\(b\|c\)\+"),
+            ("a(b|c)+", true, "a(b|c)+"),
+            (r"x\{2,3\}", false, "x{2,3}"),
+            ("*a", false, r"\*a"),
+            ("a^b$c$", false, r"a\^b\$c$"),
+            (r"[\]x]", false, r"[\\]x]"),
+            ("[]a]", true, r"[\]a]"),
+            ("[[:digit:]]+", true, "[[:digit:]]+"),
+            (r"\.txt", true, r"\.txt"),
+            (r"\<word\>", false, r"\<word\>"),
+            ("[abc", false, r"\[abc"),
+        ];
+        for (pattern, extended, expected) in test_cases.iter() {
+            assert_eq!(translate(pattern, *extended), *expected, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let arguments: Vec<String> = ["-inE", "-e", "x", "-efoo", "a.txt", "--", "-b.txt"]
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        let (options, operands) = GrepOptions::parse(&arguments).unwrap();
+        assert!(options.ignore_case && options.line_number && options.extended);
+        assert_eq!(options.patterns, vec!["x", "foo"]);
+        assert_eq!(operands, vec!["a.txt", "-b.txt"]);
+        assert!(GrepOptions::parse(&["-z".to_string(), "x".to_string()]).is_err());
+        assert!(GrepOptions::parse(&["-n".to_string()]).is_err());
+
+        let arguments: Vec<String> = ["-A1", "-C", "3", "--color=always", "x"]
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        let (options, _) = GrepOptions::parse(&arguments).unwrap();
+        assert_eq!((options.after(), options.before()), (1, 3));
+        assert!(options.color == ColorMode::Always);
+        let (options, _) = GrepOptions::parse(&["-2".to_string(), "x".to_string()]).unwrap();
+        assert_eq!((options.after(), options.before()), (2, 2));
+    }
+}
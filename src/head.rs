@@ -0,0 +1,166 @@
+// Option parsing and streaming for `head`; the counts are shared with `tail`.
+
+use std::io::{self, BufRead, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Count {
+    Lines(u64),
+    Bytes(u64),
+}
+
+pub struct HeadOptions {
+    pub count: Count,
+    // `Some(true)` for `-v`, `Some(false)` for `-q`; otherwise headers are
+    // shown when there are several files.
+    pub headers: Option<bool>,
+}
+
+impl HeadOptions {
+    /// Splits `head` arguments into options and operands. Takes `-n N`,
+    /// `-c N`, `-N` and the long forms.
+    pub fn parse(arguments: &[String]) -> Result<(HeadOptions, Vec<String>), &'static str> {
+        let mut options = HeadOptions {
+            count: Count::Lines(10),
+            headers: None,
+        };
+        let operands = parse_arguments(arguments, "head", |flag, value| {
+            match flag {
+                'q' => options.headers = Some(false),
+                'v' => options.headers = Some(true),
+                'n' | 'c' => {
+                    let count = value
+                        .and_then(|value| value.parse().ok())
+                        .ok_or("head: invalid number of lines or bytes")?;
+                    options.count = match flag {
+                        'n' => Count::Lines(count),
+                        _ => Count::Bytes(count),
+                    };
+                }
+                _ => return Err("head: invalid option. Supported options: -n -c -q -v"),
+            }
+            Ok(())
+        })?;
+        Ok((options, operands))
+    }
+}
+
+/// The option loop of `head` and `tail`. `set` gets each flag and, for `-n`,
+/// `-c` and `tail -s`, its value (`-5` is `-n 5`).
+pub fn parse_arguments(
+    arguments: &[String],
+    command: &str,
+    mut set: impl FnMut(char, Option<&str>) -> Result<(), &'static str>,
+) -> Result<Vec<String>, &'static str> {
+    let mut operands = Vec::new();
+    let mut end_of_options = false;
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        if end_of_options || argument == "-" || !argument.starts_with('-') {
+            operands.push(argument.clone());
+            continue;
+        }
+        if argument == "--" {
+            end_of_options = true;
+            continue;
+        }
+        if let Some(long) = argument.strip_prefix("--") {
+            match long.split_once('=') {
+                Some(("lines", value)) => set('n', Some(value))?,
+                Some(("bytes", value)) => set('c', Some(value))?,
+                None if long == "quiet" || long == "silent" => set('q', None)?,
+                None if long == "verbose" => set('v', None)?,
+                _ if command == "tail" => match long.split_once('=') {
+                    Some(("sleep-interval", value)) => set('s', Some(value))?,
+                    Some(("follow", "name")) => set('F', None)?,
+                    Some(("follow", "descriptor")) => set('f', None)?,
+                    None if long == "follow" => set('f', None)?,
+                    _ => return Err("tail: unrecognized option"),
+                },
+                _ => return Err("head: unrecognized option"),
+            }
+            continue;
+        }
+        if argument[1..].starts_with(|x: char| x.is_ascii_digit()) {
+            set('n', Some(&argument[1..]))?;
+            continue;
+        }
+        let takes_value = |flag| flag == 'n' || flag == 'c' || (flag == 's' && command == "tail");
+        let flags: Vec<char> = argument[1..].chars().collect();
+        for (index, &flag) in flags.iter().enumerate() {
+            if !takes_value(flag) {
+                set(flag, None)?;
+                continue;
+            }
+            let attached: String = flags[index + 1..].iter().collect();
+            match attached.is_empty() {
+                true => set(flag, arguments.next().map(|x| x.as_str()))?,
+                false => set(flag, Some(&attached))?,
+            }
+            break;
+        }
+    }
+    Ok(operands)
+}
+
+/// Copies the first lines or bytes of `reader` to `output`, reading no
+/// further than needed.
+pub fn head(reader: &mut dyn BufRead, count: Count, output: &mut dyn Write) -> io::Result<()> {
+    let mut remaining = match count {
+        Count::Lines(count) | Count::Bytes(count) => count,
+    };
+    while remaining > 0 {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        let end = match count {
+            Count::Lines(_) => {
+                let mut end = chunk.len();
+                for (index, _) in chunk.iter().enumerate().filter(|(_, &x)| x == b'\n') {
+                    remaining -= 1;
+                    if remaining == 0 {
+                        end = index + 1;
+                        break;
+                    }
+                }
+                end
+            }
+            Count::Bytes(_) => {
+                let end = chunk.len().min(remaining as usize);
+                remaining -= end as u64;
+                end
+            }
+        };
+        output.write_all(&chunk[..end])?;
+        reader.consume(end);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_head() {
+        let test_cases: [(&[&str], &[u8]); 5] = [
+            (&[], b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n"),
+            (&["-n", "2"], b"1\n2\n"),
+            (&["-3"], b"1\n2\n3\n"),
+            (&["-c5"], b"1\n2\n3"),
+            (&["--lines=0"], b""),
+        ];
+        let input: Vec<u8> = (1..=12)
+            .flat_map(|x| format!("{}\n", x).into_bytes())
+            .collect();
+        for (arguments, expected) in test_cases.iter() {
+            let arguments: Vec<String> = arguments.iter().map(|x| x.to_string()).collect();
+            let (options, _) = HeadOptions::parse(&arguments).unwrap();
+            let mut output = Vec::new();
+            head(&mut &input[..], options.count, &mut output).unwrap();
+            assert_eq!(output, *expected, "{:?}", arguments);
+        }
+        assert!(HeadOptions::parse(&["-n".to_string(), "x".to_string()]).is_err());
+        assert!(HeadOptions::parse(&["-z".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,543 @@
+// Option parsing, sorting and formatting for `ls`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs::Metadata;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local};
+
+// Timestamps older than this (or in the future) show the year instead of the
+// time of day.
+const RECENT: Duration = Duration::from_secs(365 * 24 * 60 * 60 / 2);
+
+// Used when LS_COLORS is not set; LS_COLORS entries override these.
+const DEFAULT_COLORS: &str =
+    "di=01;34:ln=01;36:or=40;31;01:pi=40;33:so=01;35:bd=40;33;01:cd=40;33;01:ex=01;32:\
+     su=37;41:sg=30;43:tw=30;42:ow=34;42:st=37;44";
+
+// Separation between columns in the multi-column layout.
+const COLUMN_GAP: usize = 2;
+
+#[derive(Default, PartialEq)]
+pub enum ColorMode {
+    #[default]
+    Never,
+    Always,
+    Auto,
+}
+
+#[derive(Default, PartialEq)]
+pub enum SortOrder {
+    #[default]
+    Name,
+    Time,
+    Size,
+}
+
+#[derive(Default)]
+pub struct ListOptions {
+    pub all: bool,
+    pub almost_all: bool,
+    pub human_readable: bool,
+    pub recursive: bool,
+    pub sort: SortOrder,
+    pub reverse: bool,
+    pub one_per_line: bool,
+    pub columns: bool,
+    pub classify: bool,
+    pub color: ColorMode,
+    pub directory: bool,
+    pub long: bool,
+    // `full-iso`, `long-iso`, `iso`, `locale` or `+FORMAT` (strftime).
+    pub time_style: String,
+    // Resolved by the caller once it knows where the output goes: the
+    // terminal width for the column layout (`None` for one name per line)
+    // and the colors to use, if any.
+    pub width: Option<usize>,
+    pub colors: Option<LsColors>,
+}
+
+impl ListOptions {
+    /// Splits `ls` arguments into options and operands. Options may be
+    /// combined (`-lah`) and appear anywhere before `--`.
+    pub fn parse(arguments: &[String]) -> Result<(ListOptions, Vec<String>), &'static str> {
+        let mut options = ListOptions {
+            time_style: "locale".to_string(),
+            ..Default::default()
+        };
+        let mut operands = Vec::new();
+        let mut end_of_options = false;
+        for argument in arguments {
+            if end_of_options || argument == "-" || !argument.starts_with('-') {
+                operands.push(argument.clone());
+                continue;
+            }
+            if argument == "--" {
+                end_of_options = true;
+                continue;
+            }
+            if let Some(name) = argument.strip_prefix("--") {
+                options.set_long_option(name)?;
+                continue;
+            }
+            for flag in argument[1..].chars() {
+                options.set_flag(flag)?;
+            }
+        }
+        Ok((options, operands))
+    }
+
+    fn set_flag(&mut self, flag: char) -> Result<(), &'static str> {
+        match flag {
+            'a' => self.all = true,
+            'A' => self.almost_all = true,
+            'h' => self.human_readable = true,
+            'R' => self.recursive = true,
+            't' => self.sort = SortOrder::Time,
+            'S' => self.sort = SortOrder::Size,
+            'r' => self.reverse = true,
+            '1' => {
+                self.one_per_line = true;
+                self.columns = false;
+            }
+            'C' => {
+                self.columns = true;
+                self.one_per_line = false;
+            }
+            'F' => self.classify = true,
+            'd' => self.directory = true,
+            'l' => self.long = true,
+            _ => return Err("ls: invalid option. Supported options: -aAhRtSr1CFdl"),
+        }
+        Ok(())
+    }
+
+    fn set_long_option(&mut self, name: &str) -> Result<(), &'static str> {
+        if let Some(style) = name.strip_prefix("time-style=") {
+            if !matches!(style, "full-iso" | "long-iso" | "iso" | "locale")
+                && !style.starts_with('+')
+            {
+                return Err(
+                    "ls: invalid time style. Use full-iso, long-iso, iso, locale or +FORMAT",
+                );
+            }
+            self.time_style = style.to_string();
+            return Ok(());
+        }
+        match name {
+            "color" | "color=always" => self.color = ColorMode::Always,
+            "color=auto" => self.color = ColorMode::Auto,
+            "color=never" => self.color = ColorMode::Never,
+            "classify" => self.classify = true,
+            "all" => self.all = true,
+            "almost-all" => self.almost_all = true,
+            "human-readable" => self.human_readable = true,
+            "recursive" => self.recursive = true,
+            "reverse" => self.reverse = true,
+            "directory" => self.directory = true,
+            _ => return Err("ls: unrecognized option"),
+        }
+        Ok(())
+    }
+
+    /// Whether an entry found while reading a directory should be listed.
+    pub fn shows(&self, name: &str) -> bool {
+        !name.starts_with('.') || self.all || self.almost_all
+    }
+}
+
+pub struct Entry {
+    pub name: String,
+    pub path: PathBuf,
+    pub metadata: Metadata,
+}
+
+/// Sorts by name, or newest/largest first with `-t`/`-S` (ties broken by
+/// name), reversed with `-r`.
+pub fn sort_entries(entries: &mut [Entry], options: &ListOptions) {
+    entries.sort_by(|a, b| {
+        let by_name = a.name.cmp(&b.name);
+        match options.sort {
+            SortOrder::Name => by_name,
+            SortOrder::Time => b
+                .metadata
+                .modified()
+                .ok()
+                .cmp(&a.metadata.modified().ok())
+                .then(by_name),
+            SortOrder::Size => b.metadata.len().cmp(&a.metadata.len()).then(by_name),
+        }
+    });
+    if options.reverse {
+        entries.reverse();
+    }
+}
+
+/// Formats a size the way `ls -h` does: plain bytes below 1K, one decimal
+/// below 10 units and whole units above, always rounding up.
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if size < 1024 {
+        return size.to_string();
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    let rounded = (value * 10.0).ceil() / 10.0;
+    if rounded < 10.0 {
+        return format!("{:.1}{}", rounded, UNITS[unit]);
+    }
+    let rounded = value.ceil();
+    if rounded >= 1024.0 && unit < UNITS.len() - 1 {
+        return format!("1.0{}", UNITS[unit + 1]);
+    }
+    format!("{}{}", rounded as u64, UNITS[unit])
+}
+
+/// Builds the `drwxr-xr-x` style string: the file type followed by the
+/// permission bits, with setuid/setgid/sticky shown as `s`/`t` (upper case
+/// when the underlying execute bit is not set).
+pub fn mode_string(metadata: &Metadata) -> String {
+    let file_type = metadata.file_type();
+    let mut result = String::with_capacity(10);
+    result.push(if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else {
+        '-'
+    });
+    let mode = metadata.permissions().mode();
+    let special = [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')];
+    for (i, (special_bit, special_char)) in special.iter().enumerate() {
+        let shift = 6 - 3 * i;
+        result.push(if mode & (0o4 << shift) != 0 { 'r' } else { '-' });
+        result.push(if mode & (0o2 << shift) != 0 { 'w' } else { '-' });
+        let execute = mode & (0o1 << shift) != 0;
+        result.push(match (mode & special_bit != 0, execute) {
+            (true, true) => *special_char,
+            (true, false) => special_char.to_ascii_uppercase(),
+            (false, true) => 'x',
+            (false, false) => '-',
+        });
+    }
+    result
+}
+
+/// The size column of a long listing; devices show their major and minor
+/// numbers instead.
+pub fn size_column(metadata: &Metadata, human_readable: bool) -> String {
+    use std::os::unix::fs::MetadataExt;
+    let file_type = metadata.file_type();
+    if file_type.is_char_device() || file_type.is_block_device() {
+        let device = metadata.rdev();
+        let (major, minor) = unsafe { (libc::major(device), libc::minor(device)) };
+        return format!("{}, {}", major, minor);
+    }
+    if human_readable {
+        human_size(metadata.len())
+    } else {
+        metadata.len().to_string()
+    }
+}
+
+pub struct LsColors {
+    // Two-letter type keys (`di`, `ln`, `ex`, ...) to SGR sequences.
+    types: HashMap<String, String>,
+    // `*.ext` style suffixes, later entries taking precedence.
+    suffixes: Vec<(String, String)>,
+}
+
+impl LsColors {
+    /// Parses an LS_COLORS value on top of the default colors.
+    pub fn parse(spec: Option<&str>) -> LsColors {
+        let mut colors = LsColors {
+            types: HashMap::new(),
+            suffixes: Vec::new(),
+        };
+        for spec in [Some(DEFAULT_COLORS), spec].into_iter().flatten() {
+            for assignment in spec.split(':') {
+                let Some((key, code)) = assignment.split_once('=') else {
+                    continue;
+                };
+                match key.strip_prefix('*') {
+                    Some(suffix) => colors.suffixes.push((suffix.to_string(), code.to_string())),
+                    None => {
+                        colors.types.insert(key.to_string(), code.to_string());
+                    }
+                }
+            }
+        }
+        colors
+    }
+
+    fn code_for(&self, entry: &Entry) -> Option<&String> {
+        let file_type = entry.metadata.file_type();
+        let mode = entry.metadata.permissions().mode();
+        let key = if file_type.is_dir() {
+            match (mode & 0o1000 != 0, mode & 0o002 != 0) {
+                (true, true) => "tw",
+                (false, true) => "ow",
+                (true, false) => "st",
+                (false, false) => "di",
+            }
+        } else if file_type.is_symlink() {
+            // A link whose target is missing is an orphan.
+            if entry.path.exists() {
+                "ln"
+            } else {
+                "or"
+            }
+        } else if file_type.is_fifo() {
+            "pi"
+        } else if file_type.is_socket() {
+            "so"
+        } else if file_type.is_block_device() {
+            "bd"
+        } else if file_type.is_char_device() {
+            "cd"
+        } else if mode & 0o4000 != 0 {
+            "su"
+        } else if mode & 0o2000 != 0 {
+            "sg"
+        } else if mode & 0o111 != 0 {
+            "ex"
+        } else {
+            return self
+                .suffixes
+                .iter()
+                .rev()
+                .find(|(suffix, _)| entry.name.ends_with(suffix.as_str()))
+                .map(|(_, code)| code)
+                .or_else(|| self.types.get("fi"));
+        };
+        self.types.get(key)
+    }
+
+    fn paint(&self, entry: &Entry) -> String {
+        match self.code_for(entry) {
+            Some(code) if !code.is_empty() => format!("\x1b[{}m{}\x1b[0m", code, entry.name),
+            _ => entry.name.clone(),
+        }
+    }
+}
+
+/// The `-F` suffix for an entry: `/` directories, `@` symlinks, `|` FIFOs,
+/// `=` sockets and `*` executables.
+pub fn indicator(metadata: &Metadata) -> Option<char> {
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        Some('/')
+    } else if file_type.is_symlink() {
+        Some('@')
+    } else if file_type.is_fifo() {
+        Some('|')
+    } else if file_type.is_socket() {
+        Some('=')
+    } else if file_type.is_file() && metadata.permissions().mode() & 0o111 != 0 {
+        Some('*')
+    } else {
+        None
+    }
+}
+
+/// The name as displayed, colored and classified according to the options,
+/// together with its width on screen (escape sequences take no space).
+pub fn display_name(entry: &Entry, options: &ListOptions, long: bool) -> (String, usize) {
+    let mut name = match &options.colors {
+        Some(colors) => colors.paint(entry),
+        None => entry.name.clone(),
+    };
+    let mut width = entry.name.chars().count();
+    // In long listings a symlink is followed by its target instead of `@`.
+    let skip_indicator = long && entry.metadata.file_type().is_symlink();
+    if let (true, false, Some(indicator)) =
+        (options.classify, skip_indicator, indicator(&entry.metadata))
+    {
+        name.push(indicator);
+        width += 1;
+    }
+    (name, width)
+}
+
+/// Arranges names in columns filled top to bottom, using as many columns as
+/// fit in `width`.
+pub fn layout_columns(cells: &[(String, usize)], width: usize) -> String {
+    if cells.is_empty() {
+        return String::new();
+    }
+    let mut layout = vec![cells.iter().map(|(_, w)| *w).max().unwrap_or(0)];
+    let mut rows = cells.len();
+    for columns in (2..=cells.len()).rev() {
+        let candidate_rows = cells.len().div_ceil(columns);
+        let column_widths: Vec<usize> = cells
+            .chunks(candidate_rows)
+            .map(|column| column.iter().map(|(_, w)| *w).max().unwrap_or(0))
+            .collect();
+        let total: usize =
+            column_widths.iter().sum::<usize>() + COLUMN_GAP * (column_widths.len() - 1);
+        if total <= width {
+            layout = column_widths;
+            rows = candidate_rows;
+            break;
+        }
+    }
+    let mut lines = Vec::with_capacity(rows);
+    for row in 0..rows {
+        let mut line = String::new();
+        for (column, column_width) in layout.iter().enumerate() {
+            let Some((name, name_width)) = cells.get(column * rows + row) else {
+                break;
+            };
+            line.push_str(name);
+            let is_last =
+                column == layout.len() - 1 || cells.get((column + 1) * rows + row).is_none();
+            if !is_last {
+                line.push_str(&" ".repeat(column_width - name_width + COLUMN_GAP));
+            }
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Formats a modification time in the local time zone according to
+/// `--time-style`.
+pub fn format_time(time: SystemTime, style: &str, now: SystemTime) -> String {
+    let datetime: DateTime<Local> = time.into();
+    let recent = time <= now && now.duration_since(time).unwrap_or_default() < RECENT;
+    match style {
+        "full-iso" => datetime.format("%Y-%m-%d %H:%M:%S%.9f %z").to_string(),
+        "long-iso" => datetime.format("%Y-%m-%d %H:%M").to_string(),
+        "iso" if recent => datetime.format("%m-%d %H:%M").to_string(),
+        "iso" => datetime.format("%Y-%m-%d ").to_string(),
+        custom if custom.starts_with('+') => {
+            // An invalid format makes chrono's `to_string` panic, so show the
+            // format itself instead.
+            let mut formatted = String::new();
+            match write!(formatted, "{}", datetime.format(&custom[1..])) {
+                Ok(_) => formatted,
+                Err(_) => custom[1..].to_string(),
+            }
+        }
+        _ if recent => datetime.format("%b %e %H:%M").to_string(),
+        _ => datetime.format("%b %e  %Y").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_human_size() {
+        let test_cases = [
+            (0, "0"),
+            (1023, "1023"),
+            (1024, "1.0K"),
+            (1536, "1.5K"),
+            (10 * 1024 + 1, "11K"),
+            (5 * 1024 * 1024, "5.0M"),
+            (1024 * 1024 - 1, "1.0M"),
+        ];
+        for (input, expected) in test_cases.iter() {
+            assert_eq!(human_size(*input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let arguments: Vec<String> = ["-lah", "dir", "-tr", "--", "-file"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        match ListOptions::parse(&arguments) {
+            Ok((options, operands)) => {
+                assert!(options.long && options.all && options.human_readable);
+                assert!(options.sort == SortOrder::Time && options.reverse);
+                assert_eq!(operands, vec!["dir", "-file"]);
+            }
+            Err(err) => panic!("[test_parse_options] expected Ok, got {}", err),
+        }
+        assert!(ListOptions::parse(&["-z".to_string()]).is_err());
+        assert!(ListOptions::parse(&["--time-style=bogus".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_layout_columns() {
+        let cells: Vec<(String, usize)> = ["a", "bb", "ccc", "dddd", "e"]
+            .iter()
+            .map(|name| (name.to_string(), name.len()))
+            .collect();
+        let test_cases = [
+            (80, "a  bb  ccc  dddd  e"),
+            (14, "a   ccc   e\nbb  dddd"),
+            (3, "a\nbb\nccc\ndddd\ne"),
+        ];
+        for (width, expected) in test_cases.iter() {
+            assert_eq!(layout_columns(&cells, *width), *expected, "{}", width);
+        }
+    }
+
+    #[test]
+    fn test_ls_colors() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("archive.tar"), "").unwrap();
+        std::fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        let colors = LsColors::parse(Some("di=01;33:*.tar=01;31"));
+
+        let test_cases = [
+            ("archive.tar", "\x1b[01;31marchive.tar\x1b[0m"),
+            ("dir", "\x1b[01;33mdir\x1b[0m"),
+        ];
+        for (name, expected) in test_cases.iter() {
+            let path = temp_dir.path().join(name);
+            let entry = Entry {
+                name: name.to_string(),
+                metadata: std::fs::symlink_metadata(&path).unwrap(),
+                path,
+            };
+            assert_eq!(colors.paint(&entry), *expected);
+        }
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_mode_string() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("file");
+        std::fs::File::create(&file_path).unwrap();
+
+        let test_cases = [
+            (0o644, "-rw-r--r--"),
+            (0o4755, "-rwsr-xr-x"),
+            (0o2640, "-rw-r-S---"),
+            (0o1777, "-rwxrwxrwt"),
+            (0o1666, "-rw-rw-rwT"),
+        ];
+        for (mode, expected) in test_cases.iter() {
+            std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(*mode)).unwrap();
+            let metadata = std::fs::symlink_metadata(&file_path).unwrap();
+            assert_eq!(mode_string(&metadata), *expected);
+        }
+        let metadata = std::fs::symlink_metadata(temp_dir.path()).unwrap();
+        assert!(mode_string(&metadata).starts_with('d'));
+        temp_dir.close().unwrap();
+    }
+}
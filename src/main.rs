@@ -1,4 +1,25 @@
+mod arithmetic;
+mod brace;
+mod cat;
+mod condition;
 mod emulator;
+mod files;
+mod find;
+mod follow;
+mod glob;
+mod grep;
+mod head;
+mod ls;
+mod mkdir;
+mod parser;
+mod printf;
+mod rm;
+mod sort;
+mod tail;
+mod touch;
+mod trash;
+mod users;
+mod wc;
 use emulator::Emulator;
 
 fn main() {
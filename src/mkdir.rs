@@ -0,0 +1,91 @@
+// Option parsing for `mkdir`.
+
+#[derive(Default)]
+pub struct MakeDirectoryOptions {
+    pub parents: bool,
+    pub verbose: bool,
+    // Permission bits given with `-m`; otherwise the umask decides.
+    pub mode: Option<u32>,
+}
+
+impl MakeDirectoryOptions {
+    /// Splits `mkdir` arguments into options and operands. The mode is the
+    /// argument after `-m`, or attached to it (`-m755`, `--mode=755`).
+    pub fn parse(
+        arguments: &[String],
+    ) -> Result<(MakeDirectoryOptions, Vec<String>), &'static str> {
+        let mut options = MakeDirectoryOptions::default();
+        let mut operands = Vec::new();
+        let mut end_of_options = false;
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            if end_of_options || argument == "-" || !argument.starts_with('-') {
+                operands.push(argument.clone());
+                continue;
+            }
+            if argument == "--" {
+                end_of_options = true;
+                continue;
+            }
+            let flags: Vec<char> = match argument.as_str() {
+                "--parents" => vec!['p'],
+                "--verbose" => vec!['v'],
+                _ if argument.starts_with("--mode=") => {
+                    options.mode = Some(parse_mode(&argument["--mode=".len()..])?);
+                    continue;
+                }
+                _ if argument.starts_with("--") => return Err("mkdir: unrecognized option"),
+                _ => argument[1..].chars().collect(),
+            };
+            for (index, flag) in flags.iter().enumerate() {
+                match flag {
+                    'p' => options.parents = true,
+                    'v' => options.verbose = true,
+                    'm' => {
+                        let attached: String = flags[index + 1..].iter().collect();
+                        let mode = match attached.is_empty() {
+                            true => arguments
+                                .next()
+                                .ok_or("mkdir: option requires an argument -- 'm'")?,
+                            false => &attached,
+                        };
+                        options.mode = Some(parse_mode(mode)?);
+                        break;
+                    }
+                    _ => return Err("mkdir: invalid option. Supported options: -pvm"),
+                }
+            }
+        }
+        Ok((options, operands))
+    }
+}
+
+// Only octal modes are supported, e.g. `755` or `0700`.
+fn parse_mode(mode: &str) -> Result<u32, &'static str> {
+    match u32::from_str_radix(mode, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err("mkdir: invalid mode"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        let arguments: Vec<String> = ["-pv", "-m", "0750", "a", "--", "-b"]
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        let (options, operands) = MakeDirectoryOptions::parse(&arguments).unwrap();
+        assert!(options.parents && options.verbose);
+        assert_eq!(options.mode, Some(0o750));
+        assert_eq!(operands, vec!["a", "-b"]);
+
+        let (options, _) = MakeDirectoryOptions::parse(&["-pm700".to_string()]).unwrap();
+        assert_eq!(options.mode, Some(0o700));
+        assert!(MakeDirectoryOptions::parse(&["--mode=u+x".to_string()]).is_err());
+        assert!(MakeDirectoryOptions::parse(&["-m".to_string()]).is_err());
+    }
+}
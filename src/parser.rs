@@ -0,0 +1,186 @@
+// Quote-aware scanning of command lines.
+//
+// Words keep their quotes and `$(...)` / `${...}` groups intact; removing the
+// quotes is left to the expansion step so that it still knows which characters
+// were quoted.
+
+struct ScannedChar {
+    index: usize,
+    value: char,
+    // `true` when the character is outside quotes, not escaped and not inside a
+    // `$(...)` or `${...}` group, i.e. when it can act as an operator.
+    plain: bool,
+}
+
+fn scan(line: &str) -> Result<Vec<ScannedChar>, &'static str> {
+    let mut scanned = Vec::new();
+    let mut in_single_quotes = false;
+    let mut in_double_quotes = false;
+    let mut escaped = false;
+    let mut depth = 0;
+    let mut after_dollar = false;
+    for (index, value) in line.char_indices() {
+        let mut plain = false;
+        if escaped {
+            escaped = false;
+        } else if in_single_quotes {
+            in_single_quotes = value != '\'';
+        } else if value == '\\' {
+            escaped = true;
+        } else if in_double_quotes {
+            in_double_quotes = value != '"';
+        } else if depth > 0 {
+            match value {
+                '(' | '{' => depth += 1,
+                ')' | '}' => depth -= 1,
+                _ => {}
+            }
+        } else if after_dollar && (value == '(' || value == '{') {
+            depth = 1;
+        } else if value == '\'' {
+            in_single_quotes = true;
+        } else if value == '"' {
+            in_double_quotes = true;
+        } else {
+            plain = true;
+        }
+        after_dollar = plain && value == '$';
+        scanned.push(ScannedChar {
+            index,
+            value,
+            plain,
+        });
+    }
+    if in_single_quotes || in_double_quotes {
+        return Err("unexpected EOF while looking for matching quote");
+    }
+    if depth > 0 {
+        return Err("unexpected EOF while looking for matching `)`");
+    }
+    Ok(scanned)
+}
+
+/// Splits a command line into words on unquoted whitespace. Quotes and
+/// escapes are kept in the returned words.
+pub fn split_words(line: &str) -> Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for c in scan(line)? {
+        if c.plain && c.value.is_whitespace() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c.value);
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+/// Returns the byte index of the first unquoted occurrence of `target`.
+pub fn find_unquoted(line: &str, target: char) -> Option<usize> {
+    scan(line)
+        .ok()?
+        .into_iter()
+        .find(|c| c.plain && c.value == target)
+        .map(|c| c.index)
+}
+
+/// Like `find_unquoted`, for the `<` and `>` of a redirection. In a leading
+/// `[[ ... ]]` they compare strings, so only what follows `]]` is searched.
+pub fn find_redirection(line: &str, target: char) -> Option<usize> {
+    let scanned = scan(line).ok()?;
+    let separator = |index: usize| {
+        scanned
+            .get(index)
+            .is_none_or(|c| c.plain && c.value.is_whitespace())
+    };
+    let bracket = |index: usize| scanned[index].plain && scanned[index].value == ']';
+    let mut start = 0;
+    if line.starts_with("[[") && separator(2) {
+        start = (3..scanned.len().saturating_sub(1))
+            .find(|&i| separator(i - 1) && bracket(i) && bracket(i + 1) && separator(i + 2))
+            .map_or(scanned.len(), |i| i + 2);
+    }
+    scanned[start..]
+        .iter()
+        .find(|c| c.plain && c.value == target)
+        .map(|c| c.index)
+}
+
+/// Removes the quotes from a quoted command name such as `'echo'`, so that
+/// it names the same command. Names that would still need quoting, or that
+/// aren't quoted at all, give `None`.
+pub fn unquote_command_name(line: &str) -> Option<String> {
+    let line = line.trim_start();
+    let end = scan(line)
+        .ok()?
+        .into_iter()
+        .find(|c| c.plain && c.value.is_whitespace())
+        .map_or(line.len(), |c| c.index);
+    let name = &line[..end];
+    if !name.contains(['\'', '"']) || name.contains('\\') {
+        return None;
+    }
+    let unquoted: String = name.chars().filter(|c| !matches!(c, '\'' | '"')).collect();
+    let simple = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/');
+    if unquoted.is_empty() || !unquoted.chars().all(simple) {
+        return None;
+    }
+    Some(format!("{}{}", unquoted, &line[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        let test_cases = [
+            ("echo hello  world", vec!["echo", "hello", "world"]),
+            ("echo 'a b' \"c d\"", vec!["echo", "'a b'", "\"c d\""]),
+            ("echo a\\ b", vec!["echo", "a\\ b"]),
+            ("echo $((1 + 2))", vec!["echo", "$((1 + 2))"]),
+            ("echo ${x} y", vec!["echo", "${x}", "y"]),
+        ];
+
+        for (input, expected) in test_cases.iter() {
+            match split_words(input) {
+                Ok(value) => assert_eq!(value, *expected),
+                Err(_) => panic!("[test_split_words] expected Ok, got error"),
+            }
+        }
+        assert!(split_words("echo 'unterminated").is_err());
+    }
+
+    #[test]
+    fn test_find_unquoted() {
+        assert_eq!(find_unquoted("echo a > b", '>'), Some(7));
+        assert_eq!(find_unquoted("echo '>' \\> \">\"", '>'), None);
+        assert_eq!(find_unquoted("echo $((1 > 2))", '>'), None);
+    }
+
+    #[test]
+    fn test_find_redirection() {
+        assert_eq!(find_redirection("echo a > b", '>'), Some(7));
+        assert_eq!(find_redirection("[[ a > b ]]", '>'), None);
+        assert_eq!(find_redirection("[[ a < b ]] > out", '>'), Some(12));
+        assert_eq!(find_redirection("[[ a < b ]] > out", '<'), None);
+        assert_eq!(find_redirection("[ a > b ]", '>'), Some(4));
+    }
+
+    #[test]
+    fn test_unquote_command_name() {
+        assert_eq!(
+            unquote_command_name("'echo' 'a b'"),
+            Some("echo 'a b'".to_string())
+        );
+        assert_eq!(unquote_command_name("e\"ch\"o"), Some("echo".to_string()));
+        assert_eq!(unquote_command_name("echo 'a'"), None);
+        assert_eq!(unquote_command_name("'echo x'"), None);
+        assert_eq!(unquote_command_name("'a;b'"), None);
+    }
+}
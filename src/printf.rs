@@ -0,0 +1,558 @@
+// Formatting for `printf` and the backslash escapes it shares with `echo -e`.
+
+/// Expands the escapes of `echo -e` and `%b` in `text`. The second value is
+/// `true` when `\c` asked for all further output to be dropped.
+pub fn unescape(text: &str) -> (Vec<u8>, bool) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            push_char(&mut output, chars[i]);
+            i += 1;
+            continue;
+        }
+        match escape(&chars[i + 1..], true) {
+            Escape::Bytes(bytes, consumed) => {
+                output.extend(bytes);
+                i += consumed + 1;
+            }
+            Escape::Stop => return (output, true),
+        }
+    }
+    (output, false)
+}
+
+enum Escape {
+    // The bytes of the escape and how many characters after the backslash
+    // it used.
+    Bytes(Vec<u8>, usize),
+    Stop,
+}
+
+// The escape after a backslash. In `echo` style octal escapes start with
+// `\0` and `\c` stops the output; in a `printf` format they are `\NNN`.
+fn escape(chars: &[char], echo_style: bool) -> Escape {
+    let Some(&c) = chars.first() else {
+        return Escape::Bytes(b"\\".to_vec(), 0);
+    };
+    let simple = match c {
+        'a' => Some(0x07),
+        'b' => Some(0x08),
+        'e' | 'E' => Some(0x1b),
+        'f' => Some(0x0c),
+        'n' => Some(b'\n'),
+        'r' => Some(b'\r'),
+        't' => Some(b'\t'),
+        'v' => Some(0x0b),
+        '\\' => Some(b'\\'),
+        '"' if !echo_style => Some(b'"'),
+        '\'' if !echo_style => Some(b'\''),
+        _ => None,
+    };
+    if let Some(byte) = simple {
+        return Escape::Bytes(vec![byte], 1);
+    }
+    let digits = |start: usize, radix: u32, limit: usize| -> (u32, usize) {
+        let length = chars[start..]
+            .iter()
+            .take(limit)
+            .take_while(|x| x.is_digit(radix))
+            .count();
+        let digits: String = chars[start..start + length].iter().collect();
+        (u32::from_str_radix(&digits, radix).unwrap_or(0), length)
+    };
+    match c {
+        'c' if echo_style => Escape::Stop,
+        '0' if echo_style => {
+            let (value, length) = digits(1, 8, 3);
+            Escape::Bytes(vec![value as u8], length + 1)
+        }
+        '0'..='7' if !echo_style => {
+            let (value, length) = digits(0, 8, 3);
+            Escape::Bytes(vec![value as u8], length)
+        }
+        'x' | 'u' | 'U' => {
+            let limit = match c {
+                'x' => 2,
+                'u' => 4,
+                _ => 8,
+            };
+            let (value, length) = digits(1, 16, limit);
+            if length == 0 {
+                return Escape::Bytes(format!("\\{}", c).into_bytes(), 1);
+            }
+            let bytes = match c {
+                'x' => vec![value as u8],
+                _ => {
+                    let mut bytes = Vec::new();
+                    push_char(&mut bytes, char::from_u32(value).unwrap_or('\u{fffd}'));
+                    bytes
+                }
+            };
+            Escape::Bytes(bytes, length + 1)
+        }
+        c => Escape::Bytes(format!("\\{}", c).into_bytes(), 1),
+    }
+}
+
+fn push_char(output: &mut Vec<u8>, c: char) {
+    let mut buffer = [0; 4];
+    output.extend(c.encode_utf8(&mut buffer).as_bytes());
+}
+
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    // Pads `body` to the width. Zeros go after the sign and the `0x` prefix.
+    fn pad(&self, body: String, numeric: bool) -> Vec<u8> {
+        let length = body.chars().count();
+        if length >= self.width {
+            return body.into_bytes();
+        }
+        let fill = self.width - length;
+        if self.left {
+            return format!("{}{}", body, " ".repeat(fill)).into_bytes();
+        }
+        if self.zero && numeric {
+            let prefix_length = match body.as_bytes() {
+                [b'-' | b'+' | b' ', b'0', b'x' | b'X', ..] => 3,
+                [b'0', b'x' | b'X', ..] => 2,
+                [b'-' | b'+' | b' ', ..] => 1,
+                _ => 0,
+            };
+            let (prefix, rest) = body.split_at(prefix_length);
+            return format!("{}{}{}", prefix, "0".repeat(fill), rest).into_bytes();
+        }
+        format!("{}{}", " ".repeat(fill), body).into_bytes()
+    }
+
+    // Pads raw bytes with spaces, for `%b` output that need not be UTF-8.
+    // The width counts bytes.
+    fn pad_bytes(&self, body: Vec<u8>) -> Vec<u8> {
+        let fill = vec![b' '; self.width.saturating_sub(body.len())];
+        match self.left {
+            true => [body, fill].concat(),
+            false => [fill, body].concat(),
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+}
+
+/// Formats `arguments` with `format`, reusing the format while arguments
+/// remain. Problems with an argument are reported in `errors`; the output
+/// goes on with the argument taken as 0.
+pub fn format(format: &str, arguments: &[String], errors: &mut Vec<String>) -> Vec<u8> {
+    let chars: Vec<char> = format.chars().collect();
+    let mut arguments = arguments.iter();
+    let mut output = Vec::new();
+    loop {
+        let remaining = arguments.len();
+        match format_once(&chars, &mut arguments, &mut output, errors) {
+            Ok(true) if arguments.len() > 0 && arguments.len() < remaining => {}
+            _ => return output,
+        }
+    }
+}
+
+// One pass over the format. Returns `Ok(false)` when output has to stop
+// (`\c` in a `%b` argument) and `Err` for an invalid directive.
+fn format_once(
+    chars: &[char],
+    arguments: &mut std::slice::Iter<String>,
+    output: &mut Vec<u8>,
+    errors: &mut Vec<String>,
+) -> Result<bool, ()> {
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => match escape(&chars[i + 1..], false) {
+                Escape::Bytes(bytes, consumed) => {
+                    output.extend(bytes);
+                    i += consumed + 1;
+                }
+                Escape::Stop => return Ok(false),
+            },
+            '%' if chars.get(i + 1) == Some(&'%') => {
+                output.push(b'%');
+                i += 2;
+            }
+            '%' => {
+                let start = i;
+                i += 1;
+                let mut spec = Spec {
+                    left: false,
+                    plus: false,
+                    space: false,
+                    alternate: false,
+                    zero: false,
+                    width: 0,
+                    precision: None,
+                };
+                while let Some(&flag) = chars.get(i) {
+                    match flag {
+                        '-' => spec.left = true,
+                        '+' => spec.plus = true,
+                        ' ' => spec.space = true,
+                        '#' => spec.alternate = true,
+                        '0' => spec.zero = true,
+                        _ => break,
+                    }
+                    i += 1;
+                }
+                let mut number = |i: &mut usize, errors: &mut Vec<String>| -> i64 {
+                    if chars.get(*i) == Some(&'*') {
+                        *i += 1;
+                        return integer(arguments.next(), errors);
+                    }
+                    let length = chars[*i..]
+                        .iter()
+                        .take_while(|x| x.is_ascii_digit())
+                        .count();
+                    let digits: String = chars[*i..*i + length].iter().collect();
+                    *i += length;
+                    digits.parse().unwrap_or(0)
+                };
+                let width = number(&mut i, errors);
+                // A negative width from `*` means left alignment.
+                spec.left |= width < 0;
+                spec.width = width.unsigned_abs() as usize;
+                if chars.get(i) == Some(&'.') {
+                    i += 1;
+                    spec.precision = Some(number(&mut i, errors).max(0) as usize);
+                }
+                let Some(&conversion) = chars.get(i) else {
+                    let directive: String = chars[start..].iter().collect();
+                    errors.push(format!("printf: {}: missing format character", directive));
+                    return Err(());
+                };
+                i += 1;
+                if !"diuxXofFeEgGcsbq".contains(conversion) {
+                    let directive: String = chars[start..i].iter().collect();
+                    errors.push(format!("printf: {}: invalid directive", directive));
+                    return Err(());
+                }
+                let argument = arguments.next();
+                match conversion {
+                    'd' | 'i' => {
+                        let value = integer(argument, errors);
+                        let digits = precise(value.unsigned_abs().to_string(), &spec);
+                        let body = format!("{}{}", spec.sign(value < 0), digits);
+                        output.extend(spec.pad(body, spec.precision.is_none()));
+                    }
+                    'u' | 'x' | 'X' | 'o' => {
+                        // Negative numbers wrap around, as in C.
+                        let value = integer(argument, errors) as u64;
+                        let digits = match conversion {
+                            'u' => value.to_string(),
+                            'x' => format!("{:x}", value),
+                            'X' => format!("{:X}", value),
+                            _ => format!("{:o}", value),
+                        };
+                        let mut digits = precise(digits, &spec);
+                        if spec.alternate && value != 0 {
+                            match conversion {
+                                'x' => digits.insert_str(0, "0x"),
+                                'X' => digits.insert_str(0, "0X"),
+                                'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+                                _ => {}
+                            }
+                        }
+                        output.extend(spec.pad(digits, spec.precision.is_none()));
+                    }
+                    'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                        let value = float(argument, errors);
+                        let mut body = match value.is_finite() {
+                            true => format_float(value.abs(), conversion, &spec),
+                            false if value.is_nan() => "nan".to_string(),
+                            false => "inf".to_string(),
+                        };
+                        if conversion.is_ascii_uppercase() {
+                            body = body.to_uppercase();
+                        }
+                        let negative = value.is_sign_negative() && !value.is_nan();
+                        let body = format!("{}{}", spec.sign(negative), body);
+                        output.extend(spec.pad(body, value.is_finite()));
+                    }
+                    'c' => {
+                        let body = argument
+                            .and_then(|x| x.chars().next())
+                            .map_or(String::new(), |x| x.to_string());
+                        output.extend(spec.pad(body, false));
+                    }
+                    's' | 'q' => {
+                        let argument = argument.map_or("", |x| x.as_str());
+                        let mut body = match conversion {
+                            's' => argument.to_string(),
+                            _ => quote(argument),
+                        };
+                        if let Some(precision) = spec.precision {
+                            body = body.chars().take(precision).collect();
+                        }
+                        output.extend(spec.pad(body, false));
+                    }
+                    _ => {
+                        let (mut bytes, stop) = unescape(argument.map_or("", |x| x.as_str()));
+                        if let Some(precision) = spec.precision {
+                            bytes.truncate(precision);
+                        }
+                        output.extend(spec.pad_bytes(bytes));
+                        if stop {
+                            return Ok(false);
+                        }
+                    }
+                }
+            }
+            c => {
+                push_char(output, c);
+                i += 1;
+            }
+        }
+    }
+    Ok(true)
+}
+
+// Adds leading zeros up to the precision, the minimum number of digits.
+fn precise(digits: String, spec: &Spec) -> String {
+    match spec.precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) if digits.len() < precision => {
+            format!("{}{}", "0".repeat(precision - digits.len()), digits)
+        }
+        _ => digits,
+    }
+}
+
+// A non-negative finite number in `%f`, `%e` or `%g` notation.
+fn format_float(value: f64, conversion: char, spec: &Spec) -> String {
+    let precision = spec.precision.unwrap_or(6);
+    match conversion.to_ascii_lowercase() {
+        'f' => {
+            let body = format!("{:.*}", precision, value);
+            match spec.alternate && precision == 0 {
+                true => format!("{}.", body),
+                false => body,
+            }
+        }
+        'e' => exponential(value, precision),
+        _ => {
+            // `%g` uses the shorter of `%e` and `%f` for the significant
+            // digits, without trailing zeros unless `#` is given.
+            let precision = precision.max(1);
+            let exponent: i32 = exponential(value, precision - 1)
+                .split_once('e')
+                .and_then(|(_, exponent)| exponent.parse().ok())
+                .unwrap_or(0);
+            let body = match exponent < -4 || exponent >= precision as i32 {
+                true => exponential(value, precision - 1),
+                false => format!("{:.*}", (precision as i32 - 1 - exponent) as usize, value),
+            };
+            if spec.alternate {
+                return body;
+            }
+            let (mantissa, exponent) = match body.split_once('e') {
+                Some((mantissa, exponent)) => (mantissa.to_string(), format!("e{}", exponent)),
+                None => (body, String::new()),
+            };
+            let mantissa = match mantissa.contains('.') {
+                true => mantissa.trim_end_matches('0').trim_end_matches('.'),
+                false => &mantissa,
+            };
+            format!("{}{}", mantissa, exponent)
+        }
+    }
+}
+
+// `%e`: the exponent has a sign and at least two digits, as in C.
+fn exponential(value: f64, precision: usize) -> String {
+    let body = format!("{:.*e}", precision, value);
+    match body.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let exponent: i32 = exponent.parse().unwrap_or(0);
+            let sign = if exponent < 0 { '-' } else { '+' };
+            format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+        }
+        None => body,
+    }
+}
+
+// A numeric argument: decimal, `0x` hex, `0` octal, or `'c` for the code of
+// the character `c`. A missing argument is 0.
+fn integer(argument: Option<&String>, errors: &mut Vec<String>) -> i64 {
+    let Some(argument) = argument else {
+        return 0;
+    };
+    if let Some(rest) = argument
+        .strip_prefix('\'')
+        .or_else(|| argument.strip_prefix('"'))
+    {
+        return rest.chars().next().map_or(0, |x| x as i64);
+    }
+    let text = argument.trim_start();
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    match value {
+        Ok(value) if negative => -value,
+        Ok(value) => value,
+        Err(_) => {
+            errors.push(format!("printf: {}: invalid number", argument));
+            0
+        }
+    }
+}
+
+fn float(argument: Option<&String>, errors: &mut Vec<String>) -> f64 {
+    let Some(argument) = argument else {
+        return 0.0;
+    };
+    if argument.starts_with(['\'', '"']) {
+        return integer(Some(argument), errors) as f64;
+    }
+    match argument.trim().parse() {
+        Ok(value) => value,
+        Err(_) => {
+            errors.push(format!("printf: {}: invalid number", argument));
+            0.0
+        }
+    }
+}
+
+// `%q`: the argument quoted so the shell would read it back unchanged.
+fn quote(argument: &str) -> String {
+    if argument.is_empty() {
+        return "''".to_string();
+    }
+    if argument.chars().any(|c| c.is_control()) {
+        let mut quoted = "$'".to_string();
+        for c in argument.chars() {
+            match c {
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                '\\' | '\'' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                c if c.is_control() => quoted.push_str(&format!("\\{:03o}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        return quoted;
+    }
+    let mut quoted = String::new();
+    for c in argument.chars() {
+        if !(c.is_alphanumeric() || "_./,:@%+=-".contains(c)) {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let test_cases: [(&str, &[&str], &str); 16] = [
+            ("%s-%s\\n", &["a", "b", "c"], "a-b\nc-\n"),
+            ("[%5s|%-5s|%.2s]", &["ab", "cd", "xyz"], "[   ab|cd   |xy]"),
+            ("%d %i %+d % d", &["42", "-7", "3", "3"], "42 -7 +3  3"),
+            (
+                "%05d|%-4d|%.3d|%04.1d",
+                &["-42", "7", "5", "3"],
+                "-0042|7   |005|   3",
+            ),
+            (
+                "%x %X %#x %o %#o",
+                &["255", "255", "255", "8", "8"],
+                "ff FF 0xff 10 010",
+            ),
+            ("%d %d %d", &["0x1f", "017", "'A"], "31 15 65"),
+            ("%u", &["-1"], "18446744073709551615"),
+            (
+                "%f %.2f %8.3f",
+                &["1.5", "2.345", "-3.14159"],
+                "1.500000 2.35   -3.142",
+            ),
+            (
+                "%e %.2E",
+                &["12345.678", "0.000123"],
+                "1.234568e+04 1.23E-04",
+            ),
+            (
+                "%g %g %g %G",
+                &["100000", "1000000", "0.0001", "1e-5"],
+                "100000 1e+06 0.0001 1E-05",
+            ),
+            ("%c%c", &["hello", "\u{e9}t\u{e9}"], "h\u{e9}"),
+            ("%b|%s", &["a\\tb\\0101", "a\\tb"], "a\tbA|a\\tb"),
+            ("%q %q %q", &["a b", "it's", ""], "a\\ b it\\'s ''"),
+            ("%*d|%-*s|", &["4", "7", "3", "x"], "   7|x  |"),
+            ("100%% \\x41\\101\\u00e9", &[], "100% AA\u{e9}"),
+            ("%b stop\\n", &["x\\cy", "z"], "x"),
+        ];
+        for (format_string, arguments, expected) in test_cases.iter() {
+            let arguments: Vec<String> = arguments.iter().map(|x| x.to_string()).collect();
+            let mut errors = Vec::new();
+            let output = format(format_string, &arguments, &mut errors);
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                *expected,
+                "{}",
+                format_string
+            );
+            assert!(errors.is_empty(), "{}", format_string);
+        }
+
+        let mut errors = Vec::new();
+        let output = format("%d|%z", &["x".to_string()], &mut errors);
+        assert_eq!(output, b"0|");
+        assert_eq!(
+            errors,
+            ["printf: x: invalid number", "printf: %z: invalid directive"]
+        );
+
+        // `%b` keeps bytes that aren't UTF-8, and pads and cuts them as bytes.
+        let arguments = ["\\xff\\0376".to_string(), "\\xffab".to_string()];
+        let output = format("%b|%3.2b|%-3b", &arguments, &mut errors);
+        assert_eq!(output, b"\xff\xfe| \xffa|   ");
+
+        assert_eq!(unescape("a\\nb\\c d"), (b"a\nb".to_vec(), true));
+        assert_eq!(unescape("\\x41\\0101\\q\\"), (b"AA\\q\\".to_vec(), false));
+    }
+}
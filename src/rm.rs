@@ -0,0 +1,111 @@
+// Option parsing for `rm`.
+
+use std::fs::Metadata;
+use std::os::unix::fs::FileTypeExt;
+
+pub struct RemoveOptions {
+    pub recursive: bool,
+    pub force: bool,
+    pub interactive: bool,
+    pub verbose: bool,
+    // Refuse to remove `/` or the home directory recursively.
+    pub preserve_root: bool,
+}
+
+impl RemoveOptions {
+    /// Splits `rm` arguments into options and operands. Of `-f` and `-i`, the
+    /// last one given wins.
+    pub fn parse(arguments: &[String]) -> Result<(RemoveOptions, Vec<String>), &'static str> {
+        let mut options = RemoveOptions {
+            recursive: false,
+            force: false,
+            interactive: false,
+            verbose: false,
+            preserve_root: true,
+        };
+        let mut operands = Vec::new();
+        let mut end_of_options = false;
+        for argument in arguments {
+            if end_of_options || argument == "-" || !argument.starts_with('-') {
+                operands.push(argument.clone());
+                continue;
+            }
+            if argument == "--" {
+                end_of_options = true;
+                continue;
+            }
+            let flags: Vec<char> = match argument.as_str() {
+                "--recursive" => vec!['r'],
+                "--force" => vec!['f'],
+                "--interactive" => vec!['i'],
+                "--verbose" => vec!['v'],
+                "--preserve-root" => {
+                    options.preserve_root = true;
+                    continue;
+                }
+                "--no-preserve-root" => {
+                    options.preserve_root = false;
+                    continue;
+                }
+                _ if argument.starts_with("--") => return Err("rm: unrecognized option"),
+                _ => argument[1..].chars().collect(),
+            };
+            for flag in flags {
+                match flag {
+                    'r' | 'R' => options.recursive = true,
+                    'f' => {
+                        options.force = true;
+                        options.interactive = false;
+                    }
+                    'i' => {
+                        options.interactive = true;
+                        options.force = false;
+                    }
+                    'v' => options.verbose = true,
+                    _ => return Err("rm: invalid option. Supported options: -rRfiv"),
+                }
+            }
+        }
+        Ok((options, operands))
+    }
+}
+
+/// Describes a file the way `rm -i` asks about it.
+pub fn file_kind(metadata: &Metadata) -> &'static str {
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_symlink() {
+        "symbolic link"
+    } else if file_type.is_fifo() {
+        "fifo"
+    } else if file_type.is_socket() {
+        "socket"
+    } else if file_type.is_char_device() {
+        "character special file"
+    } else if file_type.is_block_device() {
+        "block special file"
+    } else if metadata.len() == 0 {
+        "regular empty file"
+    } else {
+        "regular file"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        let arguments: Vec<String> = ["-rf", "a", "-i", "--no-preserve-root", "--", "-b"]
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        let (options, operands) = RemoveOptions::parse(&arguments).unwrap();
+        assert!(options.recursive && options.interactive && !options.force);
+        assert!(!options.preserve_root);
+        assert_eq!(operands, vec!["a", "-b"]);
+        assert!(RemoveOptions::parse(&["-x".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,459 @@
+// Option parsing and line comparison for `sort`.
+
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+struct KeyOrdering {
+    numeric: bool,
+    human: bool,
+    version: bool,
+    ignore_case: bool,
+    ignore_blanks: bool,
+    reverse: bool,
+}
+
+impl KeyOrdering {
+    fn set(&mut self, flag: char) -> bool {
+        match flag {
+            'n' => self.numeric = true,
+            'h' => self.human = true,
+            'V' => self.version = true,
+            'f' => self.ignore_case = true,
+            'b' => self.ignore_blanks = true,
+            'r' => self.reverse = true,
+            _ => return false,
+        }
+        true
+    }
+}
+
+// A `-k START[,END]` key. Fields and characters are counted from 1; an end
+// character of 0 means the end of the field.
+struct Key {
+    start_field: usize,
+    start_char: usize,
+    end_field: Option<usize>,
+    end_char: usize,
+    // `None` when the key has no options of its own and uses the global ones.
+    ordering: Option<KeyOrdering>,
+}
+
+#[derive(Default)]
+pub struct SortOptions {
+    ordering: KeyOrdering,
+    keys: Vec<Key>,
+    separator: Option<char>,
+    pub unique: bool,
+    pub stable: bool,
+    pub output: Option<String>,
+}
+
+impl SortOptions {
+    /// Splits `sort` arguments into options and file operands.
+    pub fn parse(arguments: &[String]) -> Result<(SortOptions, Vec<String>), &'static str> {
+        let mut options = SortOptions::default();
+        let mut operands = Vec::new();
+        let mut end_of_options = false;
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            if end_of_options || argument == "-" || !argument.starts_with('-') {
+                operands.push(argument.clone());
+                continue;
+            }
+            if argument == "--" {
+                end_of_options = true;
+                continue;
+            }
+            if let Some(name) = argument.strip_prefix("--") {
+                options.set_long_option(name)?;
+                continue;
+            }
+            let flags = &argument[1..];
+            for (index, flag) in flags.char_indices() {
+                if options.ordering.set(flag) {
+                    continue;
+                }
+                match flag {
+                    'u' => options.unique = true,
+                    's' => options.stable = true,
+                    'k' | 't' | 'o' => {
+                        // These take the rest of the argument or the next one.
+                        let value = match &flags[index + 1..] {
+                            "" => arguments
+                                .next()
+                                .ok_or("sort: option requires an argument")?,
+                            rest => rest,
+                        };
+                        options.set_value(flag, value)?;
+                        break;
+                    }
+                    _ => return Err("sort: invalid option. Supported options: -nrufkthVbso"),
+                }
+            }
+        }
+        Ok((options, operands))
+    }
+
+    fn set_long_option(&mut self, name: &str) -> Result<(), &'static str> {
+        if let Some((option, value)) = name.split_once('=') {
+            return match option {
+                "key" => self.set_value('k', value),
+                "field-separator" => self.set_value('t', value),
+                "output" => self.set_value('o', value),
+                _ => Err("sort: unrecognized option"),
+            };
+        }
+        let flag = match name {
+            "numeric-sort" => 'n',
+            "human-numeric-sort" => 'h',
+            "version-sort" => 'V',
+            "ignore-case" => 'f',
+            "ignore-leading-blanks" => 'b',
+            "reverse" => 'r',
+            "unique" => {
+                self.unique = true;
+                return Ok(());
+            }
+            "stable" => {
+                self.stable = true;
+                return Ok(());
+            }
+            _ => return Err("sort: unrecognized option"),
+        };
+        self.ordering.set(flag);
+        Ok(())
+    }
+
+    fn set_value(&mut self, flag: char, value: &str) -> Result<(), &'static str> {
+        match flag {
+            'k' => self.keys.push(parse_key(value)?),
+            't' => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(separator), None) => self.separator = Some(separator),
+                    _ => return Err("sort: the separator must be a single character"),
+                }
+            }
+            _ => self.output = Some(value.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Sorts the lines in place.
+    pub fn sort(&self, lines: &mut [&str]) {
+        // `sort_by` is stable, so without `-s` ties are broken by comparing
+        // the whole lines.
+        lines.sort_by(|a, b| {
+            self.compare_keys(a, b).then_with(|| {
+                if self.stable || self.unique {
+                    Ordering::Equal
+                } else if self.ordering.reverse {
+                    b.cmp(a)
+                } else {
+                    a.cmp(b)
+                }
+            })
+        });
+    }
+
+    /// Whether two lines sort as equal, which makes them duplicates for `-u`.
+    pub fn equal(&self, a: &str, b: &str) -> bool {
+        self.compare_keys(a, b) == Ordering::Equal
+    }
+
+    fn compare_keys(&self, a: &str, b: &str) -> Ordering {
+        if self.keys.is_empty() {
+            return compare(a, b, &self.ordering);
+        }
+        for key in self.keys.iter() {
+            let ordering = key.ordering.as_ref().unwrap_or(&self.ordering);
+            let result = compare(self.extract(a, key), self.extract(b, key), ordering);
+            if result != Ordering::Equal {
+                return result;
+            }
+        }
+        Ordering::Equal
+    }
+
+    // Returns the part of the line covered by the key.
+    fn extract<'a>(&self, line: &'a str, key: &Key) -> &'a str {
+        let fields = self.field_offsets(line);
+        let position = |field: usize, char: usize, end: bool| -> usize {
+            let Some(&(start, field_end)) = fields.get(field - 1) else {
+                return line.len();
+            };
+            if end && char == 0 {
+                return field_end;
+            }
+            let offset = if end { char } else { char - 1 };
+            line[start..field_end]
+                .char_indices()
+                .nth(offset)
+                .map_or(field_end, |(index, _)| start + index)
+        };
+        let start = position(key.start_field, key.start_char, false);
+        let end = match key.end_field {
+            Some(field) => position(field, key.end_char, true),
+            None => line.len(),
+        };
+        if start >= end {
+            ""
+        } else {
+            &line[start..end]
+        }
+    }
+
+    // Byte ranges of the fields. Without `-t`, a field is a run of blanks
+    // followed by a run of non-blanks, so leading blanks belong to it.
+    fn field_offsets(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut fields = Vec::new();
+        match self.separator {
+            Some(separator) => {
+                let mut start = 0;
+                for (index, c) in line.char_indices() {
+                    if c == separator {
+                        fields.push((start, index));
+                        start = index + c.len_utf8();
+                    }
+                }
+                fields.push((start, line.len()));
+            }
+            None => {
+                let mut start = 0;
+                let mut in_word = false;
+                for (index, c) in line.char_indices() {
+                    let blank = c == ' ' || c == '\t';
+                    if blank && in_word {
+                        fields.push((start, index));
+                        start = index;
+                    }
+                    in_word = !blank;
+                }
+                fields.push((start, line.len()));
+            }
+        }
+        fields
+    }
+}
+
+// Parses `F[.C][OPTS][,F[.C][OPTS]]`.
+fn parse_key(spec: &str) -> Result<Key, &'static str> {
+    let mut ordering = KeyOrdering::default();
+    let mut has_options = false;
+    let mut parse_position = |position: &str| -> Result<(usize, usize), &'static str> {
+        let digits_end = position
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(position.len());
+        for flag in position[digits_end..].chars() {
+            if !ordering.set(flag) {
+                return Err("sort: invalid key option");
+            }
+            has_options = true;
+        }
+        let (field, char) = match position[..digits_end].split_once('.') {
+            Some((field, char)) => (field, char),
+            None => (&position[..digits_end], ""),
+        };
+        let field = field.parse::<usize>().map_err(|_| "sort: invalid key")?;
+        let char = if char.is_empty() {
+            0
+        } else {
+            char.parse::<usize>().map_err(|_| "sort: invalid key")?
+        };
+        Ok((field, char))
+    };
+    let (start, end) = match spec.split_once(',') {
+        Some((start, end)) => (start, Some(end)),
+        None => (spec, None),
+    };
+    let (start_field, start_char) = parse_position(start)?;
+    if start_field == 0 {
+        return Err("sort: fields are numbered from 1");
+    }
+    let (end_field, end_char) = match end {
+        Some(end) => {
+            let (field, char) = parse_position(end)?;
+            if field == 0 {
+                return Err("sort: fields are numbered from 1");
+            }
+            (Some(field), char)
+        }
+        None => (None, 0),
+    };
+    Ok(Key {
+        start_field,
+        start_char: start_char.max(1),
+        end_field,
+        end_char,
+        ordering: has_options.then_some(ordering),
+    })
+}
+
+fn compare(a: &str, b: &str, ordering: &KeyOrdering) -> Ordering {
+    let (a, b) = if ordering.ignore_blanks {
+        (
+            a.trim_start_matches([' ', '\t']),
+            b.trim_start_matches([' ', '\t']),
+        )
+    } else {
+        (a, b)
+    };
+    let result = if ordering.numeric {
+        numeric_value(a).total_cmp(&numeric_value(b))
+    } else if ordering.human {
+        human_value(a).total_cmp(&human_value(b))
+    } else if ordering.version {
+        compare_versions(a, b)
+    } else if ordering.ignore_case {
+        a.chars()
+            .map(|c| c.to_ascii_uppercase())
+            .cmp(b.chars().map(|c| c.to_ascii_uppercase()))
+    } else {
+        a.cmp(b)
+    };
+    if ordering.reverse {
+        result.reverse()
+    } else {
+        result
+    }
+}
+
+// The leading number of the text, after blanks; text without one is 0.
+fn numeric_value(text: &str) -> f64 {
+    numeric_prefix(text).0
+}
+
+fn numeric_prefix(text: &str) -> (f64, &str) {
+    let text = text.trim_start_matches([' ', '\t']);
+    let mut end = 0;
+    let mut seen_digit = false;
+    let mut seen_point = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            '-' | '+' if index == 0 => {}
+            '0'..='9' => seen_digit = true,
+            '.' if !seen_point => seen_point = true,
+            _ => break,
+        }
+        end = index + 1;
+    }
+    if !seen_digit {
+        return (0.0, text);
+    }
+    (text[..end].parse().unwrap_or(0.0), &text[end..])
+}
+
+// Numbers with an SI suffix (`2K`, `1.5G`) as printed by `du -h` or `ls -h`.
+fn human_value(text: &str) -> f64 {
+    let (value, rest) = numeric_prefix(text);
+    let exponent = match rest.chars().next() {
+        Some('K' | 'k') => 1,
+        Some('M') => 2,
+        Some('G') => 3,
+        Some('T') => 4,
+        Some('P') => 5,
+        Some('E') => 6,
+        _ => 0,
+    };
+    value * 1024f64.powi(exponent)
+}
+
+// Compares runs of digits by value and everything else character by
+// character, so that `1.10` sorts after `1.9`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Less,
+            (false, true) => return Ordering::Greater,
+            _ => {}
+        }
+        let (a_chunk, a_rest) = split_chunk(a);
+        let (b_chunk, b_rest) = split_chunk(b);
+        let a_number = a_chunk.starts_with(|c: char| c.is_ascii_digit());
+        let b_number = b_chunk.starts_with(|c: char| c.is_ascii_digit());
+        let result = if a_number && b_number {
+            let (a_digits, b_digits) = (
+                a_chunk.trim_start_matches('0'),
+                b_chunk.trim_start_matches('0'),
+            );
+            a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits))
+        } else {
+            a_chunk.cmp(b_chunk)
+        };
+        if result != Ordering::Equal {
+            return result;
+        }
+        (a, b) = (a_rest, b_rest);
+    }
+}
+
+// Splits off the leading run of digits or of non-digits.
+fn split_chunk(text: &str) -> (&str, &str) {
+    let digits = text.starts_with(|c: char| c.is_ascii_digit());
+    let end = text
+        .find(|c: char| c.is_ascii_digit() != digits)
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(arguments: &[&str], input: &[&str]) -> Vec<String> {
+        let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+        let (options, _) = SortOptions::parse(&arguments).unwrap();
+        let mut lines = input.to_vec();
+        options.sort(&mut lines);
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sort() {
+        let test_cases: [(&[&str], &[&str], &[&str]); 9] = [
+            (&[], &["b", "B", "a"], &["B", "a", "b"]),
+            (&["-f"], &["b", "C", "a"], &["a", "b", "C"]),
+            (&["-n"], &["10", "9", "-1", "x"], &["-1", "x", "9", "10"]),
+            (&["-rn"], &["10", "9", "100"], &["100", "10", "9"]),
+            (
+                &["-h"],
+                &["1G", "10K", "2M", "3"],
+                &["3", "10K", "2M", "1G"],
+            ),
+            (
+                &["-V"],
+                &["v1.10", "v1.9", "v1.2"],
+                &["v1.2", "v1.9", "v1.10"],
+            ),
+            (&["-k2n"], &["a 10", "b 9", "c 1"], &["c 1", "b 9", "a 10"]),
+            (
+                &["-t:", "-k", "3,3"],
+                &["x:1:b", "y:2:a", "z:3"],
+                &["z:3", "y:2:a", "x:1:b"],
+            ),
+            (
+                &["-s", "-k1,1"],
+                &["b 2", "a 3", "b 1", "a 1"],
+                &["a 3", "a 1", "b 2", "b 1"],
+            ),
+        ];
+        for (arguments, input, expected) in test_cases.iter() {
+            assert_eq!(sorted(arguments, input), *expected, "{:?}", arguments);
+        }
+    }
+
+    #[test]
+    fn test_extract() {
+        let arguments: Vec<String> = vec!["-k2.2,2".to_string()];
+        let (options, _) = SortOptions::parse(&arguments).unwrap();
+        assert_eq!(options.extract("a  xyz b", &options.keys[0]), " xyz");
+        let arguments: Vec<String> = vec!["-k2.3,3.1".to_string(), "-t,".to_string()];
+        let (options, _) = SortOptions::parse(&arguments).unwrap();
+        assert_eq!(options.extract("a,bcd,ef", &options.keys[0]), "d,e");
+    }
+}
@@ -0,0 +1,254 @@
+// Option parsing and output for `tail`. Regular files are read backwards
+// from the end, so only the part that is printed is ever read.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::time::Duration;
+
+use crate::head::{parse_arguments, Count};
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Follow {
+    // `-f`: keep reading the file that was opened.
+    Descriptor,
+    // `-F`: keep reading whatever file has the name, reopening it when it is
+    // rotated or recreated.
+    Name,
+}
+
+pub struct TailOptions {
+    pub count: Count,
+    // `+N`: start at line or byte N instead of counting from the end.
+    pub from_start: bool,
+    pub headers: Option<bool>,
+    pub follow: Option<Follow>,
+    // How long `-f` waits between looks at the files (`-s`).
+    pub sleep_interval: Duration,
+}
+
+impl TailOptions {
+    /// Splits `tail` arguments into options and operands. Counts may start
+    /// with `+` to give an offset from the start.
+    pub fn parse(arguments: &[String]) -> Result<(TailOptions, Vec<String>), &'static str> {
+        let mut options = TailOptions {
+            count: Count::Lines(10),
+            from_start: false,
+            headers: None,
+            follow: None,
+            sleep_interval: Duration::from_secs(1),
+        };
+        let operands = parse_arguments(arguments, "tail", |flag, value| {
+            match flag {
+                'q' => options.headers = Some(false),
+                'v' => options.headers = Some(true),
+                'f' => options.follow = Some(Follow::Descriptor),
+                'F' => options.follow = Some(Follow::Name),
+                's' => {
+                    let seconds: f64 = value
+                        .and_then(|value| value.parse().ok())
+                        .ok_or("tail: invalid number of seconds")?;
+                    // Also rejects negative, infinite and too large values.
+                    options.sleep_interval = Duration::try_from_secs_f64(seconds)
+                        .map_err(|_| "tail: invalid number of seconds")?;
+                }
+                'n' | 'c' => {
+                    let value = value.unwrap_or_default();
+                    let (from_start, value) = match value.strip_prefix('+') {
+                        Some(value) => (true, value),
+                        None => (false, value.strip_prefix('-').unwrap_or(value)),
+                    };
+                    let count = value
+                        .parse()
+                        .map_err(|_| "tail: invalid number of lines or bytes")?;
+                    options.from_start = from_start;
+                    options.count = match flag {
+                        'n' => Count::Lines(count),
+                        _ => Count::Bytes(count),
+                    };
+                }
+                _ => return Err("tail: invalid option. Supported options: -n -c -q -v -f -F -s"),
+            }
+            Ok(())
+        })?;
+        Ok((options, operands))
+    }
+}
+
+/// Prints the end of a regular file, seeking instead of reading it all.
+pub fn tail_file(file: &mut File, options: &TailOptions, output: &mut dyn Write) -> io::Result<()> {
+    let length = file.seek(SeekFrom::End(0))?;
+    let start = match (options.from_start, options.count) {
+        (true, _) => {
+            file.seek(SeekFrom::Start(0))?;
+            return tail_stream(&mut io::BufReader::new(file), options, output);
+        }
+        (false, Count::Bytes(count)) => length.saturating_sub(count),
+        (false, Count::Lines(count)) => start_of_last_lines(file, length, count)?,
+    };
+    file.seek(SeekFrom::Start(start))?;
+    io::copy(file, output)?;
+    Ok(())
+}
+
+// Walks back from the end in chunks until `count` line starts have been
+// passed. A newline at the very end doesn't begin another line.
+fn start_of_last_lines(file: &mut File, length: u64, count: u64) -> io::Result<u64> {
+    if count == 0 {
+        return Ok(length);
+    }
+    let mut newlines = 0;
+    let mut end = length;
+    let mut buffer = vec![0; CHUNK_SIZE as usize];
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK_SIZE);
+        let chunk = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        for (index, &byte) in chunk.iter().enumerate().rev() {
+            let offset = start + index as u64;
+            if byte == b'\n' && offset + 1 != length {
+                newlines += 1;
+                if newlines == count {
+                    return Ok(offset + 1);
+                }
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Prints the end of input that can only be read forwards, keeping just the
+/// last `count` lines or bytes in memory.
+pub fn tail_stream(
+    reader: &mut dyn BufRead,
+    options: &TailOptions,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    if options.from_start {
+        // `+1` is the whole input.
+        let mut skip = match options.count {
+            Count::Lines(count) | Count::Bytes(count) => count.saturating_sub(1),
+        };
+        while skip > 0 {
+            let chunk = reader.fill_buf()?;
+            if chunk.is_empty() {
+                return Ok(());
+            }
+            let end = match options.count {
+                Count::Lines(_) => match chunk.iter().position(|&x| x == b'\n') {
+                    Some(index) => {
+                        skip -= 1;
+                        index + 1
+                    }
+                    None => chunk.len(),
+                },
+                Count::Bytes(_) => {
+                    let end = chunk.len().min(skip as usize);
+                    skip -= end as u64;
+                    end
+                }
+            };
+            reader.consume(end);
+        }
+        io::copy(reader, output)?;
+        return Ok(());
+    }
+    match options.count {
+        Count::Lines(count) => {
+            let mut lines = VecDeque::new();
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line)? > 0 {
+                lines.push_back(std::mem::take(&mut line));
+                if lines.len() as u64 > count {
+                    // Reuse the dropped line's buffer for the next one.
+                    line = lines.pop_front().unwrap_or_default();
+                    line.clear();
+                }
+            }
+            for line in lines {
+                output.write_all(&line)?;
+            }
+        }
+        Count::Bytes(count) => {
+            let mut kept = VecDeque::new();
+            loop {
+                let chunk = reader.fill_buf()?;
+                if chunk.is_empty() {
+                    break;
+                }
+                kept.extend(chunk);
+                let length = chunk.len();
+                reader.consume(length);
+                let excess = kept.len().saturating_sub(count as usize);
+                kept.drain(..excess);
+            }
+            let (front, back) = kept.as_slices();
+            output.write_all(front)?;
+            output.write_all(back)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("input.txt");
+        // Long enough that the file is read back in several chunks.
+        let input: Vec<u8> = (1..=30000)
+            .flat_map(|x| format!("{}\n", x).into_bytes())
+            .collect();
+        std::fs::write(&path, &input).unwrap();
+
+        let test_cases: [(&[&str], &[u8]); 6] = [
+            (&["-n", "2"], b"29999\n30000\n"),
+            (&["-1"], b"30000\n"),
+            (&["-c3"], b"00\n"),
+            (&["-n", "+29999"], b"29999\n30000\n"),
+            (&["-c", "+168892"], b"00\n"),
+            (&["-n0"], b""),
+        ];
+        for (arguments, expected) in test_cases.iter() {
+            let arguments: Vec<String> = arguments.iter().map(|x| x.to_string()).collect();
+            let (options, _) = TailOptions::parse(&arguments).unwrap();
+            let mut output = Vec::new();
+            tail_file(&mut File::open(&path).unwrap(), &options, &mut output).unwrap();
+            assert_eq!(output, *expected, "{:?}", arguments);
+            output.clear();
+            tail_stream(&mut &input[..], &options, &mut output).unwrap();
+            assert_eq!(output, *expected, "{:?}", arguments);
+        }
+
+        let (options, _) = TailOptions::parse(&["-n29999".to_string()]).unwrap();
+        let mut output = Vec::new();
+        tail_file(&mut File::open(&path).unwrap(), &options, &mut output).unwrap();
+        assert_eq!(output, &input[2..]);
+
+        // Without a final newline the last line still counts.
+        let (options, _) = TailOptions::parse(&["-n2".to_string()]).unwrap();
+        std::fs::write(&path, "a\nb\nc").unwrap();
+        output.clear();
+        tail_file(&mut File::open(&path).unwrap(), &options, &mut output).unwrap();
+        assert_eq!(output, b"b\nc");
+        assert!(TailOptions::parse(&["-n".to_string(), "+x".to_string()]).is_err());
+
+        let arguments = ["-fs0.5".to_string(), "--follow=name".to_string()];
+        let (options, _) = TailOptions::parse(&arguments).unwrap();
+        assert_eq!(options.follow, Some(Follow::Name));
+        assert_eq!(options.sleep_interval, Duration::from_millis(500));
+        for seconds in ["1e20", "-1", "inf", "NaN"] {
+            let arguments = ["-s".to_string(), seconds.to_string()];
+            assert!(TailOptions::parse(&arguments).is_err(), "{}", seconds);
+        }
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+// Option parsing and timestamp formats for `touch`.
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+// Where the new timestamps come from when not the current time.
+pub enum TimeSource {
+    At(DateTime<Local>),
+    // Copy the times of this file (`-r`).
+    Reference(String),
+}
+
+#[derive(Default)]
+pub struct TouchOptions {
+    pub no_create: bool,
+    pub access_only: bool,
+    pub modify_only: bool,
+    pub time: Option<TimeSource>,
+}
+
+impl TouchOptions {
+    /// Splits `touch` arguments into options and operands. `-d`, `-t` and
+    /// `-r` take the next argument, or the rest of the same one.
+    pub fn parse(arguments: &[String]) -> Result<(TouchOptions, Vec<String>), &'static str> {
+        let mut options = TouchOptions::default();
+        let mut operands = Vec::new();
+        let mut end_of_options = false;
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            if end_of_options || argument == "-" || !argument.starts_with('-') {
+                operands.push(argument.clone());
+                continue;
+            }
+            if argument == "--" {
+                end_of_options = true;
+                continue;
+            }
+            let flags: Vec<char> = match argument.as_str() {
+                "--no-create" => vec!['c'],
+                _ if argument.starts_with("--date=") => {
+                    options.time = Some(TimeSource::At(parse_date(&argument[7..])?));
+                    continue;
+                }
+                _ if argument.starts_with("--reference=") => {
+                    options.time = Some(TimeSource::Reference(argument[12..].to_string()));
+                    continue;
+                }
+                _ if argument.starts_with("--") => return Err("touch: unrecognized option"),
+                _ => argument[1..].chars().collect(),
+            };
+            for (index, flag) in flags.iter().enumerate() {
+                match flag {
+                    'c' => options.no_create = true,
+                    'a' => options.access_only = true,
+                    'm' => options.modify_only = true,
+                    'd' | 't' | 'r' => {
+                        let attached: String = flags[index + 1..].iter().collect();
+                        let value = match attached.is_empty() {
+                            true => arguments
+                                .next()
+                                .ok_or("touch: option requires an argument")?
+                                .clone(),
+                            false => attached,
+                        };
+                        options.time = Some(match flag {
+                            'd' => TimeSource::At(parse_date(&value)?),
+                            't' => TimeSource::At(parse_stamp(&value)?),
+                            _ => TimeSource::Reference(value),
+                        });
+                        break;
+                    }
+                    _ => return Err("touch: invalid option. Supported options: -camdtr"),
+                }
+            }
+        }
+        Ok((options, operands))
+    }
+
+    pub fn sets_access(&self) -> bool {
+        self.access_only || !self.modify_only
+    }
+
+    pub fn sets_modification(&self) -> bool {
+        self.modify_only || !self.access_only
+    }
+}
+
+// `-d`: `now`, `@SECONDS`, RFC 3339, or a local `YYYY-MM-DD[ HH:MM[:SS]]`.
+fn parse_date(date: &str) -> Result<DateTime<Local>, &'static str> {
+    let date = date.trim();
+    if date == "now" {
+        return Ok(Local::now());
+    }
+    if let Some(seconds) = date.strip_prefix('@') {
+        let seconds = seconds.parse().map_err(|_| "touch: invalid date format")?;
+        return Local
+            .timestamp_opt(seconds, 0)
+            .single()
+            .ok_or("touch: invalid date format");
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(date) {
+        return Ok(time.with_timezone(&Local));
+    }
+    let formats = [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ];
+    let time = formats
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|day| day.and_hms_opt(0, 0, 0))
+        });
+    time.and_then(local_time)
+        .ok_or("touch: invalid date format")
+}
+
+// `-t`: `[[CC]YY]MMDDhhmm[.ss]`, in local time.
+fn parse_stamp(stamp: &str) -> Result<DateTime<Local>, &'static str> {
+    const INVALID: &str = "touch: invalid date format";
+    let (digits, seconds) = match stamp.split_once('.') {
+        Some((digits, seconds)) if seconds.len() == 2 => (digits, seconds),
+        Some(_) => return Err(INVALID),
+        None => (stamp, "00"),
+    };
+    if !digits
+        .chars()
+        .chain(seconds.chars())
+        .all(|x| x.is_ascii_digit())
+    {
+        return Err(INVALID);
+    }
+    let number = |text: &str| text.parse::<u32>().map_err(|_| INVALID);
+    let (year, rest) = match digits.len() {
+        8 => (Local::now().year(), digits),
+        // Two-digit years 69-99 are 19xx, like POSIX says.
+        10 => match number(&digits[..2])? {
+            year if year >= 69 => (1900 + year as i32, &digits[2..]),
+            year => (2000 + year as i32, &digits[2..]),
+        },
+        12 => (number(&digits[..4])? as i32, &digits[4..]),
+        _ => return Err(INVALID),
+    };
+    let (month, day) = (number(&rest[..2])?, number(&rest[2..4])?);
+    let (hour, minute, second) = (number(&rest[4..6])?, number(&rest[6..8])?, number(seconds)?);
+    NaiveDate::from_ymd_opt(year, month, day)
+        .and_then(|day| day.and_hms_opt(hour, minute, second))
+        .and_then(local_time)
+        .ok_or(INVALID)
+}
+
+// Times skipped by a DST change don't exist; repeated ones take the first.
+fn local_time(time: NaiveDateTime) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&time).earliest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_time(arguments: &[&str]) -> Result<String, &'static str> {
+        let arguments: Vec<String> = arguments.iter().map(|x| x.to_string()).collect();
+        match TouchOptions::parse(&arguments)?.0.time {
+            Some(TimeSource::At(time)) => Ok(time.format("%Y-%m-%d %H:%M:%S").to_string()),
+            Some(TimeSource::Reference(name)) => Ok(name),
+            None => Ok(String::new()),
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let test_cases = [
+            (vec!["-d", "2024-02-29 13:45"], "2024-02-29 13:45:00"),
+            (vec!["-d2024-02-29"], "2024-02-29 00:00:00"),
+            (vec!["--date=2024-02-29T13:45:10"], "2024-02-29 13:45:10"),
+            (vec!["-t", "202402291345.10"], "2024-02-29 13:45:10"),
+            (vec!["-t", "7001010000"], "1970-01-01 00:00:00"),
+            (vec!["-t", "3001010000"], "2030-01-01 00:00:00"),
+            (vec!["-cr", "ref.txt"], "ref.txt"),
+        ];
+        for (arguments, expected) in test_cases.iter() {
+            assert_eq!(parse_time(arguments).unwrap(), *expected, "{:?}", arguments);
+        }
+        for invalid in [
+            vec!["-d", "yesterday-ish"],
+            vec!["-t", "2024022913"],
+            vec!["-t", "202413011200"],
+            vec!["-x"],
+            vec!["-r"],
+        ] {
+            assert!(parse_time(&invalid).is_err(), "{:?}", invalid);
+        }
+
+        let arguments = ["-a".to_string(), "f".to_string()];
+        let (options, operands) = TouchOptions::parse(&arguments).unwrap();
+        assert!(options.sets_access() && !options.sets_modification());
+        assert_eq!(operands, vec!["f"]);
+    }
+}
@@ -0,0 +1,265 @@
+// A trash can following the XDG trash layout: removed files are moved to
+// `files/` and each gets a `info/<name>.trashinfo` recording where it came
+// from and when it was removed.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::files::move_path;
+
+pub struct Trash {
+    root: PathBuf,
+}
+
+pub struct TrashEntry {
+    /// Name of the item inside the trash, used to restore it.
+    pub name: String,
+    pub original_path: PathBuf,
+    pub deletion_date: String,
+}
+
+impl Trash {
+    /// The trash of the user: `$XDG_DATA_HOME/Trash`, or
+    /// `~/.local/share/Trash` when that isn't set.
+    pub fn new(home: &str, data_home: Option<&str>) -> Trash {
+        let root = match data_home.filter(|data_home| !data_home.is_empty()) {
+            Some(data_home) => PathBuf::from(data_home).join("Trash"),
+            None => PathBuf::from(home).join(".local/share/Trash"),
+        };
+        Trash { root }
+    }
+
+    fn files(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
+    fn info_path(&self, name: &str) -> PathBuf {
+        self.root.join("info").join(format!("{}.trashinfo", name))
+    }
+
+    /// Moves `path` (absolute) into the trash and returns its name there.
+    pub fn put(&self, path: &Path) -> io::Result<String> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?
+            .to_string_lossy()
+            .into_owned();
+        fs::create_dir_all(self.files())?;
+        fs::create_dir_all(self.root.join("info"))?;
+
+        // Creating the info file reserves the name, so two shells can't pick
+        // the same one.
+        let mut counter = 1;
+        let (name, mut info) = loop {
+            let name = match counter {
+                1 => file_name.clone(),
+                _ => format!("{}.{}", file_name, counter),
+            };
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(self.info_path(&name))
+            {
+                // A dangling symlink still takes up the name.
+                Ok(info) if fs::symlink_metadata(self.files().join(&name)).is_err() => {
+                    break (name, info)
+                }
+                Ok(_) => fs::remove_file(self.info_path(&name))?,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err),
+            }
+            counter += 1;
+        };
+        let written = write!(
+            info,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(path),
+            Local::now().format("%Y-%m-%dT%H:%M:%S")
+        );
+        let moved = written.and_then(|_| move_path(path, &self.files().join(&name)));
+        if let Err(err) = moved {
+            let _ignored = fs::remove_file(self.info_path(&name));
+            return Err(err);
+        }
+        Ok(name)
+    }
+
+    /// The items in the trash, oldest first.
+    pub fn list(&self) -> io::Result<Vec<TrashEntry>> {
+        let read_dir = match fs::read_dir(self.root.join("info")) {
+            Ok(read_dir) => read_dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut entries = Vec::new();
+        for entry in read_dir.filter_map(|entry| entry.ok()) {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(name) = file_name.strip_suffix(".trashinfo") else {
+                continue;
+            };
+            // Info files whose item is gone are leftovers of a failed move.
+            if fs::symlink_metadata(self.files().join(name)).is_err() {
+                continue;
+            }
+            if let Some(entry) = self.read_info(name) {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by(|a, b| (&a.deletion_date, &a.name).cmp(&(&b.deletion_date, &b.name)));
+        Ok(entries)
+    }
+
+    fn read_info(&self, name: &str) -> Option<TrashEntry> {
+        let contents = fs::read_to_string(self.info_path(name)).ok()?;
+        let mut lines = contents.lines();
+        if lines.next()?.trim() != "[Trash Info]" {
+            return None;
+        }
+        let mut original_path = None;
+        let mut deletion_date = String::new();
+        for line in lines {
+            if let Some(path) = line.strip_prefix("Path=") {
+                original_path = Some(decode_path(path));
+            } else if let Some(date) = line.strip_prefix("DeletionDate=") {
+                deletion_date = date.to_string();
+            }
+        }
+        Some(TrashEntry {
+            name: name.to_string(),
+            original_path: original_path?,
+            deletion_date,
+        })
+    }
+
+    /// Moves the item called `name` back to where it was removed from and
+    /// returns that path. An existing file there is never overwritten.
+    pub fn restore(&self, name: &str) -> Result<PathBuf, String> {
+        let entry = match self.read_info(name) {
+            Some(entry) if fs::symlink_metadata(self.files().join(name)).is_ok() => entry,
+            _ => return Err(format!("trash: '{}': not in trash", name)),
+        };
+        let destination = &entry.original_path;
+        if fs::symlink_metadata(destination).is_ok() {
+            return Err(format!(
+                "trash: cannot restore '{}': '{}' already exists",
+                name,
+                destination.display()
+            ));
+        }
+        let restored = match destination.parent() {
+            Some(parent) => fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|_| move_path(&self.files().join(name), destination));
+        if let Err(err) = restored {
+            return Err(format!("trash: cannot restore '{}': {}", name, err));
+        }
+        let _ignored = fs::remove_file(self.info_path(name));
+        Ok(entry.original_path)
+    }
+
+    /// Deletes everything in the trash for good.
+    pub fn empty(&self) -> io::Result<()> {
+        for directory in [self.files(), self.root.join("info")] {
+            let read_dir = match fs::read_dir(&directory) {
+                Ok(read_dir) => read_dir,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            for entry in read_dir {
+                let path = entry?.path();
+                match fs::symlink_metadata(&path)?.is_dir() {
+                    true => fs::remove_dir_all(&path)?,
+                    false => fs::remove_file(&path)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Percent-encodes a path the way `.trashinfo` files store it.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn decode_path(encoded: &str) -> PathBuf {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsString::from_vec(decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_path() {
+        let path = Path::new("/tmp/a b/100%é.txt");
+        assert_eq!(encode_path(path), "/tmp/a%20b/100%25%C3%A9.txt");
+        assert_eq!(decode_path(&encode_path(path)), path);
+    }
+
+    #[test]
+    fn test_put_and_restore() {
+        use tempfile::tempdir;
+        let temp_dir = tempdir().unwrap();
+        let trash = Trash::new("", Some(&temp_dir.path().join("data").to_string_lossy()));
+        let file = temp_dir.path().join("notes.txt");
+        std::fs::write(&file, "first").unwrap();
+        assert_eq!(trash.put(&file).unwrap(), "notes.txt");
+        std::fs::write(&file, "second").unwrap();
+        assert_eq!(trash.put(&file).unwrap(), "notes.txt.2");
+        assert!(!file.exists());
+
+        let info = std::fs::read_to_string(trash.info_path("notes.txt")).unwrap();
+        assert!(info.starts_with(&format!("[Trash Info]\nPath={}\n", file.display())));
+        let names: Vec<_> = trash.list().unwrap().into_iter().map(|x| x.name).collect();
+        assert_eq!(names, vec!["notes.txt", "notes.txt.2"]);
+
+        assert_eq!(trash.restore("notes.txt").unwrap(), file);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "first");
+        assert!(trash.restore("notes.txt.2").is_err());
+        assert!(trash.restore("missing").is_err());
+
+        // A dangling symlink already in the trash is not replaced.
+        let dangling = trash.files().join("link");
+        std::os::unix::fs::symlink("missing", &dangling).unwrap();
+        let file = temp_dir.path().join("link");
+        std::fs::write(&file, "link").unwrap();
+        assert_eq!(trash.put(&file).unwrap(), "link.2");
+        assert!(std::fs::symlink_metadata(&dangling).unwrap().is_symlink());
+
+        trash.empty().unwrap();
+        assert!(trash.list().unwrap().is_empty());
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+// Option parsing and counting for `wc`.
+
+use std::io::{self, BufRead};
+
+pub struct WcOptions {
+    pub lines: bool,
+    pub words: bool,
+    pub chars: bool,
+    pub bytes: bool,
+}
+
+impl WcOptions {
+    /// Splits `wc` arguments into options and operands. Without `-l`, `-w`,
+    /// `-m` or `-c`, lines, words and bytes are counted.
+    pub fn parse(arguments: &[String]) -> Result<(WcOptions, Vec<String>), &'static str> {
+        let mut options = WcOptions {
+            lines: false,
+            words: false,
+            chars: false,
+            bytes: false,
+        };
+        let mut operands = Vec::new();
+        let mut end_of_options = false;
+        for argument in arguments {
+            if end_of_options || argument == "-" || !argument.starts_with('-') {
+                operands.push(argument.clone());
+                continue;
+            }
+            if argument == "--" {
+                end_of_options = true;
+                continue;
+            }
+            let flags: Vec<char> = match argument.as_str() {
+                "--lines" => vec!['l'],
+                "--words" => vec!['w'],
+                "--chars" => vec!['m'],
+                "--bytes" => vec!['c'],
+                _ if argument.starts_with("--") => return Err("wc: unrecognized option"),
+                _ => argument[1..].chars().collect(),
+            };
+            for flag in flags {
+                match flag {
+                    'l' => options.lines = true,
+                    'w' => options.words = true,
+                    'm' => options.chars = true,
+                    'c' => options.bytes = true,
+                    _ => return Err("wc: invalid option. Supported options: -lwmc"),
+                }
+            }
+        }
+        if !(options.lines || options.words || options.chars || options.bytes) {
+            options.lines = true;
+            options.words = true;
+            options.bytes = true;
+        }
+        Ok((options, operands))
+    }
+
+    /// The selected counts, in the order `wc` prints them.
+    pub fn columns(&self, counts: &Counts) -> Vec<u64> {
+        [
+            (self.lines, counts.lines),
+            (self.words, counts.words),
+            (self.chars, counts.chars),
+            (self.bytes, counts.bytes),
+        ]
+        .iter()
+        .filter(|(selected, _)| *selected)
+        .map(|(_, count)| *count)
+        .collect()
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Counts {
+    pub lines: u64,
+    pub words: u64,
+    pub chars: u64,
+    pub bytes: u64,
+}
+
+impl Counts {
+    /// Counts `reader` a buffer at a time. Characters are UTF-8 sequences;
+    /// words are runs of non-whitespace.
+    pub fn count(reader: &mut dyn BufRead) -> io::Result<Counts> {
+        let mut counts = Counts::default();
+        let mut in_word = false;
+        loop {
+            let chunk = reader.fill_buf()?;
+            if chunk.is_empty() {
+                return Ok(counts);
+            }
+            for &byte in chunk {
+                if byte == b'\n' {
+                    counts.lines += 1;
+                }
+                // Continuation bytes belong to the character before them.
+                if byte & 0xc0 != 0x80 {
+                    counts.chars += 1;
+                }
+                let space = byte.is_ascii_whitespace() || byte == 0x0b;
+                if !space && !in_word {
+                    counts.words += 1;
+                }
+                in_word = !space;
+            }
+            counts.bytes += chunk.len() as u64;
+            let length = chunk.len();
+            reader.consume(length);
+        }
+    }
+
+    pub fn add(&mut self, other: &Counts) {
+        self.lines += other.lines;
+        self.words += other.words;
+        self.chars += other.chars;
+        self.bytes += other.bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let test_cases: [(&str, &[u8], &[u64]); 4] = [
+            ("", b"one two\n  three\n", &[2, 3, 16]),
+            ("-l", b"no newline", &[0]),
+            ("-mc", "h\u{e9}llo w\u{f6}rld\n".as_bytes(), &[12, 14]),
+            ("-wl", b"\ta\x0bb\r\nc", &[1, 3]),
+        ];
+        for (flags, input, expected) in test_cases.iter() {
+            let arguments: Vec<String> = match flags.is_empty() {
+                true => Vec::new(),
+                false => vec![flags.to_string()],
+            };
+            let (options, _) = WcOptions::parse(&arguments).unwrap();
+            let counts = Counts::count(&mut &input[..]).unwrap();
+            assert_eq!(options.columns(&counts), *expected, "{}", flags);
+        }
+        assert!(WcOptions::parse(&["-x".to_string()]).is_err());
+    }
+}